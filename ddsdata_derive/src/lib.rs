@@ -19,18 +19,28 @@ use syn::{parse_macro_input, Data, DataStruct, DeriveInput, Fields, LitStr};
 /// * `#[key]`: Marks a field as part of the DDS Key. Can be applied to multiple fields.
 /// * `#[dds_data(type_name = "CustomName")]`: (Optional) Overrides the default type name.
 ///   If omitted, the struct's exact Rust identifier is used.
+/// * `#[dds_data(key_deserialize)]`: (Optional) Generates `deserialize_key`.
+///   All fields not marked with `#[key]` must implement `Default`.
+///   Without this, the DataReader can't deliver the samples of disposed or unregistered instances.
 ///
 /// ## Key Generation Logic (`gen_key`)
 /// When calculating the `KeyHash`, the macro extracts fields marked with `#[key]`,
 /// serializes them in Big Endian format, and applies the following rules:
 /// 1. **Length <= 16 bytes:** Padded with trailing zeros (`0`) to exactly 16 bytes.
 /// 2. **Length > 16 bytes:** Computes an MD5 hash of the bytes and uses the 16-byte digest.
+///
+/// ## Key-only Serialization (`serialize_key`, `deserialize_key`)
+/// A DATA disposing or unregistering an instance carries only the fields marked with `#[key]`,
+/// serialized in CDR with the given endianness.
+/// `deserialize_key`, generated only with `#[dds_data(key_deserialize)]`,
+/// fills the other fields with `Default::default()`.
 #[proc_macro_derive(DdsData, attributes(key, dds_data))]
 pub fn derive_ddsdata(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
 
     let mut user_type_name = None;
+    let mut is_key_deserialize = false;
     for attr in &input.attrs {
         if attr.path().is_ident("dds_data") {
             let _ = attr.parse_nested_meta(|meta| {
@@ -40,20 +50,25 @@ pub fn derive_ddsdata(input: TokenStream) -> TokenStream {
                     let s: LitStr = expr.parse()?;
                     user_type_name = Some(s.value());
                 }
+                // #[dds_data(key_deserialize)]
+                if meta.path.is_ident("key_deserialize") {
+                    is_key_deserialize = true;
+                }
                 Ok(())
             });
         }
     }
 
     let mut keys = Vec::new();
+    let mut non_keys = Vec::new();
     if let Data::Struct(data_struct) = &input.data {
         if let Fields::Named(fields) = &data_struct.fields {
             for field in &fields.named {
-                for attr in &field.attrs {
-                    if attr.path().is_ident("key") {
-                        if let Some(ident) = &field.ident {
-                            keys.push((ident, &field.ty));
-                        }
+                if let Some(ident) = &field.ident {
+                    if field.attrs.iter().any(|attr| attr.path().is_ident("key")) {
+                        keys.push((ident, &field.ty));
+                    } else {
+                        non_keys.push(ident);
                     }
                 }
             }
@@ -70,43 +85,23 @@ pub fn derive_ddsdata(input: TokenStream) -> TokenStream {
 
     let is_with_key_val = keys_count != 0;
 
+    let wrapper_name = syn::Ident::new(&format!("{}KeyWrapper", name), name.span());
+
     let gen_key_body = if keys_count == 0 {
         quote! {
             None
         }
     } else {
         // create wrapper struct for gen_key().
-        let wrapper_name = syn::Ident::new(&format!("{}KeyWrapper", name), name.span());
-
-        // definition of wrapper struct field
-        let wrapper_fields = keys.iter().map(|(ident, ty)| {
-            quote! { #ident: #ty }
-        });
+        let wrapper_def = gen_key_wrapper(&wrapper_name, &keys, false);
 
         // initialization of wrapper struct field
         let wrapper_init = keys.iter().map(|(ident, _ty)| {
             quote! { #ident: self.#ident.clone() }
         });
 
-        let write_stmts = keys.iter().map(|(ident, ty)| {
-            let write_stmt = gen_write_stmt(ty, quote!(self.#ident));
-            quote! { #write_stmt }
-        });
-
         quote! {
-            #[derive(Clone)]
-            struct #wrapper_name {
-                #(#wrapper_fields),*
-            }
-
-            impl<C: speedy::Context> speedy::Writable<C> for #wrapper_name {
-                #[inline]
-                fn write_to<T: ?Sized + speedy::Writer<C>>(&self, writer: &mut T) -> Result<(), C::Error> {
-                    let mut __cdr_offset = 0usize;
-                    #(#write_stmts)*
-                    Ok(())
-                }
-            }
+            #wrapper_def
 
             let wrapper = #wrapper_name {
                 #(#wrapper_init),*
@@ -127,6 +122,48 @@ pub fn derive_ddsdata(input: TokenStream) -> TokenStream {
         }
     };
 
+    let serialize_key_fn = if keys_count == 0 {
+        quote! {}
+    } else {
+        let wrapper_def = gen_key_wrapper(&wrapper_name, &keys, false);
+        let wrapper_init = keys.iter().map(|(ident, _ty)| {
+            quote! { #ident: self.#ident.clone() }
+        });
+        quote! {
+            fn serialize_key(&self, endianness: speedy::Endianness) -> Option<Vec<u8>> {
+                #wrapper_def
+
+                let wrapper = #wrapper_name {
+                    #(#wrapper_init),*
+                };
+                speedy::Writable::<speedy::Endianness>::write_to_vec_with_ctx(&wrapper, endianness).ok()
+            }
+        }
+    };
+
+    let deserialize_key_fn = if keys_count == 0 || !is_key_deserialize {
+        quote! {}
+    } else {
+        let wrapper_def = gen_key_wrapper(&wrapper_name, &keys, true);
+        let key_init = keys.iter().map(|(ident, _ty)| {
+            quote! { #ident: wrapper.#ident }
+        });
+        let non_key_init = non_keys.iter().map(|ident| {
+            quote! { #ident: Default::default() }
+        });
+        quote! {
+            fn deserialize_key(endianness: speedy::Endianness, bytes: &[u8]) -> Option<Self> {
+                #wrapper_def
+
+                let wrapper = <#wrapper_name as speedy::Readable<'_, speedy::Endianness>>::read_from_buffer_with_ctx(endianness, bytes).ok()?;
+                Some(Self {
+                    #(#key_init,)*
+                    #(#non_key_init,)*
+                })
+            }
+        }
+    };
+
     let expanded = quote! {
         impl DdsData for #name {
             fn gen_key(&self) -> Option<KeyHash> {
@@ -140,12 +177,75 @@ pub fn derive_ddsdata(input: TokenStream) -> TokenStream {
             fn is_with_key() -> bool {
                 #is_with_key_val
             }
+
+            #serialize_key_fn
+
+            #deserialize_key_fn
         }
     };
 
     TokenStream::from(expanded)
 }
 
+/// struct holding only the key fields, which is serialized in CDR
+fn gen_key_wrapper(
+    wrapper_name: &syn::Ident,
+    keys: &[(&syn::Ident, &syn::Type)],
+    is_readable: bool,
+) -> TokenStream2 {
+    // definition of wrapper struct field
+    let wrapper_fields = keys.iter().map(|(ident, ty)| {
+        quote! { #ident: #ty }
+    });
+
+    let write_stmts = keys.iter().map(|(ident, ty)| {
+        let write_stmt = gen_write_stmt(ty, quote!(self.#ident));
+        quote! { #write_stmt }
+    });
+
+    let readable_impl = if is_readable {
+        let read_fields = keys.iter().map(|(ident, ty)| {
+            let read_expr = gen_read_expr(ty);
+            quote! { let #ident = #read_expr; }
+        });
+        let init_fields = keys.iter().map(|(ident, _ty)| {
+            quote! { #ident }
+        });
+        quote! {
+            impl<'a, C: speedy::Context> speedy::Readable<'a, C> for #wrapper_name {
+                #[inline]
+                fn read_from<R: speedy::Reader<'a, C>>(reader: &mut R) -> Result<Self, C::Error> {
+                    let mut __cdr_offset = 0usize;
+                    #(#read_fields)*
+                    Ok(Self {
+                        #(#init_fields),*
+                    })
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #[derive(Clone)]
+        struct #wrapper_name {
+            #(#wrapper_fields),*
+        }
+
+        impl<C: speedy::Context> speedy::Writable<C> for #wrapper_name {
+            #[inline]
+            fn write_to<T: ?Sized + speedy::Writer<C>>(&self, writer: &mut T) -> Result<(), C::Error> {
+                let mut __cdr_offset = 0usize;
+                #(#write_stmts)*
+                Ok(())
+            }
+        }
+
+        #readable_impl
+    }
+}
+
 /// Vec<T> -> T
 fn get_vec_inner_type(ty: &syn::Type) -> Option<&syn::Type> {
     if let syn::Type::Path(syn::TypePath { path, .. }) = ty {
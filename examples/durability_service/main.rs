@@ -0,0 +1,191 @@
+//! Durability service for Topics with TRANSIENT Durability
+//!
+//! DDS 1.4 spec, 2.2.3.4 DURABILITY
+//! > TRANSIENT: the service will attempt to keep some samples so that they can be delivered to any potential late-joining
+//! > DataReader. Which particular samples are kept depends on other QoS such as HISTORY and RESOURCE_LIMITS.
+//!
+//! This service runs as its own DomainParticipant. It subscribes to the configured Topics,
+//! keeps their history according to the DurabilityService QoS and republishes it to late-joining DataReaders,
+//! so that the history is available even after the original DataWriter is gone.
+//!
+//! Samples are forwarded as UntypedData, so the service works for any type.
+//! Only the type name and whether the type has a key are needed for each Topic.
+//!
+//! Dispose and unregister are not forwarded, and the source timestamp of a sample is not kept.
+//! Run only one durability service in a domain, otherwise the services republish samples of each other.
+//!
+//! usage: durability_service -t Square:ShapeType:key -t Status:StatusType -d 10
+use clap::{Arg, ArgAction, Command};
+use log::LevelFilter;
+use log4rs::{
+    append::console::ConsoleAppender,
+    config::{Appender, Config, Root},
+    encode::pattern::PatternEncoder,
+    init_config,
+};
+use mio_v06::{Events, Poll, PollOpt, Ready, Token};
+use rand::SeedableRng;
+use std::time::{Duration, SystemTime};
+use umber_dds::dds::{
+    qos::*, DataReader, DataReaderStatusChanged, DataWriter, DomainParticipant, UntypedData,
+};
+use umber_dds::structure::{RTPSEntity, TopicKind};
+
+struct ServicedTopic {
+    name: String,
+    datareader: DataReader<UntypedData>,
+    datawriter: DataWriter<UntypedData>,
+}
+
+fn main() {
+    let stderr = ConsoleAppender::builder()
+        .target(log4rs::append::console::Target::Stderr)
+        .encoder(Box::new(PatternEncoder::new(
+            "[{l}] [{d(%s%.f)}] [{t}]: {m}{n}",
+        )))
+        .build();
+    let config = Config::builder()
+        .appender(Appender::builder().build("stderr", Box::new(stderr)))
+        .build(Root::builder().appender("stderr").build(LevelFilter::Warn))
+        .unwrap();
+    init_config(config).unwrap();
+
+    let args = Command::new("durability_service")
+        .arg(
+            Arg::new("topic")
+                .short('t')
+                .help("Topic to keep, '<topic name>:<type name>' or '<topic name>:<type name>:key' if the type has a key")
+                .action(ArgAction::Append)
+                .required(true),
+        )
+        .arg(
+            Arg::new("depth")
+                .short('d')
+                .help("history depth of each instance kept by the service (default: 1)")
+                .required(false),
+        )
+        .get_matches();
+    let depth = match args.get_one::<String>("depth").map(|d| d.parse::<i32>()) {
+        Some(Ok(d)) if d > 0 => d,
+        Some(_) => {
+            println!("Warning: depth must be a positive integer. use default depth 1");
+            1
+        }
+        None => 1,
+    };
+    // DDS 1.4 spec, 2.2.3.5 DURABILITY_SERVICE
+    // the history kept by the service is configured by the DurabilityService QoS.
+    let history = policy::History::new(policy::HistoryQosKind::KeepLast, depth);
+    let resource_limits = policy::ResourceLimits::default();
+    let durability_service =
+        policy::DurabilityService::new(Duration::ZERO, history, resource_limits);
+
+    let poll = Poll::new().unwrap();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let mut small_rng = rand::rngs::SmallRng::seed_from_u64(now.as_nanos() as u64);
+
+    let domain_id = 0;
+    let nic = Vec::new();
+    let participant = DomainParticipant::new(domain_id, nic, None, &mut small_rng);
+    let subscriber = participant.create_subscriber(SubscriberQos::Default);
+    let publisher = participant.create_publisher(PublisherQos::Default);
+
+    let mut topics = Vec::new();
+    for topic_arg in args.get_many::<String>("topic").unwrap() {
+        let (name, type_name, kind) = match topic_arg.split(':').collect::<Vec<&str>>()[..] {
+            [name, type_name] => (name, type_name, TopicKind::NoKey),
+            [name, type_name, "key"] => (name, type_name, TopicKind::WithKey),
+            _ => {
+                println!(
+                    "Warning: invalid topic '{}'. topic must be '<topic name>:<type name>[:key]'",
+                    topic_arg
+                );
+                continue;
+            }
+        };
+        let topic = participant.create_untyped_topic(
+            name.to_string(),
+            type_name.to_string(),
+            kind,
+            TopicQos::Default,
+        );
+        let dr_qos = DataReaderQosBuilder::new()
+            .durability(policy::Durability::Transient)
+            .reliability(policy::Reliability::default_reliable())
+            .history(history)
+            .resource_limits(resource_limits)
+            .build();
+        let datareader = subscriber.create_datareader::<UntypedData>(
+            DataReaderQos::Policies(Box::new(dr_qos)),
+            topic.clone(),
+        );
+        let dw_qos = DataWriterQosBuilder::new()
+            .durability(policy::Durability::Transient)
+            .durability_service(durability_service)
+            .reliability(policy::Reliability::default_reliable())
+            .history(history)
+            .resource_limits(resource_limits)
+            .build();
+        let datawriter = publisher
+            .create_datawriter::<UntypedData>(DataWriterQos::Policies(Box::new(dw_qos)), topic);
+        // the DataReader of the service must not receive the samples republished by the service itself.
+        participant.ignore_publication(datawriter.guid());
+        poll.register(
+            &datareader,
+            Token(topics.len()),
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .unwrap();
+        println!("keep Topic ({}, {})", name, type_name);
+        topics.push(ServicedTopic {
+            name: name.to_string(),
+            datareader,
+            datawriter,
+        });
+    }
+    if topics.is_empty() {
+        println!("no valid topic is specified");
+        return;
+    }
+
+    loop {
+        let mut events = Events::with_capacity(64);
+        poll.poll(&mut events, None).unwrap();
+        for event in events.iter() {
+            let serviced = match topics.get_mut(event.token().0) {
+                Some(t) => t,
+                None => continue,
+            };
+            while let Ok(drc) = serviced.datareader.try_recv() {
+                match drc {
+                    DataReaderStatusChanged::DataAvailable => {
+                        for sample in serviced.datareader.take() {
+                            let mut data = sample.data().clone();
+                            data.set_key_hash(sample.sample_info().key_hash());
+                            serviced
+                                .datawriter
+                                .write_untyped(&data)
+                                .expect("failed to write serviced data");
+                        }
+                    }
+                    DataReaderStatusChanged::SubscriptionMatched(state) => {
+                        match state.current_count_change {
+                            1 => println!("{}: DataWriter matched, {}", serviced.name, state.guid),
+                            -1 => {
+                                println!("{}: DataWriter unmatched, {}", serviced.name, state.guid)
+                            }
+                            _ => (),
+                        }
+                    }
+                    DataReaderStatusChanged::RequestedIncompatibleQos(e) => {
+                        println!("{}: RequestedIncompatibleQos:\n{}", serviced.name, e);
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+}
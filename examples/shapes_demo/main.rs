@@ -15,7 +15,7 @@ use umber_dds::dds::{qos::*, DataReaderStatusChanged, DataWriterStatusChanged, D
 use umber_dds::{DdsData, DdsDeserialize, DdsSerialize, KeyHash};
 
 #[derive(Clone, Debug, DdsData, DdsSerialize, DdsDeserialize)]
-#[dds_data(type_name = "ShapeType")]
+#[dds_data(type_name = "ShapeType", key_deserialize)]
 struct Shape {
     #[key]
     color: String,
@@ -162,14 +162,14 @@ fn main() {
                                         DataWriterStatusChanged::LivelinessLost(_) => {
                                             println!("LivelinessLost");
                                         }
-                                        DataWriterStatusChanged::OfferedDeadlineMissed => {
-                                            println!("Deadline missed");
+                                        DataWriterStatusChanged::OfferedDeadlineMissed(d) => {
+                                            println!("Deadline missed: {:?}", d.last_instance_handle);
                                         }
                                     }
                                 }
                             }
                             WRITETIMTER => {
-                                datawriter.write(&shape);
+                                datawriter.write(&shape).expect("failed to write shape");
                                 println!("send: {:?}", shape);
                                 shape.x = (shape.x + 5) % 255;
                                 shape.y = (shape.y + 5) % 255;
@@ -204,7 +204,14 @@ fn main() {
                                         DataReaderStatusChanged::DataAvailable => {
                                             let received_shapes = datareader.take();
                                             for shape in received_shapes {
-                                                println!("received: {:?}", shape.data());
+                                                if shape.sample_info().valid_data {
+                                                    println!("received: {:?}", shape.data());
+                                                } else {
+                                                    println!(
+                                                        "received instance state change: {}",
+                                                        shape.data().color
+                                                    );
+                                                }
                                             }
                                         }
                                         DataReaderStatusChanged::SubscriptionMatched(state) => {
@@ -227,8 +234,8 @@ fn main() {
                                         DataReaderStatusChanged::LivelinessChanged(l) => {
                                             println!("LivelinessChanged: alive:{}, not_alive: {}, guid: {}", l.alive_count_change, l.not_alive_count_change, l.guid);
                                         }
-                                        DataReaderStatusChanged::RequestedDeadlineMissed(w) => {
-                                            println!("Deadline missed: {}", w);
+                                        DataReaderStatusChanged::RequestedDeadlineMissed(d) => {
+                                            println!("Deadline missed: {:?}", d.last_instance_handle);
                                         }
                                         _ => (), // TODO
                                     }
@@ -5,12 +5,14 @@ mod datawriter;
 mod event_loop;
 pub mod key;
 mod participant;
+mod persistent;
 mod publisher;
 pub mod qos;
 mod sample;
 mod subscriber;
 pub(crate) mod tokens;
 mod topic;
+mod untyped;
 
 pub use key::DdsData;
 
@@ -18,13 +20,17 @@ pub use {
     datareader::DataReader,
     datawriter::DataWriter,
     participant::{
-        DomainParticipant, ParticipantConfig, ParticipantConfigBuilder, DEFAULT_HEARTBEAT_PERIOD,
-        DEFAULT_HEARTBEAT_RESPONSE_DELAY, DEFAULT_LEASE_DURATION, DEFAULT_NACK_RESPONSE_DELAY,
-        DEFAULT_PARTICIPANT_MESSAGE_PERIOD,
+        DomainParticipant, ParticipantConfig, ParticipantConfigBuilder, DEFAULT_FRAGMENT_SIZE,
+        DEFAULT_HEARTBEAT_PERIOD, DEFAULT_HEARTBEAT_RESPONSE_DELAY, DEFAULT_LEASE_DURATION,
+        DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_NACK_RESPONSE_DELAY, DEFAULT_PARTICIPANT_MESSAGE_PERIOD,
     },
+    persistent::{FileStorage, PersistentStorage, DEFAULT_PERSISTENT_STORAGE_DIR},
     publisher::Publisher,
     subscriber::Subscriber,
     topic::Topic,
+    untyped::UntypedData,
 };
 
+pub use crate::error::DdsError;
+pub use crate::network::udp_sender::{default_transport_priority_mapping, SocketPriority};
 pub use crate::rtps::{reader::DataReaderStatusChanged, writer::DataWriterStatusChanged};
@@ -1,22 +1,54 @@
+use crate::dds::key::KeyHash;
 use crate::dds::{
-    qos::DataReaderQosPolicies,
+    qos::{
+        policy::{DestinationOrder, PresentationQosAccessScopeKind},
+        DataReaderQosPolicies,
+    },
     sample::{DataSample, SampleInfo},
     subscriber::Subscriber,
     topic::Topic,
 };
-use crate::message::submessage::element::RepresentationIdentifier;
-use crate::rtps::{cache::HistoryCache, reader::DataReaderStatusChanged};
+use crate::message::submessage::element::{RepresentationIdentifier, SerializedPayload};
+use crate::rtps::{
+    cache::{ChangeKind, HistoryCache, InstantHandle},
+    reader::DataReaderStatusChanged,
+};
 use crate::structure::GUID;
 use crate::DdsData;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use awkernel_sync::rwlock::RwLock;
+use bytes::Bytes;
 use core::marker::PhantomData;
-use log::{error, info};
+use core::sync::atomic::{AtomicBool, Ordering};
+use log::{debug, error, info};
 use mio_extras::channel as mio_channel;
 use mio_v06::{event::Evented, Poll, PollOpt, Ready, Token};
 use speedy::{Endianness, Readable};
 use std::io;
 
+/// KeyHash of the SerializedPayload of a change sent without PID_KEY_HASH
+///
+/// + is_key: whether the SerializedPayload is the key of the instance instead of the whole data
+pub(crate) fn payload_key_hash<R: for<'a> Readable<'a, Endianness> + DdsData>(
+    serialized_payload: &SerializedPayload,
+    is_key: bool,
+) -> Option<KeyHash> {
+    let endianness = match serialized_payload.representation_identifier {
+        RepresentationIdentifier::CDR_LE => Endianness::LittleEndian,
+        RepresentationIdentifier::CDR_BE => Endianness::BigEndian,
+        _ => return None,
+    };
+    let bytes = &serialized_payload.value;
+    if is_key {
+        R::deserialize_key(endianness, bytes)?.gen_key()
+    } else {
+        R::read_from_buffer_with_ctx(endianness, bytes)
+            .ok()?
+            .gen_key()
+    }
+}
+
 /// DDS DataReader
 pub struct DataReader<R: for<'a> Readable<'a, Endianness> + DdsData> {
     data_phantom: PhantomData<R>,
@@ -26,6 +58,11 @@ pub struct DataReader<R: for<'a> Readable<'a, Endianness> + DdsData> {
     _subscriber: Subscriber,
     rhc: Arc<RwLock<HistoryCache>>,
     reader_state_receiver: mio_channel::Receiver<DataReaderStatusChanged>,
+    /// SerializedPayload of the last ALIVE sample of each instance,
+    /// used to get the key of the sample which disposes or unregisters the instance without SerializedPayload
+    instance_payloads: RwLock<BTreeMap<InstantHandle, Bytes>>,
+    /// whether the failure of the key deserialization has been logged
+    key_error_logged: AtomicBool,
 }
 
 impl<R: for<'a> Readable<'a, Endianness> + DdsData> DataReader<R> {
@@ -58,6 +95,8 @@ impl<R: for<'a> Readable<'a, Endianness> + DdsData> DataReader<R> {
             _subscriber: subscriber,
             rhc,
             reader_state_receiver,
+            instance_payloads: RwLock::new(BTreeMap::new()),
+            key_error_logged: AtomicBool::new(false),
         }
     }
 
@@ -80,31 +119,86 @@ impl<R: for<'a> Readable<'a, Endianness> + DdsData> DataReader<R> {
     }
 
     fn get_data(&self) -> Vec<DataSample<R>> {
+        // DDS 1.4 spec, 2.2.3.6 PRESENTATION
+        // with ordered_access, changes from different DataWriters are also
+        // made available in the order they occurred.
+        let presentation = self._subscriber.get_qos().presentation();
+        let destination_order = if presentation.ordered_access
+            && presentation.access_scope != PresentationQosAccessScopeKind::Instance
+        {
+            DestinationOrder::BySourceTimestamp
+        } else {
+            self._qos.destination_order()
+        };
         let mut hc = self.rhc.write();
-        let (keys, changes) = hc.get_ready_changes();
+        let (keys, changes) = hc.get_ready_changes(destination_order);
         let mut v: Vec<DataSample<R>> = Vec::new();
-        for (d, ts) in changes
-            .iter()
-            .filter(|change| change.data_value().is_some())
-            .map(|change| (change.data_value().unwrap(), change.timestamp))
-        {
-            let received_bytes = d.to_bytes();
-            let encapsulation_kind =
-                RepresentationIdentifier::new([received_bytes[0], received_bytes[1]]);
-            let _encapsulation_option = [received_bytes[2], received_bytes[3]];
-            let endianness = match encapsulation_kind {
-                RepresentationIdentifier::CDR_LE => Endianness::LittleEndian,
-                RepresentationIdentifier::CDR_BE => Endianness::BigEndian,
-                rep => {
-                    let bytes = rep.bytes();
-                    panic!(
-                        "unexpected encapsulation_kind: [0x{:02x}, 0x{:02x}]",
-                        bytes[0], bytes[1]
-                    )
+        let mut instance_payloads = self.instance_payloads.write();
+        for change in changes.iter() {
+            let ts = change.source_timestamp;
+            let kind = change.kind();
+            let instance_handle = change.instance_handle();
+            if kind != ChangeKind::Alive {
+                // A change which is not ALIVE has the key of the instance as the SerializedPayload,
+                // or only PID_KEY_HASH. In the latter case, the key is got from the last ALIVE sample of the instance.
+                let key = change
+                    .data_value()
+                    .and_then(|d| {
+                        let received_bytes = d.to_bytes();
+                        R::deserialize_key(
+                            Self::payload_endianness(&received_bytes),
+                            &received_bytes[4..],
+                        )
+                    })
+                    .or_else(|| {
+                        instance_payloads.get(&instance_handle).and_then(|bytes| {
+                            R::read_from_buffer_with_ctx(
+                                Self::payload_endianness(bytes),
+                                &bytes[4..],
+                            )
+                            .ok()
+                        })
+                    });
+                if kind.is_unregistered() {
+                    instance_payloads.remove(&instance_handle);
                 }
+                match key {
+                    Some(data) => v.push(DataSample::new(
+                        data,
+                        SampleInfo::new(ts, false, instance_handle),
+                    )),
+                    None if self._reader_guid.entity_id.is_builtin() => debug!(
+                        "DataReader failed to deserialize key\n\tDataReader: {}\n\tTopic: {}",
+                        self._reader_guid, self.topic
+                    ),
+                    // the disposal or unregistration of the instance is not delivered,
+                    // so it should be noticed by the user once.
+                    None if !self.key_error_logged.swap(true, Ordering::Relaxed) => error!(
+                        "DataReader failed to deserialize key, derive DdsData with #[dds_data(key_deserialize)] to receive the samples of disposed or unregistered instances\n\tDataReader: {}\n\tTopic: {}",
+                        self._reader_guid, self.topic
+                    ),
+                    None => debug!(
+                        "DataReader failed to deserialize key\n\tDataReader: {}\n\tTopic: {}",
+                        self._reader_guid, self.topic
+                    ),
+                }
+                continue;
+            }
+            let Some(d) = change.data_value() else {
+                continue;
             };
+            let received_bytes = d.to_bytes();
+            let endianness = Self::payload_endianness(&received_bytes);
             match R::read_from_buffer_with_ctx(endianness, &received_bytes[4..]) {
-                Ok(data) => v.push(DataSample::new(data, SampleInfo::new(ts))),
+                Ok(data) => {
+                    if !instance_handle.is_nil() {
+                        instance_payloads.insert(instance_handle, received_bytes.clone());
+                    }
+                    v.push(DataSample::new(
+                        data,
+                        SampleInfo::new(ts, true, instance_handle),
+                    ))
+                }
                 Err(e) => error!(
                     "DataReader failed to deserialize: '{}'\n\tDataReader: {}\n\tTopic: {}",
                     e, self._reader_guid, self.topic
@@ -116,6 +210,23 @@ impl<R: for<'a> Readable<'a, Endianness> + DdsData> DataReader<R> {
         }
         v
     }
+    /// endianness of the SerializedPayload given by the encapsulation header
+    fn payload_endianness(received_bytes: &[u8]) -> Endianness {
+        let encapsulation_kind =
+            RepresentationIdentifier::new([received_bytes[0], received_bytes[1]]);
+        match encapsulation_kind {
+            RepresentationIdentifier::CDR_LE => Endianness::LittleEndian,
+            RepresentationIdentifier::CDR_BE => Endianness::BigEndian,
+            rep => {
+                let bytes = rep.bytes();
+                panic!(
+                    "unexpected encapsulation_kind: [0x{:02x}, 0x{:02x}]",
+                    bytes[0], bytes[1]
+                )
+            }
+        }
+    }
+
     pub fn get_qos(&self) -> DataReaderQosPolicies {
         self._qos.clone()
     }
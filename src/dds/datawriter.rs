@@ -1,12 +1,15 @@
 use crate::dds::{
-    key::DdsData,
+    key::{DdsData, KeyHash},
+    persistent::{PersistentHistory, PersistentSample},
     publisher::Publisher,
     qos::{
-        policy::{LivelinessQosKind, ReliabilityQosKind},
+        policy::{History, LivelinessQosKind, ReliabilityQosKind, ResourceLimits},
         DataWriterQosPolicies,
     },
     topic::Topic,
+    untyped::UntypedData,
 };
+use crate::error::DdsError;
 use crate::message::submessage::element::{
     RepresentationIdentifier, SequenceNumber, SerializedPayload, Timestamp,
 };
@@ -14,12 +17,13 @@ use crate::rtps::{
     cache::{AddChangeErr, CacheChange, ChangeKind, HistoryCache, InstantHandle},
     writer::*,
 };
-use crate::structure::GUID;
+use crate::structure::{RTPSEntity, GUID};
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use awkernel_sync::rwlock::RwLock;
 use core::marker::PhantomData;
 use core::time::Duration as CoreDuration;
-use log::{info, trace, warn};
+use log::{error, info, trace, warn};
 use mio_extras::channel as mio_channel;
 use mio_v06::{event::Evented, Poll, PollOpt, Ready, Token};
 use speedy::{Endianness, Writable};
@@ -34,10 +38,12 @@ pub struct DataWriter<W: Writable<Endianness> + DdsData> {
     topic: Topic,
     publisher: Publisher,
     whc: Arc<RwLock<HistoryCache>>,
-    // last_change_sequence_numberは本来はWriter::new_change()でのCacheChangeの作成時に使用するRTPS Writerのメンバ
-    // 本実装ではDataWrtierとRTPS Writerが別スレッドに配置されるため、DataWriterはRTPS Writerのnew_changeを叩けない。
-    // そのため、DataWriterがlast_change_sequence_numberを保持している。
-    last_change_sequence_number: SequenceNumber,
+    sequence: Arc<RwLock<WriterSequence>>,
+    /// instances written by this DataWriter and not unregistered yet,
+    /// with the SerializedPayload of their key sent when the DataWriter is deleted
+    registered_instances: BTreeMap<InstantHandle, Option<SerializedPayload>>,
+    /// history kept in the PersistentStorage if Durability is Persistent
+    persistent_history: Option<PersistentHistory>,
     // my_guid: GUID, // In RustDDS, DataWriter has guid to drop corresponding RTPSWriter
     // I implement guid for DataWriter when need.
     writer_command_sender: mio_channel::SyncSender<WriterCmd>,
@@ -53,6 +59,7 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
         publisher: Publisher,
         whc: Arc<RwLock<HistoryCache>>,
         writer_state_receiver: mio_channel::Receiver<DataWriterStatusChanged>,
+        persistent_history: Option<PersistentHistory>,
     ) -> Self {
         if writer_guid.entity_id.is_builtin() {
             info!(
@@ -74,7 +81,12 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
             topic,
             publisher,
             whc,
-            last_change_sequence_number: SequenceNumber(0),
+            sequence: Arc::new(RwLock::new(WriterSequence {
+                last_change_sequence_number: SequenceNumber(0),
+                coherent_set: None,
+            })),
+            registered_instances: BTreeMap::new(),
+            persistent_history,
             writer_command_sender,
             writer_state_receiver,
         }
@@ -87,11 +99,164 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
     }
 
     /// publish data for matching DataReader
-    pub fn write(&mut self, data: &W) {
-        let ts = Timestamp::now().expect("failed to get Timestamp::now()");
+    ///
+    /// Return `DdsError::OutOfResources` if the HistoryCache is full while the publications are suspended.
+    pub fn write(&mut self, data: &W) -> Result<(), DdsError> {
         let serialized_payload =
             SerializedPayload::new_from_cdr_data(data, RepresentationIdentifier::CDR_LE);
-        self.writer_data_to_hc(ts, serialized_payload, true);
+        self.write_payload(serialized_payload, data.gen_key(), || {
+            Self::key_payload(data, Endianness::LittleEndian)
+        })
+    }
+
+    /// + key: the SerializedPayload of the key, which is computed only if the key of the instance is not known yet.
+    fn write_payload(
+        &mut self,
+        serialized_payload: SerializedPayload,
+        key_hash: Option<KeyHash>,
+        key: impl FnOnce() -> Option<SerializedPayload>,
+    ) -> Result<(), DdsError> {
+        let ts = Timestamp::now().expect("failed to get Timestamp::now()");
+        let instance_handle = InstantHandle::from_key_hash(key_hash);
+        let registered_key = self
+            .registered_instances
+            .entry(instance_handle)
+            .or_insert(None);
+        if registered_key.is_none() {
+            *registered_key = key();
+        }
+        self.writer_data_to_hc(
+            ts,
+            ChangeKind::Alive,
+            Some(serialized_payload),
+            instance_handle,
+            true,
+            true,
+        )
+    }
+
+    /// dispose the instance which the data belongs to
+    ///
+    /// DDS 1.4 spec, 2.2.2.4.2.13 dispose
+    /// > This operation requests the middleware to delete the data (the actual deletion is postponed until there is no more use
+    /// > for that data in the whole system).
+    ///
+    /// Return `DdsError::OutOfResources` if the HistoryCache is full while the publications are suspended.
+    pub fn dispose(&mut self, data: &W) -> Result<(), DdsError> {
+        let ts = Timestamp::now().expect("failed to get Timestamp::now()");
+        let instance_handle = InstantHandle::from_key_hash(data.gen_key());
+        self.writer_data_to_hc(
+            ts,
+            ChangeKind::NotAliveDisposed,
+            Self::key_payload(data, Endianness::LittleEndian),
+            instance_handle,
+            true,
+            true,
+        )
+    }
+
+    /// unregister the instance which the data belongs to
+    ///
+    /// DDS 1.4 spec, 2.2.2.4.2.7 unregister_instance
+    /// > This operation reverses the action of register_instance. It should only be called on an instance that is currently
+    /// > registered.
+    ///
+    /// If `WriterDataLifecycle::autodispose_unregistered_instance` is true, the instance is also disposed.
+    ///
+    /// Return `DdsError::OutOfResources` if the HistoryCache is full while the publications are suspended.
+    pub fn unregister_instance(&mut self, data: &W) -> Result<(), DdsError> {
+        let instance_handle = InstantHandle::from_key_hash(data.gen_key());
+        if !self.registered_instances.contains_key(&instance_handle) {
+            warn!(
+                "DataWriter::unregister_instance called with unregistered instance {:?}\n\tWriter: {}",
+                instance_handle, self.writer_guid
+            );
+            return Ok(());
+        }
+        self.unregister(
+            instance_handle,
+            Self::key_payload(data, Endianness::LittleEndian),
+            true,
+        )
+    }
+
+    /// SerializedPayload of the key fields of the data,
+    /// sent by DATA with the Key flag instead of the whole data.
+    fn key_payload(data: &W, endianness: Endianness) -> Option<SerializedPayload> {
+        let rep_id = match endianness {
+            Endianness::LittleEndian => RepresentationIdentifier::CDR_LE,
+            Endianness::BigEndian => RepresentationIdentifier::CDR_BE,
+        };
+        data.serialize_key(endianness)
+            // a key serialized to nothing can't be carried by SerializedPayload
+            .filter(|key| !key.is_empty())
+            .map(|key| SerializedPayload::new(rep_id, key))
+    }
+
+    /// + may_block: whether to wait for the HistoryCache to have room for the change.
+    fn unregister(
+        &mut self,
+        instance_handle: InstantHandle,
+        key: Option<SerializedPayload>,
+        may_block: bool,
+    ) -> Result<(), DdsError> {
+        let ts = Timestamp::now().expect("failed to get Timestamp::now()");
+        // DDS 1.4 spec, 2.2.3.21 WRITER_DATA_LIFECYCLE
+        let kind = if self
+            .qos
+            .writer_data_lifecycle()
+            .autodispose_unregistered_instance
+        {
+            ChangeKind::NotAliveDisposedUnregistered
+        } else {
+            ChangeKind::NotAliveUnregistered
+        };
+        // the instance stays registered if the change can't be written
+        self.writer_data_to_hc(ts, kind, key, instance_handle, true, may_block)?;
+        self.registered_instances.remove(&instance_handle);
+        Ok(())
+    }
+
+    /// Write the history kept in the PersistentStorage to the HistoryCache
+    /// so that it is served to late-joining DataReaders.
+    ///
+    /// DDS 1.4 spec, 2.2.3.4 DURABILITY
+    pub(crate) fn restore_persistent_history(&mut self) {
+        // take the history so that the restored changes are not stored again
+        let history = match self.persistent_history.take() {
+            Some(h) => h,
+            None => return,
+        };
+        for sample in history.samples() {
+            if sample.kind == ChangeKind::Alive {
+                // the key can't be taken from the stored sample without deserializing it,
+                // so it is known only after the instance is written again.
+                self.registered_instances
+                    .entry(sample.instance_handle)
+                    .or_insert(None);
+            } else if sample.kind.is_unregistered() {
+                self.registered_instances.remove(&sample.instance_handle);
+            }
+            if let Err(e) = self.writer_data_to_hc(
+                sample.timestamp,
+                sample.kind,
+                sample.serialized_payload.clone(),
+                sample.instance_handle,
+                true,
+                true,
+            ) {
+                warn!(
+                    "DataWriter failed to restore change from PersistentStorage: {}\n\tWriter: {}",
+                    e, self.writer_guid
+                );
+            }
+        }
+        info!(
+            "DataWriter restored {} changes from PersistentStorage\n\tWriter: {}",
+            history.samples().len(),
+            self.writer_guid
+        );
+        self.persistent_history = Some(history);
     }
 
     /// + inc_seq_num: whether the seq_num needs to be incremented.
@@ -99,7 +264,15 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
         let ts = Timestamp::now().expect("failed to get Timestamp::now()");
         let serialized_payload =
             SerializedPayload::new_from_cdr_data(data, RepresentationIdentifier::PL_CDR_LE);
-        self.writer_data_to_hc(ts, serialized_payload, inc_seq_num);
+        self.writer_data_to_hc(
+            ts,
+            ChangeKind::Alive,
+            Some(serialized_payload),
+            InstantHandle::NIL,
+            inc_seq_num,
+            true,
+        )
+        .expect("builtin DataWriter is never suspended");
     }
 
     /// + inc_seq_num: whether the seq_num needs to be incremented.
@@ -109,28 +282,54 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
         inc_seq_num: bool,
     ) {
         let ts = Timestamp::now().expect("failed to get Timestamp::now()");
-        self.writer_data_to_hc(ts, data, inc_seq_num);
+        self.writer_data_to_hc(
+            ts,
+            ChangeKind::Alive,
+            Some(data),
+            InstantHandle::NIL,
+            inc_seq_num,
+            true,
+        )
+        .expect("builtin DataWriter is never suspended");
     }
 
+    /// + may_block: whether to wait for the HistoryCache to have room for the change.
+    ///   If false, the change is dropped and the failure of notifying the RTPS Writer is ignored.
+    ///
+    /// Return `DdsError::OutOfResources` if the change is dropped because the HistoryCache is full.
     fn writer_data_to_hc(
         &mut self,
         ts: Timestamp,
-        serialized_payload: SerializedPayload,
+        kind: ChangeKind,
+        serialized_payload: Option<SerializedPayload>,
+        instance_handle: InstantHandle,
         inc_seq_num: bool,
-    ) {
+        may_block: bool,
+    ) -> Result<(), DdsError> {
+        let is_coherent_changes = self.publisher.is_coherent_changes();
+        let is_suspended = self.publisher.is_suspended();
+        let mut sequence = self.sequence.write();
         if inc_seq_num {
-            self.last_change_sequence_number += SequenceNumber(1);
-        } else if self.last_change_sequence_number == SequenceNumber(0) {
-            self.last_change_sequence_number = SequenceNumber(1);
+            sequence.last_change_sequence_number += SequenceNumber(1);
+        } else if sequence.last_change_sequence_number == SequenceNumber(0) {
+            sequence.last_change_sequence_number = SequenceNumber(1);
         }
-        let a_change = CacheChange::new(
-            ChangeKind::Alive,
+        let seq_num = sequence.last_change_sequence_number;
+        let mut a_change = CacheChange::new(
+            kind,
             self.writer_guid,
-            self.last_change_sequence_number,
+            seq_num,
             ts,
-            Some(serialized_payload),
-            InstantHandle {},
+            serialized_payload,
+            instance_handle,
         );
+        // rtps 2.3 spec, 8.7.5 Coherent Sets
+        // changes in a coherent set have the SequenceNumber of the first change in the set.
+        if is_coherent_changes {
+            a_change.coherent_set = Some(*sequence.coherent_set.get_or_insert(seq_num));
+        } else {
+            sequence.coherent_set = None;
+        }
         loop {
             let write_res = self.whc.write().add_change(
                 a_change.clone(),
@@ -149,13 +348,41 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
                     }
                     trace!(
                         "DataWriter add change to HistoryCache: seq_num: {}\n\tWriter: {}",
-                        self.last_change_sequence_number.0,
+                        seq_num.0,
                         self.writer_guid
                     );
-                    self.writer_command_sender
-                        .send(WriterCmd::WriteData)
-                        .expect("failed to send WriterCmd via channel 'writer_command_sender'");
-                    break;
+                    if let Some(history) = self.persistent_history.as_mut() {
+                        if !a_change.is_coherent_set_end() {
+                            history.push(PersistentSample::from_change(&a_change));
+                        }
+                    }
+                    // while the publications are suspended, the change is kept in the HistoryCache
+                    // and sent when Publisher::resume_publications is called.
+                    if !is_suspended {
+                        let send_res = self.writer_command_sender.send(WriterCmd::WriteData);
+                        if may_block {
+                            send_res.expect(
+                                "failed to send WriterCmd via channel 'writer_command_sender'",
+                            );
+                        } else if send_res.is_err() {
+                            warn!(
+                                "DataWriter failed to send WriterCmd, the event loop may have stopped\n\tWriter: {}",
+                                self.writer_guid
+                            );
+                        }
+                    }
+                    return Ok(());
+                }
+                Err(AddChangeErr::WouldBlock(t)) if is_suspended || !may_block => {
+                    // while the publications are suspended, no change is sent nor acknowledged,
+                    // so blocking here never ends.
+                    if inc_seq_num {
+                        sequence.last_change_sequence_number -= SequenceNumber(1);
+                    }
+                    if sequence.coherent_set == Some(seq_num) {
+                        sequence.coherent_set = None;
+                    }
+                    return Err(DdsError::OutOfResources(t));
                 }
                 Err(AddChangeErr::WouldBlock(t)) => {
                     warn!(
@@ -164,6 +391,9 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
                     );
                     std::thread::sleep(CoreDuration::from_millis(200));
                 }
+                Err(AddChangeErr::Rejected(_)) => {
+                    unreachable!("Writer HistoryCache rejected change")
+                }
             }
         }
     }
@@ -201,6 +431,134 @@ impl<W: Writable<Endianness> + DdsData> DataWriter<W> {
             ReliabilityQosKind::BestEffort => false,
         }
     }
+
+    pub(crate) fn handle(&self) -> DataWriterHandle {
+        DataWriterHandle {
+            writer_guid: self.writer_guid,
+            whc: self.whc.clone(),
+            sequence: self.sequence.clone(),
+            writer_command_sender: self.writer_command_sender.clone(),
+        }
+    }
+}
+
+impl DataWriter<UntypedData> {
+    /// publish UntypedData for matching DataReader
+    ///
+    /// Unlike `write`, the value is published with the CDR encapsulation of its endianness,
+    /// so UntypedData taken from a DataReader can be forwarded as it is.
+    ///
+    /// Return `DdsError::OutOfResources` if the HistoryCache is full while the publications are suspended.
+    pub fn write_untyped(&mut self, data: &UntypedData) -> Result<(), DdsError> {
+        let rep_id = match data.endianness() {
+            Endianness::LittleEndian => RepresentationIdentifier::CDR_LE,
+            Endianness::BigEndian => RepresentationIdentifier::CDR_BE,
+        };
+        let serialized_payload = SerializedPayload::new_from_cdr_data(data, rep_id);
+        self.write_payload(serialized_payload, data.gen_key(), || {
+            Self::key_payload(data, data.endianness())
+        })
+    }
+}
+
+impl<W: Writable<Endianness> + DdsData> RTPSEntity for DataWriter<W> {
+    fn guid(&self) -> GUID {
+        self.writer_guid
+    }
+}
+
+/// SequenceNumber state of a DataWriter
+///
+/// This is shared with the Publisher which created the DataWriter
+/// so that `Publisher::end_coherent_changes` can end the coherent set of the DataWriter.
+pub(crate) struct WriterSequence {
+    // last_change_sequence_numberは本来はWriter::new_change()でのCacheChangeの作成時に使用するRTPS Writerのメンバ
+    // 本実装ではDataWrtierとRTPS Writerが別スレッドに配置されるため、DataWriterはRTPS Writerのnew_changeを叩けない。
+    // そのため、DataWriterがlast_change_sequence_numberを保持している。
+    last_change_sequence_number: SequenceNumber,
+    /// SequenceNumber of the first change in the coherent set the DataWriter is writing
+    coherent_set: Option<SequenceNumber>,
+}
+
+/// DataWriter held by the Publisher to end coherent sets
+#[derive(Clone)]
+pub(crate) struct DataWriterHandle {
+    writer_guid: GUID,
+    whc: Arc<RwLock<HistoryCache>>,
+    sequence: Arc<RwLock<WriterSequence>>,
+    writer_command_sender: mio_channel::SyncSender<WriterCmd>,
+}
+
+impl DataWriterHandle {
+    /// rtps 2.3 spec, 8.7.5 Coherent Sets
+    /// If the DataWriter has written changes in a coherent set, add a change without data
+    /// whose coherent set is SEQUENCENUMBER_UNKNOWN to notify the end of the set.
+    ///
+    /// return whether the change was added to the HistoryCache.
+    /// The change is sent when [`Self::send_changes`] is called.
+    pub(crate) fn end_coherent_set(&self) -> bool {
+        let mut sequence = self.sequence.write();
+        if sequence.coherent_set.take().is_none() {
+            return false;
+        }
+        sequence.last_change_sequence_number += SequenceNumber(1);
+        let mut a_change = CacheChange::new(
+            ChangeKind::Alive,
+            self.writer_guid,
+            sequence.last_change_sequence_number,
+            Timestamp::now().expect("failed to get Timestamp::now()"),
+            None,
+            InstantHandle::NIL,
+        );
+        a_change.coherent_set = Some(SequenceNumber::SEQUENCENUMBER_UNKNOWN);
+        // the change has no data, so it is added regardless of History and ResourceLimits
+        if let Err(e) = self.whc.write().add_change(
+            a_change,
+            false,
+            ResourceLimits::default(),
+            History::default(),
+        ) {
+            warn!(
+                "DataWriter failed to add end of coherent set to HistoryCache: {}\n\tWriter: {}",
+                e, self.writer_guid
+            );
+            return false;
+        }
+        trace!(
+            "DataWriter add end of coherent set to HistoryCache: seq_num: {}\n\tWriter: {}",
+            sequence.last_change_sequence_number.0,
+            self.writer_guid
+        );
+        true
+    }
+
+    /// request the RTPS Writer to send the changes which have not been sent yet
+    pub(crate) fn send_changes(&self) {
+        self.writer_command_sender
+            .send(WriterCmd::WriteData)
+            .expect("failed to send WriterCmd via channel 'writer_command_sender'");
+    }
+}
+
+impl<W: Writable<Endianness> + DdsData> Drop for DataWriter<W> {
+    /// DDS 1.4 spec, 2.2.3.21 WRITER_DATA_LIFECYCLE
+    /// > The deletion of a DataWriter automatically unregisters all data-instances it manages.
+    fn drop(&mut self) {
+        // the deletion of the DataWriter is not stored in the PersistentStorage,
+        // so that the history survives the restart of the application.
+        self.persistent_history = None;
+        let instances = core::mem::take(&mut self.registered_instances);
+        for (instance_handle, key) in instances {
+            // drop must not block nor panic, even if the Reliable HistoryCache is full
+            // or the event loop has already stopped.
+            if let Err(e) = self.unregister(instance_handle, key, false) {
+                error!(
+                    "DataWriter dropped unregistration of instance {:?}: {}\n\tWriter: {}",
+                    instance_handle, e, self.writer_guid
+                );
+            }
+        }
+    }
 }
 
 impl<W: Writable<Endianness> + DdsData> Evented for DataWriter<W> {
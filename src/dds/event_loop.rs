@@ -1,5 +1,5 @@
 use crate::dds::qos::{
-    policy::{LivelinessQosKind, Reliability},
+    policy::{LivelinessQosKind, Partition, Reliability},
     DataReaderQosBuilder,
 };
 use crate::dds::tokens::*;
@@ -8,8 +8,8 @@ use crate::discovery::{
     structure::data::{DiscoveredReaderData, DiscoveredWriterData},
     BuiltinEndpointsIngredients, DiscoveryDBUpdateNotifier,
 };
-use crate::rtps::cache::HCKey;
-use crate::rtps::reader::{Reader, ReaderIngredients, ReaderTimer};
+use crate::rtps::cache::{HCKey, InstantHandle};
+use crate::rtps::reader::{Reader, ReaderIngredients, ReaderTimer, FRAGMENT_REASSEMBLY_TIMEOUT};
 use crate::rtps::writer::{Writer, WriterIngredients, WriterTimer};
 use crate::structure::{Duration, EntityId, GuidPrefix, RTPSEntity, GUID};
 use alloc::collections::BTreeMap;
@@ -24,14 +24,32 @@ use mio_extras::{
 use mio_v06::net::UdpSocket;
 use mio_v06::{Events, Poll, PollOpt, Ready, Token};
 
+use crate::message::message_builder::MessagePacker;
 use crate::message::message_receiver::*;
-use crate::message::submessage::element::{Locator, SerializedPayload, Timestamp};
-use crate::network::{net_util::*, udp_sender::UdpSender};
+use crate::message::submessage::element::{Locator, SequenceNumber, SerializedPayload, Timestamp};
+use crate::network::{
+    net_util::*,
+    udp_sender::{SocketPriority, UdpSender},
+};
 
 const MAX_MESSAGE_SIZE: usize = 64 * 1024; // This is max we can get from UDP.
 const MESSAGE_BUFFER_ALLOCATION_CHUNK: usize = 256 * 1024;
 const ASSERT_LIVELINESS_PERIOD: u64 = 10;
 
+/// Notification from Publisher/Subscriber that its Partition has been changed.
+/// EntityIds are the ones of Writers/Readers which belong to the Publisher/Subscriber.
+pub(crate) enum PartitionUpdate {
+    Writers(Vec<EntityId>, Partition),
+    Readers(Vec<EntityId>, Partition),
+}
+
+/// Request from Publisher to the Writers which belong to it.
+pub(crate) enum PublisherCmd {
+    /// DDS 1.4 spec, 2.2.2.4.1.9 resume_publications
+    /// send the changes written by the Writers while the publications are suspended
+    ResumePublications(Vec<EntityId>),
+}
+
 pub struct EventLoop {
     domain_id: u16,
     guid_prefix: GuidPrefix,
@@ -42,6 +60,10 @@ pub struct EventLoop {
     create_writer_receiver: mio_channel::Receiver<WriterIngredients>,
     // receive writer ingredients from subscriber
     create_reader_receiver: mio_channel::Receiver<ReaderIngredients>,
+    // receive Partition update from publisher and subscriber
+    update_partition_receiver: mio_channel::Receiver<PartitionUpdate>,
+    // receive command from publisher
+    publisher_cmd_receiver: mio_channel::Receiver<PublisherCmd>,
     // notify new writer to discovery module
     notify_new_writer_sender: mio_channel::Sender<(EntityId, DiscoveredWriterData)>,
     // notify new reader to discovery module
@@ -50,13 +72,18 @@ pub struct EventLoop {
     readers: BTreeMap<EntityId, Reader>,
     udp_sender: Rc<UdpSender>,
     writer_hb_timer: Timer<EntityId>,
-    reader_hb_timer: Timer<(EntityId, GUID)>, // (reader EntityId, writer GUID)
-    reader_deadline_timer: Timer<((EntityId, GUID), CoreDuration)>, // (reader EntityId, writer GUID)
-    reader_deadline_timeout: BTreeMap<(EntityId, GUID), Timeout>, // (reader EntityId, writer GUID)
-    reader_lifespan_timer: Timer<(EntityId, HCKey)>,              // (reader EntityId, writer GUID)
-    writer_nack_timer: Timer<(EntityId, GUID)>,                   // (writer EntityId, reader GUID)
-    writer_deadline_timer: Timer<(EntityId, CoreDuration)>,
-    writer_deadline_timeout: BTreeMap<EntityId, Timeout>,
+    writer_hb_timeout: BTreeMap<EntityId, Timeout>, // writer EntityId
+    reader_hb_timer: Timer<(EntityId, GUID)>,       // (reader EntityId, writer GUID)
+    reader_deadline_timer: Timer<((EntityId, InstantHandle), CoreDuration)>, // (reader EntityId, instance)
+    reader_deadline_timeout: BTreeMap<(EntityId, InstantHandle), Timeout>, // (reader EntityId, instance)
+    reader_lifespan_timer: Timer<(EntityId, HCKey)>, // (reader EntityId, writer GUID)
+    reader_autopurge_timer: Timer<(EntityId, InstantHandle, Timestamp)>, // (reader EntityId, instance, Timestamp of the state change)
+    reader_fragment_timer: Timer<(EntityId, HCKey, Timestamp)>, // (reader EntityId, HCKey of the fragmented change, Timestamp of the first fragment)
+    writer_nack_timer: Timer<(EntityId, GUID)>,                 // (writer EntityId, reader GUID)
+    writer_deadline_timer: Timer<((EntityId, InstantHandle), CoreDuration)>, // (writer EntityId, instance)
+    writer_deadline_timeout: BTreeMap<(EntityId, InstantHandle), Timeout>, // (writer EntityId, instance)
+    writer_lifespan_timer: Timer<(EntityId, SequenceNumber)>, // (writer EntityId, SequenceNumber of the change)
+    writer_latency_budget_timer: Timer<EntityId>,             // writer EntityId
     wlp_timer_receiver: mio_channel::Receiver<EntityId>,
     wlp_timer: Timer<EntityId>,                //  reader EntityId
     wlp_timeouts: BTreeMap<EntityId, Timeout>, //  reader EntityId
@@ -78,6 +105,8 @@ impl EventLoop {
         participant_guidprefix: GuidPrefix,
         create_writer_receiver: mio_channel::Receiver<WriterIngredients>,
         create_reader_receiver: mio_channel::Receiver<ReaderIngredients>,
+        update_partition_receiver: mio_channel::Receiver<PartitionUpdate>,
+        publisher_cmd_receiver: mio_channel::Receiver<PublisherCmd>,
         notify_new_writer_sender: mio_channel::Sender<(EntityId, DiscoveredWriterData)>,
         notify_new_reader_sender: mio_channel::Sender<(EntityId, DiscoveredReaderData)>,
         discovery_db: DiscoveryDB,
@@ -104,6 +133,20 @@ impl EventLoop {
             PollOpt::edge(),
         )
         .expect("failed to register receiver 'create_reader_receiver' with poll");
+        poll.register(
+            &update_partition_receiver,
+            UPDATE_PARTITION_TOKEN,
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .expect("failed to register receiver 'update_partition_receiver' with poll");
+        poll.register(
+            &publisher_cmd_receiver,
+            PUBLISHER_CMD_TOKEN,
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .expect("failed to register receiver 'publisher_cmd_receiver' with poll");
         let writer_hb_timer = Timer::default();
         poll.register(
             &writer_hb_timer,
@@ -172,6 +215,22 @@ impl EventLoop {
             PollOpt::edge(),
         )
         .expect("failed to register timer 'reader_lifespan_timer' with poll");
+        let reader_autopurge_timer = Timer::default();
+        poll.register(
+            &reader_autopurge_timer,
+            READER_AUTOPURGE_TIMER,
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .expect("failed to register timer 'reader_autopurge_timer' with poll");
+        let reader_fragment_timer = Timer::default();
+        poll.register(
+            &reader_fragment_timer,
+            READER_FRAGMENT_TIMER,
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .expect("failed to register timer 'reader_fragment_timer' with poll");
         let writer_nack_timer = Timer::default();
         poll.register(
             &writer_nack_timer,
@@ -188,6 +247,22 @@ impl EventLoop {
             PollOpt::edge(),
         )
         .expect("failed to register timer 'writer_deadline_timer' with poll");
+        let writer_lifespan_timer = Timer::default();
+        poll.register(
+            &writer_lifespan_timer,
+            WRITER_LIFESPAN_TIMER,
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .expect("failed to register timer 'writer_lifespan_timer' with poll");
+        let writer_latency_budget_timer = Timer::default();
+        poll.register(
+            &writer_latency_budget_timer,
+            WRITER_LATENCY_BUDGET_TIMER,
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .expect("failed to register timer 'writer_latency_budget_timer' with poll");
         let wlp_timer = Timer::default();
         poll.register(
             &wlp_timer,
@@ -211,17 +286,24 @@ impl EventLoop {
             message_receiver,
             create_writer_receiver,
             create_reader_receiver,
+            update_partition_receiver,
+            publisher_cmd_receiver,
             notify_new_writer_sender,
             notify_new_reader_sender,
             writers: BTreeMap::new(),
             readers: BTreeMap::new(),
             udp_sender: Rc::new(udp_sender),
             writer_hb_timer,
+            writer_hb_timeout: BTreeMap::new(),
             reader_hb_timer,
             reader_deadline_timer,
             reader_deadline_timeout: BTreeMap::new(),
             reader_lifespan_timer,
+            reader_autopurge_timer,
+            reader_fragment_timer,
             writer_nack_timer,
+            writer_lifespan_timer,
+            writer_latency_budget_timer,
             writer_deadline_timer,
             writer_deadline_timeout: BTreeMap::new(),
             wlp_timer_receiver,
@@ -270,34 +352,53 @@ impl EventLoop {
                         error!("not found Reader from EventLoop.readers which attempt to set heartbeat timer\n\tReader: {}", reader_entity_id);
                     }
                 }
-                ReaderTimer::Deadline(reader_entity_id, writer_guid, duration) => {
+                ReaderTimer::Deadline(reader_entity_id, instance, duration) => {
                     if let Some(to) = self
                         .reader_deadline_timeout
-                        .get(&(*reader_entity_id, *writer_guid))
+                        .get(&(*reader_entity_id, *instance))
                     {
                         trace!(
-                            "cancel Writer Deadline timer({:?})\n\tReader: {}\n\tWriter: {}",
+                            "cancel Reader Deadline timer({:?})\n\tReader: {}\n\tinstance: {:?}",
                             duration,
                             reader_entity_id,
-                            writer_guid,
+                            instance,
                         );
                         self.reader_deadline_timer.cancel_timeout(to);
                     }
                     trace!(
-                        "set Reader Deadline timer({:?})\n\tReader: {}\n\tWriter: {}",
+                        "set Reader Deadline timer({:?})\n\tReader: {}\n\tinstance: {:?}",
                         duration,
                         reader_entity_id,
-                        writer_guid,
+                        instance,
                     );
                     let to = self
                         .reader_deadline_timer
-                        .set_timeout(*duration, ((*reader_entity_id, *writer_guid), *duration));
+                        .set_timeout(*duration, ((*reader_entity_id, *instance), *duration));
                     self.reader_deadline_timeout
-                        .insert((*reader_entity_id, *writer_guid), to);
+                        .insert((*reader_entity_id, *instance), to);
+                }
+                ReaderTimer::CancelDeadline(reader_entity_id, instance) => {
+                    if let Some(to) = self
+                        .reader_deadline_timeout
+                        .remove(&(*reader_entity_id, *instance))
+                    {
+                        trace!(
+                            "cancel Reader Deadline timer\n\tReader: {}\n\tinstance: {:?}",
+                            reader_entity_id,
+                            instance,
+                        );
+                        self.reader_deadline_timer.cancel_timeout(&to);
+                    }
                 }
                 ReaderTimer::Lifespan(reader_entity_id, hc_key, ts, lifespan_duration) => {
                     let now = Timestamp::now().expect("failed get Timestamp::now");
-                    let duration = (*ts + *lifespan_duration) - now;
+                    // the change may already be expired, e.g. when it is written with an old source timestamp
+                    let expires_at = *ts + *lifespan_duration;
+                    let duration = if expires_at > now {
+                        expires_at - now
+                    } else {
+                        CoreDuration::ZERO
+                    };
                     self.reader_lifespan_timer
                         .set_timeout(duration, (*reader_entity_id, *hc_key));
                     trace!(
@@ -307,6 +408,28 @@ impl EventLoop {
                         hc_key
                     );
                 }
+                ReaderTimer::Autopurge(reader_entity_id, instance, changed_at, delay) => {
+                    self.reader_autopurge_timer
+                        .set_timeout(*delay, (*reader_entity_id, *instance, *changed_at));
+                    trace!(
+                        "set Reader Autopurge timer({:?})\n\tReader: {}\n\tinstance: {:?}",
+                        delay,
+                        reader_entity_id,
+                        instance
+                    );
+                }
+                ReaderTimer::Fragment(reader_entity_id, hc_key, first_received) => {
+                    self.reader_fragment_timer.set_timeout(
+                        FRAGMENT_REASSEMBLY_TIMEOUT,
+                        (*reader_entity_id, *hc_key, *first_received),
+                    );
+                    trace!(
+                        "set Reader Fragment timer({:?})\n\tReader: {}\n\t{}",
+                        FRAGMENT_REASSEMBLY_TIMEOUT,
+                        reader_entity_id,
+                        hc_key
+                    );
+                }
             }
         }
     }
@@ -329,24 +452,83 @@ impl EventLoop {
                         error!("not found Writer from EventLoop.writers which attempt to set nack response timer\n\tWriter: {}", writer_entity_id);
                     }
                 }
-                WriterTimer::Deadline(writer_entity_id, duration) => {
-                    if let Some(to) = self.writer_deadline_timeout.get(writer_entity_id) {
+                WriterTimer::Deadline(writer_entity_id, instance, duration) => {
+                    if let Some(to) = self
+                        .writer_deadline_timeout
+                        .get(&(*writer_entity_id, *instance))
+                    {
                         trace!(
-                            "cancel Writer Deadline timer({:?})\n\tWriter: {}",
+                            "cancel Writer Deadline timer({:?})\n\tWriter: {}\n\tinstance: {:?}",
                             duration,
                             writer_entity_id,
+                            instance,
                         );
                         self.writer_deadline_timer.cancel_timeout(to);
                     }
                     trace!(
-                        "set Writer Deadline timer({:?})\n\tWriter: {}",
+                        "set Writer Deadline timer({:?})\n\tWriter: {}\n\tinstance: {:?}",
                         duration,
                         writer_entity_id,
+                        instance,
                     );
                     let to = self
                         .writer_deadline_timer
-                        .set_timeout(*duration, (*writer_entity_id, *duration));
-                    self.writer_deadline_timeout.insert(*writer_entity_id, to);
+                        .set_timeout(*duration, ((*writer_entity_id, *instance), *duration));
+                    self.writer_deadline_timeout
+                        .insert((*writer_entity_id, *instance), to);
+                }
+                WriterTimer::CancelDeadline(writer_entity_id, instance) => {
+                    if let Some(to) = self
+                        .writer_deadline_timeout
+                        .remove(&(*writer_entity_id, *instance))
+                    {
+                        trace!(
+                            "cancel Writer Deadline timer\n\tWriter: {}\n\tinstance: {:?}",
+                            writer_entity_id,
+                            instance,
+                        );
+                        self.writer_deadline_timer.cancel_timeout(&to);
+                    }
+                }
+                WriterTimer::Lifespan(writer_entity_id, seq_num, ts, lifespan_duration) => {
+                    let now = Timestamp::now().expect("failed get Timestamp::now");
+                    // the change may already be expired, e.g. when it is written with an old source timestamp
+                    let expires_at = *ts + *lifespan_duration;
+                    let duration = if expires_at > now {
+                        expires_at - now
+                    } else {
+                        CoreDuration::ZERO
+                    };
+                    self.writer_lifespan_timer
+                        .set_timeout(duration, (*writer_entity_id, *seq_num));
+                    trace!(
+                        "set Writer Lifespan timer({:?})\n\tWriter: {}\n\tseq_num: {}",
+                        duration,
+                        writer_entity_id,
+                        seq_num.0
+                    );
+                }
+                WriterTimer::LatencyBudget(writer_entity_id, duration) => {
+                    self.writer_latency_budget_timer
+                        .set_timeout(*duration, *writer_entity_id);
+                    trace!(
+                        "set Writer LatencyBudget timer({:?})\n\tWriter: {}",
+                        duration,
+                        writer_entity_id,
+                    );
+                }
+                WriterTimer::Heartbeat(writer_entity_id, period) => {
+                    // reschedule the next HEARTBEAT
+                    if let Some(to) = self.writer_hb_timeout.get(writer_entity_id) {
+                        self.writer_hb_timer.cancel_timeout(to);
+                    }
+                    let to = self.writer_hb_timer.set_timeout(*period, *writer_entity_id);
+                    self.writer_hb_timeout.insert(*writer_entity_id, to);
+                    trace!(
+                        "set Writer Heartbeat timer({:?})\n\tWriter: {}",
+                        period,
+                        writer_entity_id,
+                    );
                 }
             }
         }
@@ -396,17 +578,28 @@ impl EventLoop {
                         DISCOVERY_DB_UPDATE => {
                             self.handle_participant_discovery();
                         }
+                        UPDATE_PARTITION_TOKEN => {
+                            while let Ok(update) = self.update_partition_receiver.try_recv() {
+                                self.handle_partition_update(update);
+                            }
+                        }
+                        PUBLISHER_CMD_TOKEN => {
+                            while let Ok(cmd) = self.publisher_cmd_receiver.try_recv() {
+                                self.handle_publisher_cmd(cmd);
+                            }
+                        }
                         WRITER_HEARTBEAT_TIMER => {
                             while let Some(eid) = self.writer_hb_timer.poll() {
                                 trace!("fired Writer Heartbeat timer({})", eid);
                                 if let Some(writer) = self.writers.get_mut(&eid) {
                                     writer.send_heart_beat(false);
-                                    self.writer_hb_timer
-                                        .set_timeout(writer.heartbeat_period(), writer.entity_id());
+                                    let period = writer.next_heartbeat_period();
+                                    let to = self.writer_hb_timer.set_timeout(period, eid);
+                                    self.writer_hb_timeout.insert(eid, to);
                                     trace!(
                                         "set Writer Heartbeat timer({:?})\n\tWriter: {}",
-                                        writer.heartbeat_period(),
-                                        writer.entity_id(),
+                                        period,
+                                        eid,
                                     );
                                 } else {
                                     error!("not found Writer from EventLoop.writers which fired Heartbeat timer\n\tWriter: {}", eid);
@@ -414,25 +607,26 @@ impl EventLoop {
                             }
                         }
                         READER_DEADLINE_TIMER => {
-                            while let Some(((reid, wguid), duration)) =
+                            while let Some(((reid, instance), duration)) =
                                 self.reader_deadline_timer.poll()
                             {
                                 trace!(
-                                    "fired Reader Deadline timer\n\tReader: {}\n\tWriter: {}",
+                                    "fired Reader Deadline timer\n\tReader: {}\n\tinstance: {:?}",
                                     reid,
-                                    wguid
+                                    instance
                                 );
-                                if let Some(reader) = self.readers.get(&reid) {
-                                    reader.notify_reqested_deadline_missed(wguid);
+                                if let Some(reader) = self.readers.get_mut(&reid) {
+                                    reader.notify_reqested_deadline_missed(instance);
                                     trace!(
-                                        "set Reader Deadline timer({:?})\n\tReader: {}",
+                                        "set Reader Deadline timer({:?})\n\tReader: {}\n\tinstance: {:?}",
                                         duration,
                                         reid,
+                                        instance,
                                     );
                                     let to = self
                                         .reader_deadline_timer
-                                        .set_timeout(duration, ((reid, wguid), duration));
-                                    self.reader_deadline_timeout.insert((reid, wguid), to);
+                                        .set_timeout(duration, ((reid, instance), duration));
+                                    self.reader_deadline_timeout.insert((reid, instance), to);
                                 } else {
                                     unreachable!();
                                 }
@@ -456,25 +650,90 @@ impl EventLoop {
                                 }
                             }
                         }
+                        READER_AUTOPURGE_TIMER => {
+                            while let Some((reid, instance, changed_at)) =
+                                self.reader_autopurge_timer.poll()
+                            {
+                                trace!(
+                                    "fired Reader Autopurge timer\n\tReader: {}\n\tinstance: {:?}",
+                                    reid,
+                                    instance
+                                );
+                                if let Some(reader) = self.readers.get_mut(&reid) {
+                                    reader.autopurge_instance(instance, changed_at);
+                                } else {
+                                    unreachable!();
+                                }
+                            }
+                        }
+                        READER_FRAGMENT_TIMER => {
+                            while let Some((reid, hc_key, first_received)) =
+                                self.reader_fragment_timer.poll()
+                            {
+                                trace!(
+                                    "fired Reader Fragment timer\n\tReader: {}\n\t{}",
+                                    reid,
+                                    hc_key
+                                );
+                                // the Reader may have been deleted before the timer fires.
+                                if let Some(reader) = self.readers.get_mut(&reid) {
+                                    reader.handle_fragment_timeout(hc_key, first_received);
+                                }
+                            }
+                        }
                         WRITER_DEADLINE_TIMER => {
-                            while let Some((eid, duration)) = self.writer_deadline_timer.poll() {
-                                trace!("fired Writer Deadline timer\n\tWriter: {}", eid);
-                                if let Some(writer) = self.writers.get(&eid) {
-                                    writer.notify_offered_deadline_missed();
+                            while let Some(((eid, instance), duration)) =
+                                self.writer_deadline_timer.poll()
+                            {
+                                trace!(
+                                    "fired Writer Deadline timer\n\tWriter: {}\n\tinstance: {:?}",
+                                    eid,
+                                    instance
+                                );
+                                if let Some(writer) = self.writers.get_mut(&eid) {
+                                    writer.notify_offered_deadline_missed(instance);
                                     trace!(
-                                        "set Writer Deadline timer({:?})\n\tWriter: {}",
+                                        "set Writer Deadline timer({:?})\n\tWriter: {}\n\tinstance: {:?}",
                                         duration,
                                         eid,
+                                        instance,
                                     );
                                     let to = self
                                         .writer_deadline_timer
-                                        .set_timeout(duration, (eid, duration));
-                                    self.writer_deadline_timeout.insert(eid, to);
+                                        .set_timeout(duration, ((eid, instance), duration));
+                                    self.writer_deadline_timeout.insert((eid, instance), to);
                                 } else {
                                     unreachable!();
                                 }
                             }
                         }
+                        WRITER_LIFESPAN_TIMER => {
+                            // NOTE: The `writer_lifespan_timer` does not get canceled when the change
+                            // is removed from the `HistoryCache` because it is acknowledged by all Readers.
+                            // As a result, when the timer fires, the target change may no longer
+                            // exist in the `HistoryCache`.
+                            while let Some((eid, seq_num)) = self.writer_lifespan_timer.poll() {
+                                trace!(
+                                    "fired Writer Lifespan timer\n\tWriter: {}\n\tseq_num: {}",
+                                    eid,
+                                    seq_num.0
+                                );
+                                if let Some(writer) = self.writers.get_mut(&eid) {
+                                    writer.remove_expired_change(seq_num);
+                                } else {
+                                    unreachable!();
+                                }
+                            }
+                        }
+                        WRITER_LATENCY_BUDGET_TIMER => {
+                            while let Some(eid) = self.writer_latency_budget_timer.poll() {
+                                trace!("fired Writer LatencyBudget timer\n\tWriter: {}", eid);
+                                // the Writer may have been deleted before the timer fired
+                                if let Some(writer) = self.writers.get_mut(&eid) {
+                                    writer.flush_batch();
+                                }
+                            }
+                        }
                         WRITER_LIVELINESS_CHECK_TIMER => {
                             while let Some(eid) = self.wlp_timer.poll() {
                                 trace!("fired Reader liveliness check timer\n\tReader: {}", eid);
@@ -626,11 +885,25 @@ impl EventLoop {
                     }
                 }
             }
+            // Autopurge and CancelDeadline timers are requested when the instance_state is changed,
+            // which is caused by receiving changes and by removing Writers on various events.
+            let reader_timers: Vec<ReaderTimer> = self
+                .readers
+                .values_mut()
+                .flat_map(|reader| reader.take_pending_timers())
+                .collect();
+            if !reader_timers.is_empty() {
+                self.handle_set_reader_timer(&reader_timers);
+            }
         }
     }
 
     fn register_writer(&mut self, writer_ing: WriterIngredients) {
-        let (mut writer, wt) = Writer::new(writer_ing, self.udp_sender.clone());
+        let (mut writer, wt) = Writer::new(
+            writer_ing,
+            self.udp_sender.clone(),
+            self.discovery_db.clone(),
+        );
         if let Some(wt) = wt {
             self.handle_set_writer_timer(&[wt]);
         }
@@ -661,8 +934,10 @@ impl EventLoop {
                 writer.heartbeat_period(),
                 writer.entity_id(),
             );
-            self.writer_hb_timer
+            let to = self
+                .writer_hb_timer
                 .set_timeout(writer.heartbeat_period(), writer.entity_id());
+            self.writer_hb_timeout.insert(writer.entity_id(), to);
         }
         let qos = writer.get_qos();
         match qos.liveliness().kind {
@@ -727,7 +1002,11 @@ impl EventLoop {
         self.writers.insert(writer.entity_id(), writer);
     }
     fn register_reader(&mut self, reader_ing: ReaderIngredients) {
-        let reader = Reader::new(reader_ing, self.udp_sender.clone());
+        let reader = Reader::new(
+            reader_ing,
+            self.udp_sender.clone(),
+            self.discovery_db.clone(),
+        );
         if reader.entity_id() != EntityId::SPDP_BUILTIN_PARTICIPANT_DETECTOR
             && reader.entity_id() != EntityId::SEDP_BUILTIN_PUBLICATIONS_DETECTOR
             && reader.entity_id() != EntityId::SEDP_BUILTIN_SUBSCRIPTIONS_DETECTOR
@@ -744,6 +1023,87 @@ impl EventLoop {
         self.readers.insert(reader.entity_id(), reader);
     }
 
+    fn handle_partition_update(&mut self, update: PartitionUpdate) {
+        // DDS 1.4 spec, 2.2.3.13 PARTITION
+        // Changing the partition may break existing matches and establish new ones.
+        // The updated Partition is also re-announced by SEDP.
+        match update {
+            PartitionUpdate::Writers(eids, partition) => {
+                for eid in eids {
+                    if let Some(writer) = self.writers.get_mut(&eid) {
+                        writer.set_partition(partition.clone());
+                        self.notify_new_writer_sender
+                            .send((eid, writer.sedp_data()))
+                            .expect("failed to send data via channel 'notify_new_writer_sender'");
+                    } else {
+                        error!(
+                            "not found Writer from EventLoop.writers which Partition updated\n\tWriter: {}",
+                            eid
+                        );
+                    }
+                }
+            }
+            PartitionUpdate::Readers(eids, partition) => {
+                let mut reader_timers = Vec::new();
+                for eid in eids {
+                    if let Some(reader) = self.readers.get_mut(&eid) {
+                        reader_timers.append(&mut reader.set_partition(partition.clone()));
+                        self.notify_new_reader_sender
+                            .send((eid, reader.sedp_data()))
+                            .expect("failed to send data via channle 'notify_new_reader_sender'");
+                    } else {
+                        error!(
+                            "not found Reader from EventLoop.readers which Partition updated\n\tReader: {}",
+                            eid
+                        );
+                    }
+                }
+                self.handle_set_reader_timer(&reader_timers);
+            }
+        }
+    }
+
+    fn handle_publisher_cmd(&mut self, cmd: PublisherCmd) {
+        match cmd {
+            PublisherCmd::ResumePublications(eids) => {
+                // pack the submessages of the Writers destined for the same locator
+                // into as few RTPS Messages as possible.
+                // The Writers with different TransportPriority are sent by different sockets,
+                // so their submessages are not packed together.
+                let mut packers: BTreeMap<
+                    (Option<SocketPriority>, Locator),
+                    (EntityId, MessagePacker),
+                > = BTreeMap::new();
+                let mut writer_timers = Vec::new();
+                for eid in eids {
+                    if let Some(writer) = self.writers.get_mut(&eid) {
+                        let (mut wts, messages) = writer.resume_publication();
+                        writer_timers.append(&mut wts);
+                        for (loc, submessages) in messages {
+                            packers
+                                .entry((writer.socket_priority(), loc))
+                                .or_insert_with(|| (eid, writer.message_packer()))
+                                .1
+                                .append(submessages);
+                        }
+                    } else {
+                        error!(
+                            "not found Writer from EventLoop.writers which publication resumed\n\tWriter: {}",
+                            eid
+                        );
+                    }
+                }
+                for ((_priority, loc), (eid, packer)) in packers {
+                    let writer = &self.writers[&eid];
+                    for message_buf in packer.finish() {
+                        writer.send_msg_to_locator(loc, message_buf, "data");
+                    }
+                }
+                self.handle_set_writer_timer(&writer_timers);
+            }
+        }
+    }
+
     fn receiv_packet(udp_sock: &UdpSocket) -> Vec<UdpMessage> {
         let mut packets: Vec<UdpMessage> = Vec::with_capacity(4);
         loop {
@@ -783,6 +1143,10 @@ impl EventLoop {
                     );
                     self.remove_discoverd_participant(guid_prefix);
                 }
+                DiscoveryDBUpdateNotifier::IgnoreEndpoint(guid) => {
+                    info!("remove ignored Endpoint\n\tEndpoint: {}", guid);
+                    self.remove_ignored_endpoint(guid);
+                }
             }
         }
     }
@@ -795,4 +1159,13 @@ impl EventLoop {
             w.delete_reader_proxy(participant_guidp);
         }
     }
+
+    fn remove_ignored_endpoint(&mut self, guid: GUID) {
+        for (_eid, r) in self.readers.iter_mut() {
+            r.delete_writer_proxy_with_guid(guid);
+        }
+        for (_eid, w) in self.writers.iter_mut() {
+            w.delete_reader_proxy_with_guid(guid);
+        }
+    }
 }
@@ -11,6 +11,10 @@ impl KeyHash {
         hash_in.copy_from_slice(bytes);
         Self { _hash: hash_in }
     }
+
+    pub(crate) fn value(&self) -> [u8; 16] {
+        self._hash
+    }
 }
 
 /// Trait for Data that exchanged via DDS
@@ -53,6 +57,24 @@ pub trait DdsData {
     fn type_name() -> String;
     /// Returns whether this type has a key.
     fn is_with_key() -> bool;
+    /// Serialize only the key fields in CDR.
+    ///
+    /// This is sent by DATA with the Key flag when the instance is disposed or unregistered.
+    /// Returns None if this type has no key.
+    fn serialize_key(&self, _endianness: Endianness) -> Option<Vec<u8>> {
+        None
+    }
+    /// Deserialize the key fields serialized by `serialize_key`.
+    ///
+    /// The fields which are not key are `Default::default()`.
+    /// The derive macro generates this only with `#[dds_data(key_deserialize)]`.
+    /// Returns None if this type doesn't support it or failed to deserialize.
+    fn deserialize_key(_endianness: Endianness, _bytes: &[u8]) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 pub trait Key: std::fmt::Debug + Writable<Endianness> {}
@@ -76,9 +98,10 @@ impl<K: Key> Key for Vec<K> {} // IDL: sequence<K: Key>
 mod test {
     use super::KeyHash;
     use crate::DdsData;
-    use speedy::Writable;
+    use speedy::{Endianness, Writable};
 
     #[derive(DdsData, Debug)]
+    #[dds_data(key_deserialize)]
     struct Shape {
         #[key]
         color: String,
@@ -105,4 +128,21 @@ mod test {
             ]
         )
     }
+
+    #[test]
+    fn test_key_only() {
+        let shape = Shape {
+            color: String::from("RED"),
+            _x: 10,
+            _y: 20,
+            _shapesize: 30,
+        };
+
+        let key = shape.serialize_key(Endianness::LittleEndian).unwrap();
+        assert_eq!(key, [0x04, 0x00, 0x00, 0x00, 0x52, 0x45, 0x44, 0x00]);
+        let key_only = Shape::deserialize_key(Endianness::LittleEndian, &key).unwrap();
+        assert_eq!(key_only.color, "RED");
+        assert_eq!(key_only._x, 0);
+        assert_eq!(key_only._shapesize, 0);
+    }
 }
@@ -11,13 +11,17 @@ use crate::message::{
     message_header::ProtocolVersion,
     submessage::element::{Locator, RepresentationIdentifier, SerializedPayload},
 };
-use crate::network::{net_util::*, udp_sender::UdpSender};
+use crate::network::{
+    net_util::*,
+    udp_sender::{default_transport_priority_mapping, SocketPriority, UdpSender},
+};
 use crate::rtps::reader::ReaderIngredients;
 use crate::rtps::writer::WriterIngredients;
 use crate::structure::{RTPSEntity, VendorId};
 use crate::{
     dds::{
-        event_loop::EventLoop,
+        event_loop::{EventLoop, PartitionUpdate, PublisherCmd},
+        persistent::{FileStorage, PersistentStorage, DEFAULT_PERSISTENT_STORAGE_DIR},
         publisher::Publisher,
         qos::{
             PublisherQos, PublisherQosBuilder, PublisherQosPolicies, SubscriberQos,
@@ -30,7 +34,7 @@ use crate::{
         DdsData,
     },
     network::udp_listinig_socket::*,
-    structure::{EntityId, EntityKind, TopicKind, GUID},
+    structure::{EntityId, EntityKind, GuidPrefix, TopicKind, GUID},
 };
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
@@ -66,6 +70,8 @@ struct EvLoopIngredients {
     udp_sender: UdpSender,
     create_writer_receiver: mio_extras::channel::Receiver<WriterIngredients>,
     create_reader_receiver: mio_extras::channel::Receiver<ReaderIngredients>,
+    update_partition_receiver: mio_extras::channel::Receiver<PartitionUpdate>,
+    publisher_cmd_receiver: mio_extras::channel::Receiver<PublisherCmd>,
 }
 
 impl DomainParticipant {
@@ -118,6 +124,8 @@ impl DomainParticipant {
         let (dp_inner, ev_loop_ing) = DomainParticipantInner::new(
             domain_id,
             participant_msg_cmd_sender,
+            discovery_db.clone(),
+            discdb_update_sender.clone(),
             dp_network_interfaces.clone(),
             participant_config,
             small_rng,
@@ -144,6 +152,8 @@ impl DomainParticipant {
                     guid_prefix,
                     ev_loop_ing.create_writer_receiver,
                     ev_loop_ing.create_reader_receiver,
+                    ev_loop_ing.update_partition_receiver,
+                    ev_loop_ing.publisher_cmd_receiver,
                     notify_new_writer_sender,
                     notify_new_reader_sender,
                     discovery_db_clone,
@@ -198,6 +208,21 @@ impl DomainParticipant {
             .lock(&mut node)
             .create_topic::<D>(self.clone(), name, qos)
     }
+    /// Create a Topic whose type is given by `type_name` instead of a type implementing DdsData
+    ///
+    /// Use this Topic with [`crate::dds::UntypedData`] to handle samples of a type not known at compile time.
+    pub fn create_untyped_topic(
+        &self,
+        name: String,
+        type_name: String,
+        kind: TopicKind,
+        qos: TopicQos,
+    ) -> Topic {
+        let mut node = MCSNode::new();
+        self.inner
+            .lock(&mut node)
+            .create_builtin_topic(self.clone(), name, type_name, kind, qos)
+    }
     pub(crate) fn create_builtin_topic(
         &self,
         name: String,
@@ -230,6 +255,20 @@ impl DomainParticipant {
         let mut node = MCSNode::new();
         self.inner.lock(&mut node).get_config()
     }
+    /// Set the storage which keeps the history of DataWriters with `Durability::Persistent`.
+    ///
+    /// By default, [`FileStorage`] in [`DEFAULT_PERSISTENT_STORAGE_DIR`] is used.
+    /// This must be called before creating DataWriters with `Durability::Persistent`.
+    pub fn set_persistent_storage(&self, storage: Box<dyn PersistentStorage>) {
+        let mut node = MCSNode::new();
+        let persistent_storage = self.inner.lock(&mut node).persistent_storage.clone();
+        let mut node = MCSNode::new();
+        *persistent_storage.lock(&mut node) = storage;
+    }
+    pub(crate) fn persistent_storage(&self) -> Arc<Mutex<Box<dyn PersistentStorage>>> {
+        let mut node = MCSNode::new();
+        self.inner.lock(&mut node).persistent_storage.clone()
+    }
     pub fn get_default_publisher_qos(&self) -> PublisherQosPolicies {
         let mut node = MCSNode::new();
         self.inner.lock(&mut node).get_default_publisher_qos()
@@ -254,6 +293,48 @@ impl DomainParticipant {
         let mut node = MCSNode::new();
         self.inner.lock(&mut node).set_default_topic_qos(qos);
     }
+
+    /// Ignore the remote DomainParticipant identified by `guid_prefix`.
+    ///
+    /// After this call, SPDP, SEDP and user DATA from the Participant are dropped,
+    /// and Writers and Readers which belong to the Participant are never matched.
+    /// Endpoints of the Participant which are already matched are unmatched.
+    ///
+    /// There is no way to stop ignoring the Participant.
+    pub fn ignore_participant(&self, guid_prefix: GuidPrefix) {
+        let mut node = MCSNode::new();
+        self.inner.lock(&mut node).ignore_participant(guid_prefix);
+    }
+    /// Ignore the remote DataWriter identified by `guid`.
+    ///
+    /// After this call, the DataWriter is never matched with local DataReaders,
+    /// and DATA from it is dropped. If the DataWriter is already matched, it is unmatched.
+    ///
+    /// There is no way to stop ignoring the DataWriter.
+    pub fn ignore_publication(&self, guid: GUID) {
+        let mut node = MCSNode::new();
+        self.inner.lock(&mut node).ignore_endpoint(guid);
+    }
+    /// Ignore the remote DataReader identified by `guid`.
+    ///
+    /// After this call, the DataReader is never matched with local DataWriters.
+    /// If the DataReader is already matched, it is unmatched.
+    ///
+    /// There is no way to stop ignoring the DataReader.
+    pub fn ignore_subscription(&self, guid: GUID) {
+        let mut node = MCSNode::new();
+        self.inner.lock(&mut node).ignore_endpoint(guid);
+    }
+    /// Ignore the Topic named `topic_name`.
+    ///
+    /// After this call, remote DataWriters and DataReaders of the Topic discovered by SEDP are never matched.
+    /// Endpoints which are already matched are not affected.
+    ///
+    /// There is no way to stop ignoring the Topic.
+    pub fn ignore_topic(&self, topic_name: &str) {
+        let mut node = MCSNode::new();
+        self.inner.lock(&mut node).ignore_topic(topic_name);
+    }
 }
 
 pub(crate) struct DomainParticipantInner {
@@ -262,6 +343,8 @@ pub(crate) struct DomainParticipantInner {
     pub my_guid: GUID,
     create_writer_sender: mio_channel::SyncSender<WriterIngredients>,
     create_reader_sender: mio_channel::SyncSender<ReaderIngredients>,
+    update_partition_sender: mio_channel::SyncSender<PartitionUpdate>,
+    publisher_cmd_sender: mio_channel::SyncSender<PublisherCmd>,
     ev_loop_handler: Option<thread::JoinHandle<()>>,
     discovery_handler: Option<thread::JoinHandle<()>>,
     entity_key_generator: AtomicU32,
@@ -269,7 +352,10 @@ pub(crate) struct DomainParticipantInner {
     default_subscriber_qos: SubscriberQosPolicies,
     default_topic_qos: TopicQosPolicies,
     participant_msg_cmd_sender: mio_channel::SyncSender<ParticipantMessageCmd>,
+    discovery_db: DiscoveryDB,
+    discdb_update_sender: mio_channel::Sender<DiscoveryDBUpdateNotifier>,
     participant_config: ParticipantConfig,
+    persistent_storage: Arc<Mutex<Box<dyn PersistentStorage>>>,
     network_interfaces: Vec<Ipv4Addr>,
     _spdp_data: SPDPdiscoveredParticipantData,
     serialized_spdp_data: SerializedPayload,
@@ -280,6 +366,8 @@ impl DomainParticipantInner {
     fn new(
         domain_id: u16,
         participant_msg_cmd_sender: mio_channel::SyncSender<ParticipantMessageCmd>,
+        discovery_db: DiscoveryDB,
+        discdb_update_sender: mio_channel::Sender<DiscoveryDBUpdateNotifier>,
         network_interfaces: Vec<Ipv4Addr>,
         participant_config: ParticipantConfig,
         small_rng: &mut SmallRng,
@@ -339,6 +427,10 @@ impl DomainParticipantInner {
             mio_channel::sync_channel::<WriterIngredients>(10);
         let (create_reader_sender, create_reader_receiver) =
             mio_channel::sync_channel::<ReaderIngredients>(10);
+        let (update_partition_sender, update_partition_receiver) =
+            mio_channel::sync_channel::<PartitionUpdate>(10);
+        let (publisher_cmd_sender, publisher_cmd_receiver) =
+            mio_channel::sync_channel::<PublisherCmd>(10);
 
         let my_guid = GUID::new_participant_guid(small_rng);
 
@@ -385,6 +477,8 @@ impl DomainParticipantInner {
             my_guid,
             create_writer_sender,
             create_reader_sender,
+            update_partition_sender,
+            publisher_cmd_sender,
             ev_loop_handler: None,
             discovery_handler: None,
             // largest pre-difined entityKey is {00, 02, 01} @DDS-Security 1.1
@@ -394,7 +488,12 @@ impl DomainParticipantInner {
             default_subscriber_qos,
             default_topic_qos,
             participant_msg_cmd_sender,
+            discovery_db,
+            discdb_update_sender,
             participant_config,
+            persistent_storage: Arc::new(Mutex::new(Box::new(FileStorage::new(
+                DEFAULT_PERSISTENT_STORAGE_DIR,
+            )))),
             network_interfaces,
             _spdp_data: spdp_data,
             serialized_spdp_data,
@@ -404,6 +503,8 @@ impl DomainParticipantInner {
             udp_sender,
             create_writer_receiver,
             create_reader_receiver,
+            update_partition_receiver,
+            publisher_cmd_receiver,
         };
         (dp, ev_loop_ing)
     }
@@ -420,6 +521,8 @@ impl DomainParticipantInner {
                 self.default_publisher_qos.clone(),
                 dp,
                 self.create_writer_sender.clone(),
+                self.update_partition_sender.clone(),
+                self.publisher_cmd_sender.clone(),
                 self.participant_msg_cmd_sender.clone(),
             ),
             PublisherQos::Policies(q) => Publisher::new(
@@ -427,6 +530,8 @@ impl DomainParticipantInner {
                 *q,
                 dp,
                 self.create_writer_sender.clone(),
+                self.update_partition_sender.clone(),
+                self.publisher_cmd_sender.clone(),
                 self.participant_msg_cmd_sender.clone(),
             ),
         }
@@ -443,10 +548,15 @@ impl DomainParticipantInner {
                 self.default_subscriber_qos.clone(),
                 dp,
                 self.create_reader_sender.clone(),
+                self.update_partition_sender.clone(),
+            ),
+            SubscriberQos::Policies(q) => Subscriber::new(
+                guid,
+                *q,
+                dp,
+                self.create_reader_sender.clone(),
+                self.update_partition_sender.clone(),
             ),
-            SubscriberQos::Policies(q) => {
-                Subscriber::new(guid, *q, dp, self.create_reader_sender.clone())
-            }
         }
     }
 
@@ -518,6 +628,22 @@ impl DomainParticipantInner {
     pub fn set_default_topic_qos(&mut self, qos: TopicQosPolicies) {
         self.default_topic_qos = qos;
     }
+
+    fn ignore_participant(&mut self, guid_prefix: GuidPrefix) {
+        self.discovery_db.ignore_participant(guid_prefix);
+        self.discdb_update_sender
+            .send(DiscoveryDBUpdateNotifier::DeleteParticipant(guid_prefix))
+            .expect("failed to send data via channel 'discdb_update_sender'");
+    }
+    fn ignore_endpoint(&mut self, guid: GUID) {
+        self.discovery_db.ignore_endpoint(guid);
+        self.discdb_update_sender
+            .send(DiscoveryDBUpdateNotifier::IgnoreEndpoint(guid))
+            .expect("failed to send data via channel 'discdb_update_sender'");
+    }
+    fn ignore_topic(&mut self, topic_name: &str) {
+        self.discovery_db.ignore_topic(topic_name.to_string());
+    }
 }
 
 impl Drop for DomainParticipantInner {
@@ -541,6 +667,10 @@ pub const DEFAULT_HEARTBEAT_PERIOD: CoreDuration = CoreDuration::from_secs(2);
 pub const DEFAULT_NACK_RESPONSE_DELAY: CoreDuration = CoreDuration::from_secs(0);
 /// 0 seconds
 pub const DEFAULT_HEARTBEAT_RESPONSE_DELAY: CoreDuration = CoreDuration::from_secs(0);
+/// 64000 octets
+pub const DEFAULT_FRAGMENT_SIZE: u16 = 64000;
+/// 65507 octets, the maximum UDP payload size over IPv4
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 65507;
 
 /// ParticipantConfig
 ///
@@ -575,6 +705,9 @@ pub struct ParticipantConfig {
     /// sequence number and the range of data that requires acknowledgment. A shorter
     /// period improves latency for unacknowledged data but increases network traffic.
     ///
+    /// After new data is written, Heartbeat messages are sent faster, starting from 1/8 of this period
+    /// and doubling the interval until it reaches this period or all data is acknowledged.
+    ///
     /// default value of Umber DDS is [`DEFAULT_HEARTBEAT_PERIOD`]
     pub heartbeat_period: CoreDuration,
     /// The time interval a Reliable DataWriter waits after receiving an ACKNACK
@@ -591,6 +724,34 @@ pub struct ParticipantConfig {
     ///
     /// default value of Umber DDS is [`DEFAULT_HEARTBEAT_RESPONSE_DELAY`]
     pub heartbeat_response_delay: CoreDuration,
+    /// The mapping from the value of TransportPriority QoS of a DataWriter to the priority of the sender sockets.
+    ///
+    /// DDS 1.4 spec, 2.2.3.15 TRANSPORT_PRIORITY
+    /// > The value of the transport_priority is used by the DDS implementation to set the priority used by the underlying
+    /// > transport to send the data.
+    ///
+    /// DATA, HEARTBEAT and GAP of the DataWriter are sent from sockets with the DSCP and SO_PRIORITY returned by this function.
+    /// If this returns None, the sockets without priority are used.
+    ///
+    /// default value of Umber DDS is [`default_transport_priority_mapping`]
+    pub transport_priority_mapping: fn(i32) -> Option<SocketPriority>,
+    /// The size in octets of a fragment of the SerializedPayload sent by DATA_FRAG.
+    ///
+    /// A SerializedPayload larger than this is split into fragments of this size and each fragment is sent by DATA_FRAG.
+    /// A smaller value avoids the IP fragmentation of large samples, but increases the number of RTPS Messages.
+    /// This must be greater than 0 and not greater than [`DEFAULT_FRAGMENT_SIZE`],
+    /// because the RTPS Message including the fragment must fit in a UDP datagram.
+    ///
+    /// default value of Umber DDS is [`DEFAULT_FRAGMENT_SIZE`]
+    pub fragment_size: u16,
+    /// The upper limit in octets of the size of an RTPS Message sent by DataWriters.
+    ///
+    /// DATA, GAP and HEARTBEAT submessages sent to the same destination are packed into one RTPS Message up to this size,
+    /// which reduces the number of packets and system calls when many samples are sent at once.
+    /// A DATA_FRAG is not split by this, so set `fragment_size` smaller than this to avoid the IP fragmentation.
+    ///
+    /// default value of Umber DDS is [`DEFAULT_MAX_MESSAGE_SIZE`]
+    pub max_message_size: usize,
 }
 
 impl Default for ParticipantConfig {
@@ -601,6 +762,9 @@ impl Default for ParticipantConfig {
             heartbeat_period: DEFAULT_HEARTBEAT_PERIOD,
             nack_response_delay: DEFAULT_NACK_RESPONSE_DELAY,
             heartbeat_response_delay: DEFAULT_HEARTBEAT_RESPONSE_DELAY,
+            transport_priority_mapping: default_transport_priority_mapping,
+            fragment_size: DEFAULT_FRAGMENT_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
         }
     }
 }
@@ -616,6 +780,9 @@ pub struct ParticipantConfigBuilder {
     heartbeat_period: Option<CoreDuration>,
     nack_response_delay: Option<CoreDuration>,
     heartbeat_response_delay: Option<CoreDuration>,
+    transport_priority_mapping: Option<fn(i32) -> Option<SocketPriority>>,
+    fragment_size: Option<u16>,
+    max_message_size: Option<usize>,
 }
 
 impl ParticipantConfigBuilder {
@@ -626,6 +793,9 @@ impl ParticipantConfigBuilder {
             heartbeat_period: None,
             nack_response_delay: None,
             heartbeat_response_delay: None,
+            transport_priority_mapping: None,
+            fragment_size: None,
+            max_message_size: None,
         }
     }
 
@@ -637,6 +807,20 @@ impl ParticipantConfigBuilder {
         if participant_message_period >= lease_duration {
             panic!("lease_duration must longer than participant_message_period. lease_duration: {:?}, participant_message_period: {:?}", lease_duration, participant_message_period);
         }
+        let fragment_size = self.fragment_size.unwrap_or(DEFAULT_FRAGMENT_SIZE);
+        if fragment_size == 0 || fragment_size > DEFAULT_FRAGMENT_SIZE {
+            panic!(
+                "fragment_size must be in 1..={}. fragment_size: {}",
+                DEFAULT_FRAGMENT_SIZE, fragment_size
+            );
+        }
+        let max_message_size = self.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+        if max_message_size > DEFAULT_MAX_MESSAGE_SIZE {
+            panic!(
+                "max_message_size must not be greater than {}. max_message_size: {}",
+                DEFAULT_MAX_MESSAGE_SIZE, max_message_size
+            );
+        }
         ParticipantConfig {
             participant_message_period,
             lease_duration,
@@ -647,6 +831,11 @@ impl ParticipantConfigBuilder {
             heartbeat_response_delay: self
                 .heartbeat_response_delay
                 .unwrap_or(DEFAULT_HEARTBEAT_RESPONSE_DELAY),
+            transport_priority_mapping: self
+                .transport_priority_mapping
+                .unwrap_or(default_transport_priority_mapping),
+            fragment_size,
+            max_message_size,
         }
     }
 
@@ -670,6 +859,21 @@ impl ParticipantConfigBuilder {
         self.heartbeat_response_delay = Some(period);
         self
     }
+    pub fn transport_priority_mapping(
+        mut self,
+        mapping: fn(i32) -> Option<SocketPriority>,
+    ) -> Self {
+        self.transport_priority_mapping = Some(mapping);
+        self
+    }
+    pub fn fragment_size(mut self, size: u16) -> Self {
+        self.fragment_size = Some(size);
+        self
+    }
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = Some(size);
+        self
+    }
 }
 
 impl Default for ParticipantConfigBuilder {
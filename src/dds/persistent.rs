@@ -0,0 +1,443 @@
+//! Storage for PERSISTENT Durability
+//!
+//! DDS 1.4 spec, 2.2.3.4 DURABILITY
+//! > PERSISTENT: [The Service] stores the data in permanent storage (e.g., a file) so that they can outlive a system session.
+//!
+//! The DataWriter with `Durability::Persistent` keeps its history in a [`PersistentStorage`]
+//! according to the `DurabilityService` QoS policy. When a DataWriter with the same persistent id is created
+//! after the restart of the DomainParticipant, the history is reloaded and served to late-joining DataReaders.
+//!
+//! The history is keyed by the Topic name and the persistent id of the DataWriter
+//! (see `Publisher::create_persistent_datawriter`), so DataWriters of the same Topic don't share it.
+//! Processes which share the storage must give their DataWriters distinct persistent ids.
+//! A DataWriter created without a persistent id doesn't store its history.
+
+use crate::dds::qos::policy::{DurabilityService, HistoryQosKind, LENGTH_UNLIMITED};
+use crate::message::submessage::element::{SerializedPayload, Timestamp};
+use crate::rtps::cache::{CacheChange, ChangeKind, InstantHandle};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use awkernel_sync::{mcs::MCSNode, mutex::Mutex};
+use bytes::Bytes;
+use log::{error, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+/// default directory of [`FileStorage`] used by the DomainParticipant
+pub const DEFAULT_PERSISTENT_STORAGE_DIR: &str = ".umber_dds";
+
+/// Storage which keeps the history of DataWriters with `Durability::Persistent`
+///
+/// The history is stored per DataWriter as a sequence of opaque records.
+/// Implement this trait and pass it to `DomainParticipant::set_persistent_storage`
+/// to store the history somewhere other than files.
+pub trait PersistentStorage: Send {
+    /// load all records of the DataWriter identified by `key` in the order they were appended
+    fn load(&mut self, key: &str) -> io::Result<Vec<Vec<u8>>>;
+    /// append a record to the DataWriter identified by `key`
+    fn append(&mut self, key: &str, record: &[u8]) -> io::Result<()>;
+    /// replace all records of the DataWriter identified by `key`, which is used to compact the storage
+    fn replace(&mut self, key: &str, records: &[Vec<u8>]) -> io::Result<()>;
+}
+
+/// [`PersistentStorage`] which appends records to a log file per DataWriter in the directory
+///
+/// Each record is stored as its length (u32, little endian) followed by its contents.
+/// A record which is partially written (e.g. the process crashed while writing it) is ignored on loading.
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn log_path(&self, key: &str) -> PathBuf {
+        // key contains the Topic name, which may contain characters which can't be used in file names.
+        // They are percent-encoded, so that different keys never share the same file.
+        let mut file_name = String::with_capacity(key.len());
+        for b in key.bytes() {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+                file_name.push(b as char);
+            } else {
+                file_name.push_str(&format!("%{b:02X}"));
+            }
+        }
+        self.dir.join(format!("{file_name}.log"))
+    }
+
+    fn write_record<W: Write>(w: &mut W, record: &[u8]) -> io::Result<()> {
+        w.write_all(&(record.len() as u32).to_le_bytes())?;
+        w.write_all(record)
+    }
+}
+
+impl PersistentStorage for FileStorage {
+    fn load(&mut self, key: &str) -> io::Result<Vec<Vec<u8>>> {
+        let path = self.log_path(key);
+        let mut buf = Vec::new();
+        match File::open(&path) {
+            Ok(mut f) => f.read_to_end(&mut buf)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while pos + 4 <= buf.len() {
+            let len =
+                u32::from_le_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]]) as usize;
+            pos += 4;
+            if pos + len > buf.len() {
+                break;
+            }
+            records.push(buf[pos..pos + len].to_vec());
+            pos += len;
+        }
+        if pos != buf.len() {
+            warn!(
+                "FileStorage ignore partially written record in {}",
+                path.display()
+            );
+        }
+        Ok(records)
+    }
+
+    fn append(&mut self, key: &str, record: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path(key))?;
+        Self::write_record(&mut f, record)?;
+        f.sync_data()
+    }
+
+    fn replace(&mut self, key: &str, records: &[Vec<u8>]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.log_path(key);
+        let tmp_path = path.with_extension("log.tmp");
+        {
+            let mut f = File::create(&tmp_path)?;
+            for record in records {
+                Self::write_record(&mut f, record)?;
+            }
+            f.sync_data()?;
+        }
+        // rename is atomic, so the log is never lost even if the process crashes while compacting.
+        fs::rename(tmp_path, path)
+    }
+}
+
+/// a change of the DataWriter stored in the PersistentStorage
+#[derive(Clone, PartialEq)]
+pub(crate) struct PersistentSample {
+    pub kind: ChangeKind,
+    pub instance_handle: InstantHandle,
+    pub timestamp: Timestamp,
+    pub serialized_payload: Option<SerializedPayload>,
+}
+
+impl PersistentSample {
+    pub fn from_change(change: &CacheChange) -> Self {
+        Self {
+            kind: change.kind(),
+            instance_handle: change.instance_handle(),
+            timestamp: change.timestamp,
+            serialized_payload: change.data_value().cloned(),
+        }
+    }
+
+    // status_info(1), key_hash(16), timestamp(8), serialized_payload(rest)
+    fn to_record(&self) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.push(self.kind.status_info());
+        record.extend_from_slice(&self.instance_handle.key_hash());
+        record.extend_from_slice(&self.timestamp.seconds.to_le_bytes());
+        record.extend_from_slice(&self.timestamp.fraction.to_le_bytes());
+        if let Some(payload) = &self.serialized_payload {
+            record.extend_from_slice(&payload.to_bytes());
+        }
+        record
+    }
+
+    fn from_record(record: &[u8]) -> Option<Self> {
+        const HEADER_LEN: usize = 1 + 16 + 8;
+        if record.len() < HEADER_LEN {
+            return None;
+        }
+        let kind = ChangeKind::from_status_info(record[0]);
+        let mut key_hash = [0; 16];
+        key_hash.copy_from_slice(&record[1..17]);
+        let timestamp = Timestamp {
+            seconds: u32::from_le_bytes(record[17..21].try_into().unwrap()),
+            fraction: u32::from_le_bytes(record[21..25].try_into().unwrap()),
+        };
+        let serialized_payload = if record.len() > HEADER_LEN {
+            // representation_identifier(2), representation_options(2), value
+            if record.len() <= HEADER_LEN + 4 {
+                return None;
+            }
+            let payload = Bytes::copy_from_slice(&record[HEADER_LEN..]);
+            match SerializedPayload::from_bytes(&payload) {
+                Ok(p) => Some(p),
+                Err(_) => return None,
+            }
+        } else {
+            None
+        };
+        Some(Self {
+            kind,
+            instance_handle: InstantHandle::new(key_hash),
+            timestamp,
+            serialized_payload,
+        })
+    }
+}
+
+/// history of a DataWriter with `Durability::Persistent`
+///
+/// The samples are kept according to the history and resource limits of `DurabilityService`.
+pub(crate) struct PersistentHistory {
+    storage: Arc<Mutex<Box<dyn PersistentStorage>>>,
+    key: String,
+    durability_service: DurabilityService,
+    samples: Vec<PersistentSample>,
+    /// number of records in the storage which are no longer in `samples`
+    garbage: usize,
+}
+
+impl PersistentHistory {
+    pub fn load(
+        storage: Arc<Mutex<Box<dyn PersistentStorage>>>,
+        key: &str,
+        durability_service: DurabilityService,
+    ) -> Self {
+        let records = {
+            let mut node = MCSNode::new();
+            let mut storage = storage.lock(&mut node);
+            storage.load(key).unwrap_or_else(|e| {
+                error!(
+                    "failed to load persistent history of DataWriter {}: {}",
+                    key, e
+                );
+                Vec::new()
+            })
+        };
+        let samples = records
+            .iter()
+            .filter_map(|r| {
+                let sample = PersistentSample::from_record(r);
+                if sample.is_none() {
+                    warn!(
+                        "ignore broken record in persistent history of DataWriter {}",
+                        key
+                    );
+                }
+                sample
+            })
+            .collect();
+        let mut history = Self {
+            storage,
+            key: key.to_string(),
+            durability_service,
+            samples,
+            garbage: records.len(),
+        };
+        history.apply_limits();
+        history.garbage -= history.samples.len();
+        history.compact_if_needed();
+        history
+    }
+
+    pub fn samples(&self) -> &[PersistentSample] {
+        &self.samples
+    }
+
+    pub fn push(&mut self, sample: PersistentSample) {
+        let record = sample.to_record();
+        self.samples.push(sample);
+        self.garbage += self.apply_limits();
+        {
+            let mut node = MCSNode::new();
+            let mut storage = self.storage.lock(&mut node);
+            if let Err(e) = storage.append(&self.key, &record) {
+                error!(
+                    "failed to append persistent history of DataWriter {}: {}",
+                    self.key, e
+                );
+            }
+        }
+        self.compact_if_needed();
+    }
+
+    /// remove samples exceeding the history and resource limits of DurabilityService,
+    /// and return the number of removed samples
+    fn apply_limits(&mut self) -> usize {
+        let ds = &self.durability_service;
+        let mut per_instance = if ds.history_kind == HistoryQosKind::KeepLast {
+            ds.history_depth.max(1)
+        } else {
+            LENGTH_UNLIMITED
+        };
+        if ds.max_samples_per_instanse != LENGTH_UNLIMITED
+            && (per_instance == LENGTH_UNLIMITED || ds.max_samples_per_instanse < per_instance)
+        {
+            per_instance = ds.max_samples_per_instanse;
+        }
+        let before = self.samples.len();
+
+        // keep the latest samples of each instance, from the newest one
+        let mut instance_count: BTreeMap<InstantHandle, i32> = BTreeMap::new();
+        let mut keep = vec![true; self.samples.len()];
+        for (i, sample) in self.samples.iter().enumerate().rev() {
+            let count = instance_count.entry(sample.instance_handle).or_insert(0);
+            *count += 1;
+            if per_instance != LENGTH_UNLIMITED && *count > per_instance {
+                keep[i] = false;
+            }
+        }
+        // drop the instances which are not updated for the longest time
+        if ds.max_instance != LENGTH_UNLIMITED && instance_count.len() > ds.max_instance as usize {
+            let mut latest: Vec<(usize, InstantHandle)> = Vec::new();
+            for (i, sample) in self.samples.iter().enumerate().rev() {
+                if !latest.iter().any(|(_, ih)| *ih == sample.instance_handle) {
+                    latest.push((i, sample.instance_handle));
+                }
+            }
+            let to_drop: Vec<InstantHandle> = latest
+                .iter()
+                .skip(ds.max_instance as usize)
+                .map(|(_, ih)| *ih)
+                .collect();
+            for (i, sample) in self.samples.iter().enumerate() {
+                if to_drop.contains(&sample.instance_handle) {
+                    keep[i] = false;
+                }
+            }
+        }
+        let mut i = 0;
+        self.samples.retain(|_| {
+            i += 1;
+            keep[i - 1]
+        });
+        // drop the oldest samples
+        if ds.max_samples != LENGTH_UNLIMITED && self.samples.len() > ds.max_samples as usize {
+            let over = self.samples.len() - ds.max_samples as usize;
+            self.samples.drain(..over);
+        }
+        before - self.samples.len()
+    }
+
+    /// rewrite the storage when it holds more removed records than live ones
+    fn compact_if_needed(&mut self) {
+        if self.garbage == 0 || self.garbage < self.samples.len() {
+            return;
+        }
+        let records: Vec<Vec<u8>> = self.samples.iter().map(|s| s.to_record()).collect();
+        let mut node = MCSNode::new();
+        let mut storage = self.storage.lock(&mut node);
+        match storage.replace(&self.key, &records) {
+            Ok(_) => self.garbage = 0,
+            Err(e) => error!(
+                "failed to compact persistent history of DataWriter {}: {}",
+                self.key, e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dds::qos::policy::{History, ResourceLimits};
+    use core::time::Duration as CoreDuration;
+
+    fn sample(instance: u8, seconds: u32) -> PersistentSample {
+        PersistentSample {
+            kind: ChangeKind::Alive,
+            instance_handle: InstantHandle::new([instance; 16]),
+            timestamp: Timestamp {
+                seconds,
+                fraction: 0,
+            },
+            serialized_payload: Some(
+                SerializedPayload::from_bytes(&Bytes::from(vec![0, 1, 0, 0, seconds as u8]))
+                    .unwrap(),
+            ),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "umber_dds_persistent_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_file_storage() {
+        let dir = temp_dir("file_storage");
+        let mut storage = FileStorage::new(&dir);
+        assert!(storage.load("topic/a").unwrap().is_empty());
+        storage.append("topic/a", &[1, 2, 3]).unwrap();
+        storage.append("topic/a", &[]).unwrap();
+        assert_eq!(
+            storage.load("topic/a").unwrap(),
+            vec![vec![1, 2, 3], vec![]]
+        );
+
+        // partially written record is ignored
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(storage.log_path("topic/a"))
+            .unwrap();
+        f.write_all(&[10, 0, 0, 0, 1]).unwrap();
+        assert_eq!(
+            storage.load("topic/a").unwrap(),
+            vec![vec![1, 2, 3], vec![]]
+        );
+
+        storage.replace("topic/a", &[vec![4]]).unwrap();
+        assert_eq!(storage.load("topic/a").unwrap(), vec![vec![4]]);
+
+        // keys which differ only in the characters not allowed in file names don't collide
+        assert_ne!(storage.log_path("a/b_c"), storage.log_path("a_b/c"));
+        assert_ne!(storage.log_path("a/b"), storage.log_path("a%2Fb"));
+        storage.append("a/b_c", &[5]).unwrap();
+        assert!(storage.load("a_b/c").unwrap().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_persistent_history() {
+        let dir = temp_dir("history");
+        let storage: Arc<Mutex<Box<dyn PersistentStorage>>> =
+            Arc::new(Mutex::new(Box::new(FileStorage::new(&dir))));
+        let durability_service = DurabilityService::new(
+            CoreDuration::ZERO,
+            History::new(HistoryQosKind::KeepLast, 2),
+            ResourceLimits {
+                max_samples: LENGTH_UNLIMITED,
+                max_instance: 2,
+                max_samples_per_instanse: LENGTH_UNLIMITED,
+            },
+        );
+        let mut history = PersistentHistory::load(storage.clone(), "topic", durability_service);
+        for (instance, seconds) in [(1, 1), (1, 2), (2, 3), (1, 4), (3, 5), (2, 6)] {
+            history.push(sample(instance, seconds));
+        }
+        // only the latest 2 instances are kept, and instance 1 is dropped
+        // because it is not updated for the longest time.
+        assert!(history.samples() == [sample(3, 5), sample(2, 6)]);
+
+        // reload after restart
+        let reloaded = PersistentHistory::load(storage, "topic", durability_service);
+        assert!(reloaded.samples() == history.samples());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
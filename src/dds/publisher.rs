@@ -1,11 +1,14 @@
 use crate::dds::{
-    datawriter::DataWriter,
+    datawriter::{DataWriter, DataWriterHandle},
+    event_loop::{PartitionUpdate, PublisherCmd},
     participant::DomainParticipant,
+    persistent::PersistentHistory,
     qos::policy::*,
     qos::{DataWriterQos, DataWriterQosBuilder, DataWriterQosPolicies, PublisherQosPolicies},
     topic::Topic,
 };
 use crate::discovery::ParticipantMessageCmd;
+use crate::error::DdsError;
 use crate::message::submessage::element::Locator;
 use crate::network::net_util::{usertraffic_multicast_port, usertraffic_unicast_port};
 use crate::rtps::cache::{HistoryCache, HistoryCacheType};
@@ -14,7 +17,8 @@ use crate::structure::{Duration, EntityId, EntityKind, RTPSEntity, TopicKind, GU
 use crate::DdsData;
 use alloc::sync::Arc;
 use awkernel_sync::rwlock::RwLock;
-use log::info;
+use core::net::SocketAddrV4;
+use log::{info, warn};
 use mio_extras::channel as mio_channel;
 use speedy::{Endianness, Writable};
 
@@ -37,7 +41,14 @@ struct InnerPublisher {
     default_dw_qos: DataWriterQosPolicies,
     dp: DomainParticipant,
     create_writer_sender: mio_channel::SyncSender<WriterIngredients>,
+    update_partition_sender: mio_channel::SyncSender<PartitionUpdate>,
+    publisher_cmd_sender: mio_channel::SyncSender<PublisherCmd>,
     participant_msg_cmd_sender: mio_channel::SyncSender<ParticipantMessageCmd>,
+    // EntityIds of DataWriters created by this Publisher
+    writers: Vec<EntityId>,
+    writer_handles: Vec<DataWriterHandle>,
+    is_coherent_changes: bool,
+    is_suspended: bool,
 }
 
 impl Publisher {
@@ -46,6 +57,8 @@ impl Publisher {
         qos: PublisherQosPolicies,
         dp: DomainParticipant,
         create_writer_sender: mio_channel::SyncSender<WriterIngredients>,
+        update_partition_sender: mio_channel::SyncSender<PartitionUpdate>,
+        publisher_cmd_sender: mio_channel::SyncSender<PublisherCmd>,
         participant_msg_cmd_sender: mio_channel::SyncSender<ParticipantMessageCmd>,
     ) -> Self {
         let default_dw_qos = DataWriterQosBuilder::new().build();
@@ -56,6 +69,8 @@ impl Publisher {
                 default_dw_qos,
                 dp,
                 create_writer_sender,
+                update_partition_sender,
+                publisher_cmd_sender,
                 participant_msg_cmd_sender,
             ))),
         }
@@ -102,14 +117,75 @@ impl Publisher {
     /// ```ignore
     /// publisher.create_datawriter::<Hoge>(publisher.get_default_datawriter_qos(), &topic)
     /// ```
+    ///
+    /// A DataWriter with BestEffort Reliability sends each sample once to each unique locator
+    /// of the matched DataReaders, preferring their multicast locators.
     pub fn create_datawriter<W: Writable<Endianness> + DdsData>(
         &self,
         qos: DataWriterQos,
         topic: Topic,
     ) -> DataWriter<W> {
         self.inner
-            .read()
-            .create_datawriter(qos, topic, self.clone())
+            .write()
+            .create_datawriter(qos, topic, self.clone(), Vec::new(), None)
+    }
+
+    /// Create a DataWriter whose `Durability::Persistent` history is identified by `persistent_id`
+    ///
+    /// The history of a DataWriter with `Durability::Persistent` is stored per Topic and persistent id,
+    /// and reloaded by the DataWriter created with the same Topic and persistent id after the restart.
+    /// A DataWriter created by [`Self::create_datawriter`] has no persistent id, so its history is not stored.
+    /// DataWriters of the same Topic, including those in other processes sharing the storage,
+    /// must use distinct persistent ids, otherwise their histories are mixed.
+    ///
+    /// See [`Self::create_datawriter`] for a note of qos.
+    pub fn create_persistent_datawriter<W: Writable<Endianness> + DdsData>(
+        &self,
+        qos: DataWriterQos,
+        topic: Topic,
+        persistent_id: &str,
+    ) -> DataWriter<W> {
+        self.inner.write().create_datawriter(
+            qos,
+            topic,
+            self.clone(),
+            Vec::new(),
+            Some(persistent_id.to_string()),
+        )
+    }
+
+    /// Create a DataWriter which also sends samples to the fixed `locators` without discovery
+    ///
+    /// rtps 2.3 spec, 8.4.7.5 RTPS ReaderLocator
+    /// The DataWriter works as a Best-Effort StatelessWriter, which sends each sample once to each locator.
+    /// This is suitable for a stream published to a multicast group with many subscribers.
+    ///
+    /// The DataWriter is still announced by SEDP,
+    /// because DataReaders of this implementation accept DATA only from matched DataWriters.
+    ///
+    /// See [`Self::create_datawriter`] for a note of qos.
+    ///
+    /// Return `DdsError::InconsistentPolicy` if the Reliability of the DataWriter is not BestEffort.
+    pub fn create_datawriter_with_locators<W: Writable<Endianness> + DdsData>(
+        &self,
+        qos: DataWriterQos,
+        topic: Topic,
+        locators: Vec<SocketAddrV4>,
+    ) -> Result<DataWriter<W>, DdsError> {
+        let mut inner = self.inner.write();
+        // checked before the entity is created, so that nothing is left on error
+        if inner.datawriter_qos(qos.clone(), &topic).reliability().kind
+            != ReliabilityQosKind::BestEffort
+        {
+            return Err(DdsError::InconsistentPolicy(
+                "DataWriter with fixed locators must be BestEffort".to_string(),
+            ));
+        }
+        let fixed_locators = locators
+            .iter()
+            .map(|addr| Locator::new_from_ipv4(addr.port() as u32, addr.ip().octets()))
+            .collect();
+        Ok(inner.create_datawriter(qos, topic, self.clone(), fixed_locators, None))
     }
 
     /// Built-in endpoints must be registered with the EventLoop before its loop
@@ -130,16 +206,69 @@ impl Publisher {
     ) -> (DataWriter<W>, WriterIngredients) {
         self.inner
             .read()
-            .create_datawriter_with_entityid(qos, topic, self.clone(), entity_id)
+            .create_datawriter_with_entityid(qos, topic, self.clone(), entity_id, None)
     }
 
     pub fn get_qos(&self) -> PublisherQosPolicies {
         self.inner.read().get_qos()
     }
+    /// If the Partition is changed, matching of the DataWriters created by this Publisher
+    /// with remote DataReaders is re-evaluated.
     pub fn set_qos(&mut self, qos: PublisherQosPolicies) {
         self.inner.write().set_qos(qos);
     }
 
+    /// Suspend sending data written by the DataWriters created by this Publisher
+    ///
+    /// DDS 1.4 spec, 2.2.2.4.1.8 suspend_publications
+    /// > This operation indicates to the Service that the application is about to make multiple modifications using DataWriter
+    /// > objects belonging to the Publisher.
+    ///
+    /// While the publications are suspended, written data is kept in the HistoryCache of each DataWriter.
+    /// If the HistoryCache of a Reliable DataWriter reaches ResourceLimits, `DataWriter::write` returns
+    /// `DdsError::OutOfResources` instead of blocking, because nothing is acknowledged until the publications are resumed.
+    pub fn suspend_publications(&self) {
+        self.inner.write().suspend_publications();
+    }
+
+    /// Send the data written while the publications are suspended
+    ///
+    /// DDS 1.4 spec, 2.2.2.4.1.9 resume_publications
+    /// > This operation indicates to the Service that the application has completed the multiple changes initiated by the
+    /// > previous suspend_publications.
+    ///
+    /// The DATA submessages of the DataWriters destined for the same locator are packed into as few RTPS Messages as possible.
+    pub fn resume_publications(&self) {
+        self.inner.write().resume_publications();
+    }
+
+    pub(crate) fn is_suspended(&self) -> bool {
+        self.inner.read().is_suspended
+    }
+
+    /// Start a coherent set
+    ///
+    /// DDS 1.4 spec, 2.2.2.4.1.10 begin_coherent_changes
+    /// > This operation requests that the application will begin a 'coherent set' of modifications using DataWriter objects
+    /// > attached to the Publisher. The 'coherent set' will be completed by a matching call to end_coherent_changes.
+    ///
+    /// The DataReaders whose Subscriber has `Presentation::coherent_access` receive
+    /// the changes in a coherent set all together when the whole set arrives.
+    pub fn begin_coherent_changes(&self) {
+        self.inner.write().begin_coherent_changes();
+    }
+
+    /// End the coherent set started by [`Self::begin_coherent_changes`]
+    ///
+    /// DDS 1.4 spec, 2.2.2.4.1.11 end_coherent_changes
+    pub fn end_coherent_changes(&self) {
+        self.inner.write().end_coherent_changes();
+    }
+
+    pub(crate) fn is_coherent_changes(&self) -> bool {
+        self.inner.read().is_coherent_changes
+    }
+
     pub fn domain_participant(&self) -> DomainParticipant {
         self.inner.read().dp.clone()
     }
@@ -159,6 +288,8 @@ impl InnerPublisher {
         default_dw_qos: DataWriterQosPolicies,
         dp: DomainParticipant,
         create_writer_sender: mio_channel::SyncSender<WriterIngredients>,
+        update_partition_sender: mio_channel::SyncSender<PartitionUpdate>,
+        publisher_cmd_sender: mio_channel::SyncSender<PublisherCmd>,
         participant_msg_cmd_sender: mio_channel::SyncSender<ParticipantMessageCmd>,
     ) -> Self {
         info!("created new Publisher {}", guid);
@@ -168,7 +299,13 @@ impl InnerPublisher {
             default_dw_qos,
             dp,
             create_writer_sender,
+            update_partition_sender,
+            publisher_cmd_sender,
             participant_msg_cmd_sender,
+            writers: Vec::new(),
+            writer_handles: Vec::new(),
+            is_coherent_changes: false,
+            is_suspended: false,
         }
     }
 
@@ -177,24 +314,40 @@ impl InnerPublisher {
     }
 
     fn set_qos(&mut self, qos: PublisherQosPolicies) {
+        let partition_changed = self.qos.partition() != qos.partition();
         self.qos = qos;
+        if partition_changed && !self.writers.is_empty() {
+            self.update_partition_sender
+                .send(PartitionUpdate::Writers(
+                    self.writers.clone(),
+                    self.qos.partition(),
+                ))
+                .expect("failed to send data via channel 'update_partition_sender'");
+        }
     }
 
     fn create_datawriter<W: Writable<Endianness> + DdsData>(
-        &self,
+        &mut self,
         qos: DataWriterQos,
         topic: Topic,
         outter: Publisher,
+        fixed_locators: Vec<Locator>,
+        persistent_id: Option<String>,
     ) -> DataWriter<W> {
         let entity_kind = match topic.kind() {
             TopicKind::WithKey => EntityKind::WRITER_WITH_KEY_USER_DEFIND,
             TopicKind::NoKey => EntityKind::WRITER_NO_KEY_USER_DEFIND,
         };
         let entity_id = EntityId::new_with_entity_kind(self.dp.gen_entity_key(), entity_kind);
-        let (dw, w_ing) = self.create_datawriter_with_entityid(qos, topic, outter, entity_id);
+        let (mut dw, mut w_ing) =
+            self.create_datawriter_with_entityid(qos, topic, outter, entity_id, persistent_id);
+        w_ing.fixed_locators = fixed_locators;
+        self.writers.push(entity_id);
+        self.writer_handles.push(dw.handle());
         self.create_writer_sender
             .send(w_ing)
             .expect("failed to send data via channel 'create_writer_sender'");
+        dw.restore_persistent_history();
         dw
     }
 
@@ -205,17 +358,12 @@ impl InnerPublisher {
         outter: Publisher,
         entity_id: EntityId,
     ) -> (DataWriter<W>, WriterIngredients) {
-        self.create_datawriter_with_entityid(qos, topic, outter, entity_id)
+        self.create_datawriter_with_entityid(qos, topic, outter, entity_id, None)
     }
 
-    fn create_datawriter_with_entityid<W: Writable<Endianness> + DdsData>(
-        &self,
-        qos: DataWriterQos,
-        topic: Topic,
-        outter: Publisher,
-        entity_id: EntityId,
-    ) -> (DataWriter<W>, WriterIngredients) {
-        let dw_qos = match qos {
+    /// DataWriterQosPolicies of the DataWriter created with `qos`
+    fn datawriter_qos(&self, qos: DataWriterQos, topic: &Topic) -> DataWriterQosPolicies {
+        match qos {
             // DDS 1.4 spec, 2.2.2.4.1.5 create_datawriter
             // > The special value DATAWRITER_QOS_DEFAULT can be used to indicate that the DataWriter should be created with the
             // default DataWriter QoS set in the factory. The use of this value is equivalent to the application obtaining the default
@@ -234,7 +382,18 @@ impl InnerPublisher {
                 dw_qos.combine(*q);
                 dw_qos
             }
-        };
+        }
+    }
+
+    fn create_datawriter_with_entityid<W: Writable<Endianness> + DdsData>(
+        &self,
+        qos: DataWriterQos,
+        topic: Topic,
+        outter: Publisher,
+        entity_id: EntityId,
+        persistent_id: Option<String>,
+    ) -> (DataWriter<W>, WriterIngredients) {
+        let dw_qos = self.datawriter_qos(qos, &topic);
         let (writer_state_notifier, writer_state_receiver) =
             mio_channel::channel::<DataWriterStatusChanged>();
         let (writer_command_sender, writer_command_receiver) =
@@ -253,6 +412,27 @@ impl InnerPublisher {
             nics,
         );
         let guid = GUID::new(self.dp.guid_prefix(), entity_id);
+        let is_persistent =
+            dw_qos.durability() == Durability::Persistent && !entity_id.is_builtin();
+        let persistent_history = match persistent_id {
+            // the history is kept per DataWriter, so that DataWriters of the same Topic
+            // don't restore or compact the samples of each other.
+            Some(persistent_id) if is_persistent => Some(PersistentHistory::load(
+                self.dp.persistent_storage(),
+                &format!("{}/{}", topic.name(), persistent_id),
+                dw_qos.durability_service(),
+            )),
+            // an id derived from the order of creation would be shared by unrelated processes
+            // using the same storage, so the history is not stored without an explicit id.
+            None if is_persistent => {
+                warn!(
+                    "DataWriter with Durability::Persistent created without persistent id, its history is not stored. Use Publisher::create_persistent_datawriter\n\tWriter: {}",
+                    guid
+                );
+                None
+            }
+            _ => None,
+        };
         let writer_ing = WriterIngredients {
             guid,
             reliability_level,
@@ -266,9 +446,17 @@ impl InnerPublisher {
             nack_response_delay: self.dp.get_config().nack_response_delay.into(),
             nack_suppression_duration: Duration::ZERO,
             data_max_size_serialized: 0,
+            fragment_size: self.dp.get_config().fragment_size,
+            max_message_size: self.dp.get_config().max_message_size,
+            fixed_locators: Vec::new(),
             whc: history_cache.clone(),
             topic: topic.clone(),
             qos: dw_qos.clone(),
+            partition: self.qos.partition(),
+            // DDS 1.4 spec, 2.2.3.15 TRANSPORT_PRIORITY
+            socket_priority: (self.dp.get_config().transport_priority_mapping)(
+                dw_qos.transport_priority().value,
+            ),
             writer_command_receiver,
             writer_state_notifier,
             participant_msg_cmd_sender: self.participant_msg_cmd_sender.clone(),
@@ -282,6 +470,7 @@ impl InnerPublisher {
                 outter,
                 history_cache,
                 writer_state_receiver,
+                persistent_history,
             ),
             writer_ing,
         )
@@ -295,9 +484,47 @@ impl InnerPublisher {
     fn set_default_datawriter_qos(&mut self, qos: DataWriterQosPolicies) {
         self.default_dw_qos = qos;
     }
-    fn suspend_publications(&self) {}
-    fn resume_publications(&self) {}
-    fn begin_coherent_change(&self) {}
-    fn end_coherent_changes(&self) {}
+    fn suspend_publications(&mut self) {
+        if self.is_suspended {
+            warn!(
+                "Publisher::suspend_publications called while suspended\n\tPublisher: {}",
+                self.guid
+            );
+        }
+        self.is_suspended = true;
+    }
+    fn resume_publications(&mut self) {
+        if !self.is_suspended {
+            warn!("Publisher::resume_publications called without suspend_publications\n\tPublisher: {}", self.guid);
+            return;
+        }
+        self.is_suspended = false;
+        if !self.writers.is_empty() {
+            self.publisher_cmd_sender
+                .send(PublisherCmd::ResumePublications(self.writers.clone()))
+                .expect("failed to send data via channel 'publisher_cmd_sender'");
+        }
+    }
+    fn begin_coherent_changes(&mut self) {
+        if self.is_coherent_changes {
+            warn!(
+                "Publisher::begin_coherent_changes called in coherent set\n\tPublisher: {}",
+                self.guid
+            );
+        }
+        self.is_coherent_changes = true;
+    }
+    fn end_coherent_changes(&mut self) {
+        if !self.is_coherent_changes {
+            warn!("Publisher::end_coherent_changes called without begin_coherent_changes\n\tPublisher: {}", self.guid);
+            return;
+        }
+        self.is_coherent_changes = false;
+        for writer in &self.writer_handles {
+            if writer.end_coherent_set() && !self.is_suspended {
+                writer.send_changes();
+            }
+        }
+    }
     fn wait_for_acknowledgments(&self) {}
 }
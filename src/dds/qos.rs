@@ -771,12 +771,28 @@ pub mod policy {
 
     #[derive(Clone, Copy, Debug, PartialEq)]
     pub struct DurabilityService {
-        service_cleanup_delay: Duration,
-        history_kind: HistoryQosKind,
-        history_depth: i32,
-        max_samples: i32,
-        max_instance: i32,
-        max_samples_per_instanse: i32,
+        pub(crate) service_cleanup_delay: Duration,
+        pub(crate) history_kind: HistoryQosKind,
+        pub(crate) history_depth: i32,
+        pub(crate) max_samples: i32,
+        pub(crate) max_instance: i32,
+        pub(crate) max_samples_per_instanse: i32,
+    }
+    impl DurabilityService {
+        pub fn new(
+            service_cleanup_delay: CoreDuration,
+            history: History,
+            resource_limits: ResourceLimits,
+        ) -> Self {
+            Self {
+                service_cleanup_delay: service_cleanup_delay.into(),
+                history_kind: history.kind,
+                history_depth: history.depth,
+                max_samples: resource_limits.max_samples,
+                max_instance: resource_limits.max_instance,
+                max_samples_per_instanse: resource_limits.max_samples_per_instanse,
+            }
+        }
     }
     impl Default for DurabilityService {
         fn default() -> Self {
@@ -829,17 +845,21 @@ pub mod policy {
     /// if ReliabilityQoS is BestEffort, this QoS policy dosen't affect behavior of Umber DDS.
     /// This is same to Cyclone DDS.
     ///
-    /// Umber DDS don't support optional Durability QoS value "Transient" and "Persistent".
-    /// So, this config dosen't affect behavior.
+    /// "Transient" is served by the DataWriter itself like "TransientLocal" while the DataWriter is alive.
     pub enum Durability {
         #[default]
         /// late-joining readers can't receive data which sent before the reader join the network.
         Volatile = 0,
-        /// late-joining readers can receive latest one data which sent before the
+        /// late-joining readers can receive the latest data of each instance which sent before the
         /// reader join the network if ReliabilityQoS is set to Reliable.
         TransientLocal = 1,
-        // Transient = 2, // DDS spec say Support this is optional
-        // Persistent = 3, // DDS spec say Support this is optional
+        /// DDS spec say Support this is optional
+        Transient = 2,
+        /// In addition to "TransientLocal", the history is kept in the PersistentStorage
+        /// according to DurabilityService, and served after the restart of the DomainParticipant.
+        ///
+        /// DDS spec say Support this is optional
+        Persistent = 3,
     }
     impl Durability {
         /// offered is Publisher side QoS value
@@ -855,6 +875,8 @@ pub mod policy {
             Ok(match val {
                 0 => Self::Volatile,
                 1 => Self::TransientLocal,
+                2 => Self::Transient,
+                3 => Self::Persistent,
                 _n => {
                     return Err(
                         speedy::private::error_invalid_enum_variant::<speedy::Error>().into(),
@@ -878,7 +900,7 @@ pub mod policy {
         pub(crate) ordered_access: bool,
     }
     impl Presentation {
-        pub fn _new(
+        pub fn new(
             access_scope: PresentationQosAccessScopeKind,
             coherent_access: bool,
             ordered_access: bool,
@@ -1481,6 +1503,131 @@ pub mod policy {
             }
             len
         }
+
+        /// Check whether two Partitions have at least one name in common.
+        ///
+        /// DDS 1.4 spec, 2.2.3.13 PARTITION
+        /// An empty list of names is equivalent to the default partition `[""]`.
+        /// Names may contain fnmatch-style wildcards (`*`, `?`, `[...]`),
+        /// but two names which both contain wildcards never match each other.
+        pub fn is_match(&self, other: &Self) -> bool {
+            const DEFAULT_NAME: [String; 1] = [String::new()];
+            let self_names = if self.name.is_empty() {
+                &DEFAULT_NAME[..]
+            } else {
+                &self.name[..]
+            };
+            let other_names = if other.name.is_empty() {
+                &DEFAULT_NAME[..]
+            } else {
+                &other.name[..]
+            };
+            self_names.iter().any(|s| {
+                other_names
+                    .iter()
+                    .any(|o| match (Self::has_wildcard(s), Self::has_wildcard(o)) {
+                        (false, false) => s == o,
+                        (true, false) => fnmatch(s.as_bytes(), o.as_bytes()),
+                        (false, true) => fnmatch(o.as_bytes(), s.as_bytes()),
+                        (true, true) => false,
+                    })
+            })
+        }
+
+        fn has_wildcard(name: &str) -> bool {
+            name.contains(['*', '?', '['])
+        }
+    }
+
+    /// POSIX fnmatch without flags: `*`, `?`, `[...]` (`!` or `^` for negation, `a-z` for range)
+    /// and `\` to escape the next character.
+    fn fnmatch(pattern: &[u8], name: &[u8]) -> bool {
+        let (mut p, mut n) = (0, 0);
+        // position of the last `*` in pattern and the position in name where it started matching
+        let mut backtrack: Option<(usize, usize)> = None;
+        while n < name.len() {
+            let matched = if p < pattern.len() {
+                match pattern[p] {
+                    b'*' => {
+                        p += 1;
+                        backtrack = Some((p, n));
+                        continue;
+                    }
+                    b'?' => Some(p + 1),
+                    b'[' => match match_bracket(&pattern[p..], name[n]) {
+                        Some((len, true)) => Some(p + len),
+                        Some((_, false)) => None,
+                        // unterminated `[` is treated as an ordinary character
+                        None if name[n] == b'[' => Some(p + 1),
+                        None => None,
+                    },
+                    b'\\' if p + 1 < pattern.len() => {
+                        if pattern[p + 1] == name[n] {
+                            Some(p + 2)
+                        } else {
+                            None
+                        }
+                    }
+                    c => {
+                        if c == name[n] {
+                            Some(p + 1)
+                        } else {
+                            None
+                        }
+                    }
+                }
+            } else {
+                None
+            };
+            match matched {
+                Some(next_p) => {
+                    p = next_p;
+                    n += 1;
+                }
+                None => match backtrack {
+                    Some((star_p, star_n)) => {
+                        p = star_p;
+                        n = star_n + 1;
+                        backtrack = Some((star_p, star_n + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|c| *c == b'*')
+    }
+
+    /// Match `c` against the bracket expression at the head of `pattern`.
+    /// Return the length of the bracket expression and whether `c` matches it,
+    /// or None if the bracket expression is unterminated.
+    fn match_bracket(pattern: &[u8], c: u8) -> Option<(usize, bool)> {
+        let mut i = 1;
+        let negate = matches!(pattern.get(i), Some(b'!') | Some(b'^'));
+        if negate {
+            i += 1;
+        }
+        let mut found = false;
+        let mut first = true;
+        loop {
+            let lo = *pattern.get(i)?;
+            if lo == b']' && !first {
+                break;
+            }
+            first = false;
+            if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|h| *h != b']') {
+                let hi = pattern[i + 2];
+                if lo <= c && c <= hi {
+                    found = true;
+                }
+                i += 3;
+            } else {
+                if lo == c {
+                    found = true;
+                }
+                i += 1;
+            }
+        }
+        Some((i + 1, found != negate))
     }
     impl Default for Partition {
         fn default() -> Self {
@@ -1635,6 +1782,17 @@ pub mod policy {
         pub(crate) autopurge_nowriter_samples_delay: Duration,
         pub(crate) autopurge_dispose_samples_delay: Duration,
     }
+    impl ReaderDataLifecycle {
+        pub fn new(
+            autopurge_nowriter_samples_delay: CoreDuration,
+            autopurge_dispose_samples_delay: CoreDuration,
+        ) -> Self {
+            Self {
+                autopurge_nowriter_samples_delay: autopurge_nowriter_samples_delay.into(),
+                autopurge_dispose_samples_delay: autopurge_dispose_samples_delay.into(),
+            }
+        }
+    }
     impl Default for ReaderDataLifecycle {
         fn default() -> Self {
             Self {
@@ -1796,4 +1954,22 @@ mod test {
         assert_eq!(presentation.coherent_access, deserialized.coherent_access);
         assert_eq!(presentation.ordered_access, deserialized.ordered_access);
     }
+
+    #[test]
+    fn test_partition_match() {
+        let partition = |names: &[&str]| policy::Partition {
+            name: names.iter().map(|n| n.to_string()).collect(),
+        };
+        // empty list is equivalent to the default partition
+        assert!(partition(&[]).is_match(&policy::Partition::default()));
+        assert!(!partition(&[]).is_match(&partition(&["vehicle1"])));
+        assert!(partition(&["vehicle1", "common"]).is_match(&partition(&["common"])));
+        assert!(partition(&["vehicle*"]).is_match(&partition(&["vehicle1"])));
+        assert!(partition(&["vehicle?"]).is_match(&partition(&["vehicle2"])));
+        assert!(!partition(&["vehicle?"]).is_match(&partition(&["vehicle10"])));
+        assert!(partition(&["vehicle[0-3]"]).is_match(&partition(&["vehicle3"])));
+        assert!(!partition(&["vehicle[!0-3]"]).is_match(&partition(&["vehicle3"])));
+        // two wildcard names never match each other
+        assert!(!partition(&["vehicle*"]).is_match(&partition(&["vehicle?"])));
+    }
 }
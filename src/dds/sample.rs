@@ -1,5 +1,6 @@
 use crate::message::submessage::element::Timestamp;
-use crate::DdsData;
+use crate::rtps::cache::InstantHandle;
+use crate::{DdsData, KeyHash};
 use speedy::{Endianness, Readable};
 
 pub struct DataSample<R: for<'a> Readable<'a, Endianness> + DdsData> {
@@ -12,6 +13,9 @@ impl<R: for<'a> Readable<'a, Endianness> + DdsData> DataSample<R> {
         Self { data, sample_info }
     }
 
+    /// the received data
+    ///
+    /// If `SampleInfo::valid_data` is false, only the key fields are valid.
     pub fn data(&self) -> &R {
         &self.data
     }
@@ -23,12 +27,36 @@ impl<R: for<'a> Readable<'a, Endianness> + DdsData> DataSample<R> {
 
 pub struct SampleInfo {
     pub source_timestamp: Timestamp,
+    /// DDS 1.4 spec, 2.2.2.5.5 SampleInfo Class
+    /// > The valid_data flag indicates whether the DataSample contains data or else it is only used to communicate
+    /// > a change in the instance_state of the instance.
+    ///
+    /// A sample disposing or unregistering the instance has valid_data false and only the key fields are set.
+    pub valid_data: bool,
+    instance_handle: InstantHandle,
 }
 
 impl SampleInfo {
-    pub(crate) fn new(source_ts: Timestamp) -> Self {
+    pub(crate) fn new(
+        source_ts: Timestamp,
+        valid_data: bool,
+        instance_handle: InstantHandle,
+    ) -> Self {
         Self {
             source_timestamp: source_ts,
+            valid_data,
+            instance_handle,
+        }
+    }
+
+    /// KeyHash of the instance the sample belongs to
+    ///
+    /// Returns None if the Topic has no key or the key of the sample is unknown.
+    pub fn key_hash(&self) -> Option<KeyHash> {
+        if self.instance_handle.is_nil() {
+            None
+        } else {
+            Some(KeyHash::new(&self.instance_handle.key_hash()))
         }
     }
 }
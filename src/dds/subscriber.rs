@@ -1,5 +1,6 @@
 use crate::dds::{
-    datareader::DataReader,
+    datareader::{payload_key_hash, DataReader},
+    event_loop::PartitionUpdate,
     participant::DomainParticipant,
     qos::{DataReaderQos, DataReaderQosBuilder, DataReaderQosPolicies, SubscriberQosPolicies},
     topic::Topic,
@@ -32,6 +33,7 @@ impl Subscriber {
         qos: SubscriberQosPolicies,
         dp: DomainParticipant,
         create_reader_sender: mio_channel::SyncSender<ReaderIngredients>,
+        update_partition_sender: mio_channel::SyncSender<PartitionUpdate>,
     ) -> Self {
         let default_dr_qos = DataReaderQosBuilder::new().build();
         Self {
@@ -41,6 +43,7 @@ impl Subscriber {
                 default_dr_qos,
                 dp,
                 create_reader_sender,
+                update_partition_sender,
             ))),
         }
     }
@@ -92,7 +95,7 @@ impl Subscriber {
         topic: Topic,
     ) -> DataReader<R> {
         self.inner
-            .read()
+            .write()
             .create_datareader(qos, topic, self.clone())
     }
 
@@ -120,6 +123,8 @@ impl Subscriber {
     pub fn get_qos(&self) -> SubscriberQosPolicies {
         self.inner.read().get_qos()
     }
+    /// If the Partition is changed, matching of the DataReaders created by this Subscriber
+    /// with remote DataWriters is re-evaluated.
     pub fn set_qos(&mut self, qos: SubscriberQosPolicies) {
         self.inner.write().set_qos(qos)
     }
@@ -129,6 +134,26 @@ impl Subscriber {
     pub fn set_default_datareader_qos(&mut self, qos: DataReaderQosPolicies) {
         self.inner.write().set_default_datareader_qos(qos)
     }
+
+    /// Start accessing the DataReaders created by this Subscriber
+    ///
+    /// DDS 1.4 spec, 2.2.2.5.2.8 begin_access
+    /// > This operation indicates that the application is about to access the data samples in any of the DataReader objects
+    /// > attached to the Subscriber.
+    ///
+    /// Until [`Self::end_access`] is called, no new sample becomes available from the DataReaders,
+    /// so the application can take samples of coherent sets from several DataReaders consistently.
+    /// Samples arriving in the meantime become available after `end_access`.
+    pub fn begin_access(&self) {
+        self.inner.read().begin_access()
+    }
+
+    /// End accessing the DataReaders started by [`Self::begin_access`]
+    ///
+    /// DDS 1.4 spec, 2.2.2.5.2.9 end_access
+    pub fn end_access(&self) {
+        self.inner.read().end_access()
+    }
 }
 
 #[allow(dead_code)]
@@ -142,6 +167,10 @@ struct InnerSubscriber {
     default_dr_qos: DataReaderQosPolicies,
     dp: DomainParticipant,
     create_reader_sender: mio_channel::SyncSender<ReaderIngredients>,
+    update_partition_sender: mio_channel::SyncSender<PartitionUpdate>,
+    // EntityIds of DataReaders created by this Subscriber
+    readers: Vec<EntityId>,
+    reader_caches: Vec<Arc<RwLock<HistoryCache>>>,
 }
 
 impl InnerSubscriber {
@@ -151,6 +180,7 @@ impl InnerSubscriber {
         default_dr_qos: DataReaderQosPolicies,
         dp: DomainParticipant,
         create_reader_sender: mio_channel::SyncSender<ReaderIngredients>,
+        update_partition_sender: mio_channel::SyncSender<PartitionUpdate>,
     ) -> Self {
         info!("created new Subscriber {}", guid);
         Self {
@@ -159,6 +189,9 @@ impl InnerSubscriber {
             default_dr_qos,
             dp,
             create_reader_sender,
+            update_partition_sender,
+            readers: Vec::new(),
+            reader_caches: Vec::new(),
         }
     }
 
@@ -167,11 +200,20 @@ impl InnerSubscriber {
     }
 
     fn set_qos(&mut self, qos: SubscriberQosPolicies) {
-        self.qos = qos
+        let partition_changed = self.qos.partition() != qos.partition();
+        self.qos = qos;
+        if partition_changed && !self.readers.is_empty() {
+            self.update_partition_sender
+                .send(PartitionUpdate::Readers(
+                    self.readers.clone(),
+                    self.qos.partition(),
+                ))
+                .expect("failed to send data via channel 'update_partition_sender'");
+        }
     }
 
     fn create_datareader<R: for<'a> Readable<'a, Endianness> + DdsData>(
-        &self,
+        &mut self,
         qos: DataReaderQos,
         topic: Topic,
         subscriber: Subscriber,
@@ -182,6 +224,8 @@ impl InnerSubscriber {
         };
         let entity_id = EntityId::new_with_entity_kind(self.dp.gen_entity_key(), entity_kind);
         let (dr, r_ing) = self.create_datareader_with_entityid(qos, topic, subscriber, entity_id);
+        self.readers.push(entity_id);
+        self.reader_caches.push(r_ing.rhc.clone());
         self.create_reader_sender
             .send(r_ing)
             .expect("failed to send data via channel 'create_reader_sender'");
@@ -250,7 +294,10 @@ impl InnerSubscriber {
             rhc: history_cache.clone(),
             topic: topic.clone(),
             qos: dr_qos.clone(),
+            partition: self.qos.partition(),
+            presentation: self.qos.presentation(),
             reader_state_notifier,
+            payload_key_hash: payload_key_hash::<R>,
         };
         (
             DataReader::<R>::new(
@@ -265,6 +312,18 @@ impl InnerSubscriber {
         )
     }
 
+    fn begin_access(&self) {
+        for rhc in &self.reader_caches {
+            rhc.write().begin_access();
+        }
+    }
+
+    fn end_access(&self) {
+        for rhc in &self.reader_caches {
+            rhc.write().end_access();
+        }
+    }
+
     fn get_default_datareader_qos(&self) -> DataReaderQosPolicies {
         self.default_dr_qos.clone()
     }
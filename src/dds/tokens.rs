@@ -8,7 +8,7 @@ pub enum TokenDec {
 
 impl TokenDec {
     pub fn decode(token: Token) -> Self {
-        if Token(0x40) <= token && Token(0x60) > token {
+        if Token(0x40) <= token && Token(0x80) > token {
             Self::ReservedToken(token)
         } else {
             let n: usize = token.into();
@@ -51,3 +51,9 @@ pub const CHECK_MANUAL_LIVELINESS_TIMER: Token = Token(PTB + 0x1C);
 pub const WRITER_DEADLINE_TIMER: Token = Token(PTB + 0x1D);
 pub const READER_DEADLINE_TIMER: Token = Token(PTB + 0x1E);
 pub const READER_LIFESPAN_TIMER: Token = Token(PTB + 0x1F);
+pub const UPDATE_PARTITION_TOKEN: Token = Token(PTB + 0x20);
+pub const READER_AUTOPURGE_TIMER: Token = Token(PTB + 0x21);
+pub const WRITER_LIFESPAN_TIMER: Token = Token(PTB + 0x22);
+pub const WRITER_LATENCY_BUDGET_TIMER: Token = Token(PTB + 0x23);
+pub const READER_FRAGMENT_TIMER: Token = Token(PTB + 0x24);
+pub const PUBLISHER_CMD_TOKEN: Token = Token(PTB + 0x25);
@@ -0,0 +1,101 @@
+use crate::dds::key::{DdsData, KeyHash};
+use speedy::{Context, Endianness, Readable, Reader, Writable, Writer};
+
+/// Data of a Topic whose type is not known at compile time
+///
+/// UntypedData keeps the serialized value of a sample as it is, so that it can be forwarded
+/// without deserializing it. This is used by a component like a durability service
+/// which handles samples of any type.
+///
+/// Use UntypedData with a Topic created by [`crate::dds::DomainParticipant::create_untyped_topic`],
+/// because the type name and the kind of the Topic can not be derived from UntypedData.
+///
+/// When UntypedData is taken from a DataReader, the key of the sample is not set.
+/// Set it from `SampleInfo::key_hash` with [`UntypedData::set_key_hash`] before forwarding it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UntypedData {
+    endianness: Endianness,
+    value: Vec<u8>,
+    key_hash: Option<[u8; 16]>,
+}
+
+impl UntypedData {
+    pub fn new(endianness: Endianness, value: Vec<u8>, key_hash: Option<KeyHash>) -> Self {
+        Self {
+            endianness,
+            value,
+            key_hash: key_hash.map(|kh| kh.value()),
+        }
+    }
+
+    /// endianness of the CDR encapsulation of the value
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// serialized value without the encapsulation header
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn key_hash(&self) -> Option<KeyHash> {
+        self.key_hash.map(|kh| KeyHash::new(&kh))
+    }
+
+    pub fn set_key_hash(&mut self, key_hash: Option<KeyHash>) {
+        self.key_hash = key_hash.map(|kh| kh.value());
+    }
+}
+
+impl DdsData for UntypedData {
+    fn gen_key(&self) -> Option<KeyHash> {
+        self.key_hash()
+    }
+    /// UntypedData has no type name.
+    /// The type name is given to the Topic by `DomainParticipant::create_untyped_topic`.
+    fn type_name() -> String {
+        String::new()
+    }
+    fn is_with_key() -> bool {
+        false
+    }
+}
+
+impl<'a, C: Context> Readable<'a, C> for UntypedData {
+    fn read_from<R: Reader<'a, C>>(reader: &mut R) -> Result<Self, C::Error> {
+        let endianness = reader.context().endianness();
+        let value = reader.read_vec_until_eof()?;
+        Ok(Self {
+            endianness,
+            value,
+            key_hash: None,
+        })
+    }
+}
+
+impl<C: Context> Writable<C> for UntypedData {
+    fn write_to<T: ?Sized + Writer<C>>(&self, writer: &mut T) -> Result<(), C::Error> {
+        // the value is written as it is, so it must be written with the same endianness.
+        // use `DataWriter::write_untyped` to keep the endianness of the value.
+        writer.write_bytes(&self.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UntypedData;
+    use speedy::{Endianness, Readable, Writable};
+
+    #[test]
+    fn test_untyped_data() {
+        let value = vec![0x03, 0x00, 0x00, 0x00, 0x52, 0x45, 0x44, 0x00];
+        let data = UntypedData::read_from_buffer_with_ctx(Endianness::BigEndian, &value).unwrap();
+        assert_eq!(data.endianness(), Endianness::BigEndian);
+        assert_eq!(data.value(), &value[..]);
+        assert!(data.key_hash().is_none());
+        assert_eq!(
+            data.write_to_vec_with_ctx(Endianness::BigEndian).unwrap(),
+            value
+        );
+    }
+}
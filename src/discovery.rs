@@ -13,7 +13,7 @@ use crate::discovery::structure::data::{
 };
 use crate::message::submessage::element::{SerializedPayload, Timestamp};
 use crate::rtps::{reader::ReaderIngredients, writer::WriterIngredients};
-use crate::structure::{EntityId, GuidPrefix, TopicKind};
+use crate::structure::{EntityId, GuidPrefix, TopicKind, GUID};
 use alloc::collections::BTreeMap;
 use core::time::Duration as CoreDuration;
 use log::{debug, info, trace};
@@ -218,6 +218,7 @@ pub fn create_builtin_endpoints(
 pub enum DiscoveryDBUpdateNotifier {
     // AddNewParticipant(GuidPrefix),
     DeleteParticipant(GuidPrefix),
+    IgnoreEndpoint(GUID),
 }
 
 pub enum ParticipantMessageCmd {
@@ -359,7 +360,9 @@ impl Discovery {
                             while let Ok(cmd) = self.participant_msg_cmd_reveiver.try_recv() {
                                 match cmd {
                                     ParticipantMessageCmd::SendData(data) => {
-                                        self.p2p_builtin_participant_msg_writer.write(&data);
+                                        self.p2p_builtin_participant_msg_writer
+                                            .write(&data)
+                                            .expect("builtin DataWriter is never suspended");
                                     }
                                 }
                             }
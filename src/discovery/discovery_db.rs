@@ -4,7 +4,7 @@ use crate::message::submessage::element::Timestamp;
 use crate::structure::{GuidPrefix, GUID};
 use alloc::collections::{
     btree_map::Entry::{Occupied, Vacant},
-    BTreeMap,
+    BTreeMap, BTreeSet,
 };
 use alloc::sync::Arc;
 use awkernel_sync::{mcs::MCSNode, mutex::Mutex};
@@ -18,10 +18,11 @@ pub enum EndpointState {
     Unknown,
 }
 
-/// DiscoveryDB has following three purposes
+/// DiscoveryDB has following four purposes
 /// 1. Manege remote Participant data.
 /// 2. Manege liveliness of Participant.
 /// 3. Manege Writer Liveliness.
+/// 4. Manege Participants, Endpoints and Topics ignored by the application.
 #[derive(Clone)]
 pub struct DiscoveryDB {
     inner: Arc<Mutex<DiscoveryDBInner>>,
@@ -150,6 +151,47 @@ impl DiscoveryDB {
         let inner = self.inner.lock(&mut node);
         inner.read_remote_writer(guid)
     }
+
+    /// Add the Participant represented by guid_prefix to ignored Participants.
+    /// The Participant data of it is removed from the discovery_db.
+    pub fn ignore_participant(&mut self, guid_prefix: GuidPrefix) {
+        let mut node = MCSNode::new();
+        let mut inner = self.inner.lock(&mut node);
+        inner.ignore_participant(guid_prefix)
+    }
+
+    /// Add the remote Writer or Reader represented by guid to ignored Endpoints.
+    pub fn ignore_endpoint(&mut self, guid: GUID) {
+        let mut node = MCSNode::new();
+        let mut inner = self.inner.lock(&mut node);
+        inner.ignore_endpoint(guid)
+    }
+
+    /// Add the Topic represented by topic_name to ignored Topics.
+    pub fn ignore_topic(&mut self, topic_name: String) {
+        let mut node = MCSNode::new();
+        let mut inner = self.inner.lock(&mut node);
+        inner.ignore_topic(topic_name)
+    }
+
+    pub fn is_ignored_participant(&self, guid_prefix: GuidPrefix) -> bool {
+        let mut node = MCSNode::new();
+        let inner = self.inner.lock(&mut node);
+        inner.is_ignored_participant(guid_prefix)
+    }
+
+    /// return value: whether the Endpoint represented by guid or the Participant it belongs to is ignored
+    pub fn is_ignored_endpoint(&self, guid: GUID) -> bool {
+        let mut node = MCSNode::new();
+        let inner = self.inner.lock(&mut node);
+        inner.is_ignored_endpoint(guid)
+    }
+
+    pub fn is_ignored_topic(&self, topic_name: &str) -> bool {
+        let mut node = MCSNode::new();
+        let inner = self.inner.lock(&mut node);
+        inner.is_ignored_topic(topic_name)
+    }
 }
 
 struct DiscoveryDBInner {
@@ -158,6 +200,9 @@ struct DiscoveryDBInner {
     local_writer_data: BTreeMap<GUID, (EndpointState, LivelinessQosKind)>,
     // remote_reader_data: BTreeMap<GUID, Timestamp>,
     remote_writer_data: BTreeMap<GUID, (EndpointState, LivelinessQosKind)>,
+    ignored_participants: BTreeSet<GuidPrefix>,
+    ignored_endpoints: BTreeSet<GUID>,
+    ignored_topics: BTreeSet<String>,
 }
 
 impl DiscoveryDBInner {
@@ -168,6 +213,9 @@ impl DiscoveryDBInner {
             local_writer_data: BTreeMap::new(),
             // remote_reader_data: BTreeMap::new(),
             remote_writer_data: BTreeMap::new(),
+            ignored_participants: BTreeSet::new(),
+            ignored_endpoints: BTreeSet::new(),
+            ignored_topics: BTreeSet::new(),
         }
     }
 
@@ -354,4 +402,45 @@ impl DiscoveryDBInner {
             EndpointState::Unknown
         }
     }
+
+    fn ignore_participant(&mut self, guid_prefix: GuidPrefix) {
+        self.ignored_participants.insert(guid_prefix);
+        self.participant_data.remove(&guid_prefix);
+        self.remote_writer_data
+            .retain(|k, _v| k.guid_prefix != guid_prefix);
+        debug!(
+            "add Participant to ignored Participants on discovery_db\n\tParticipant: {}",
+            guid_prefix
+        );
+    }
+
+    fn ignore_endpoint(&mut self, guid: GUID) {
+        self.ignored_endpoints.insert(guid);
+        self.remote_writer_data.remove(&guid);
+        debug!(
+            "add Endpoint to ignored Endpoints on discovery_db\n\tEndpoint: {}",
+            guid
+        );
+    }
+
+    fn ignore_topic(&mut self, topic_name: String) {
+        debug!(
+            "add Topic to ignored Topics on discovery_db\n\tTopic: {}",
+            topic_name
+        );
+        self.ignored_topics.insert(topic_name);
+    }
+
+    fn is_ignored_participant(&self, guid_prefix: GuidPrefix) -> bool {
+        self.ignored_participants.contains(&guid_prefix)
+    }
+
+    fn is_ignored_endpoint(&self, guid: GUID) -> bool {
+        self.ignored_participants.contains(&guid.guid_prefix)
+            || self.ignored_endpoints.contains(&guid)
+    }
+
+    fn is_ignored_topic(&self, topic_name: &str) -> bool {
+        self.ignored_topics.contains(topic_name)
+    }
 }
@@ -332,7 +332,7 @@ impl SDPBuiltinData {
             .ownership(self.ownership.unwrap_or_default())
             .time_based_filter(self.time_based_filter.unwrap_or_default())
             .build();
-        Some(ReaderProxy::new(
+        let mut proxy = ReaderProxy::new(
             remote_guid,
             expects_inline_qos,
             unicast_locator_list,
@@ -342,7 +342,9 @@ impl SDPBuiltinData {
             qos,
             history_cache,
             true,
-        ))
+        );
+        proxy.partition = self.partition.clone().unwrap_or_default();
+        Some(proxy)
     }
 
     pub fn gen_writerproxy(
@@ -378,7 +380,7 @@ impl SDPBuiltinData {
             .ownership_strength(self.ownership_strength.unwrap_or_default())
             .lifespan(self.lifespan.unwrap_or_default())
             .build();
-        Some(WriterProxy::new(
+        let mut proxy = WriterProxy::new(
             remote_guid,
             unicast_locator_list,
             multicast_locator_list,
@@ -387,7 +389,9 @@ impl SDPBuiltinData {
             data_max_size_serialized,
             qos,
             history_cache,
-        ))
+        );
+        proxy.partition = self.partition.clone().unwrap_or_default();
+        Some(proxy)
     }
     // rtps 2.4 spec: 8.5.4.4 Data Types associated with built-in Endpoints used by the Simple Endpoint Discovery Protocol
     // An implementation of the protocol need not necessarily send all information contained in the DataTypes.
@@ -1419,17 +1423,18 @@ mod test {
                 0x01, 0x00, 0x00, 0x00, 0x41, 0x00, 0x0c, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
                 // 0x49: PID_TRANSPORT_PRIORITY
                 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x49, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
-                // 0x2b: PID_LIFESPAN, 0x2c: PID_USER_DATA
-                0x2b, 0x00, 0x08, 0x00, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff, 0xff, 0x2c, 0x00,
-                // 0x1f: PID_OWNERSHIP
-                0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+                // 0x2b: PID_LIFESPAN, 0x29: PID_PARTITION
+                0x2b, 0x00, 0x08, 0x00, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff, 0xff, 0x29, 0x00,
+                0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                // 0x2c: PID_USER_DATA, 0x1f: PID_OWNERSHIP
+                0x2c, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x04, 0x00, 0x00, 0x00,
                 // 0x06: PID_OWNERSHIP_STRENGTH, 0x07: PID TYPE_NAME
-                0x06, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x10, 0x00, 0x0a, 0x00,
-                0x00, 0x00, 0x53, 0x68, 0x61, 0x70, 0x65, 0x54, 0x79, 0x70, 0x65, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x06, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x10, 0x00,
+                0x0a, 0x00, 0x00, 0x00, 0x53, 0x68, 0x61, 0x70, 0x65, 0x54, 0x79, 0x70, 0x65, 0x00,
                 // 0x05: PID_TOPIC_NAME
-                0x05, 0x00, 0x0c, 0x00, 0x07, 0x00, 0x00, 0x00, 0x53, 0x71, 0x75, 0x61, 0x72, 0x65,
+                0x00, 0x00, 0x05, 0x00, 0x0c, 0x00, 0x07, 0x00, 0x00, 0x00, 0x53, 0x71, 0x75, 0x61,
                 // 0x01: PID_SENTINEL
-                0x00, 0x00, 0x01, 0x00, 0x00, 0x00
+                0x72, 0x65, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00
             ]),
             serialized_payload.value
         );
@@ -1620,16 +1625,19 @@ mod test {
                 0x00, 0x00, 0x40, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
                 // 0x41: PID_RESOURCE_LIMIT
                 0x41, 0x00, 0x0c, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                // 0x29: PID_PARTITION
+                0xff, 0xff, 0x29, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
                 // 0x2c: PID_USER_DATA, 0x1f: PID_OWNERSHIP
-                0xff, 0xff, 0x2c, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x04, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x00,
                 // 0x04: PID_TIME_BASED_FILTER
-                0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00,
                 // 0x07: PID_TYPE_NAME
-                0x00, 0x00, 0x07, 0x00, 0x10, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x53, 0x68, 0x61, 0x70,
-                // 0x07: PID_TOPIC_NAME
-                0x65, 0x54, 0x79, 0x70, 0x65, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0c, 0x00, 0x07, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x10, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x53, 0x68,
+                // 0x05: PID_TOPIC_NAME
+                0x61, 0x70, 0x65, 0x54, 0x79, 0x70, 0x65, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0c, 0x00,
                 // 0x01: PID_SENTINEL
-                0x00, 0x00, 0x53, 0x71, 0x75, 0x61, 0x72, 0x65, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00
+                0x07, 0x00, 0x00, 0x00, 0x53, 0x71, 0x75, 0x61, 0x72, 0x65, 0x00, 0x00, 0x01, 0x00,
+                0x00, 0x00
             ]),
             serialized_payload.value
         );
@@ -10,3 +10,16 @@ pub enum IoError {
     #[error("{0}")]
     IoError(#[from] io::Error),
 }
+
+/// Error returned by the operations of the DDS entities
+///
+/// DDS 1.4 spec, 2.2.1.1 Format and Conventions, Return codes
+#[derive(Debug, thiserror::Error)]
+pub enum DdsError {
+    /// > OUT_OF_RESOURCES: Service ran out of the resources needed to complete the operation.
+    #[error("OutOfResources: {0}")]
+    OutOfResources(String),
+    /// > INCONSISTENT_POLICY: Application specified a set of policies that are not consistent with each other.
+    #[error("InconsistentPolicy: {0}")]
+    InconsistentPolicy(String),
+}
@@ -87,7 +87,7 @@
 //!                         message: "Hello, World!".to_string(),
 //!                     };
 //!                     println!("send: {:?}", send_msg);
-//!                     datawriter.write(&send_msg);
+//!                     datawriter.write(&send_msg).unwrap();
 //!                     send_count += 1;
 //!                     write_timer.set_timeout(Duration::new(2, 0), ());
 //!                 }
@@ -31,8 +31,12 @@ impl Message {
         let mut submessages: Vec<SubMessage> = Vec::new();
         let sub_header_lenght = 4;
         while !rtps_body_buf.is_empty() {
+            // rtps 2.3 spec, 8.3.4.1 Rules Followed by the Message Receiver
+            // > If the full Submessage header cannot be read, the rest of the Message is considered invalid.
+            if rtps_body_buf.len() < sub_header_lenght {
+                break;
+            }
             let submessage_header_buf = rtps_body_buf.split_to(sub_header_lenght);
-            // TODO: Message Receiverが従うルール (spec 8.3.4.1)に沿った実装に変更
             let submessage_header = SubMessageHeader::read_from_buffer(&submessage_header_buf)
                 .map_err(map_speedy_err)?;
             // RTPS spec 2.3, section 9.4.5.1.3
@@ -44,6 +48,10 @@ impl Message {
             } else {
                 submessage_header.get_content_len() as usize
             };
+            // > If the submessageLength field is invalid, the rest of the Message is invalid.
+            if submessage_body_len > rtps_body_buf.len() {
+                break;
+            }
 
             let submessage_body_buf = rtps_body_buf.split_to(submessage_body_len);
             // TODO: submessage_body_bufが空っぽのときの挙動を確認
@@ -1,18 +1,25 @@
 use super::element::*;
 use super::{
     submessage::{
-        element::{AckNack, Data, Gap, Heartbeat, InfoDestination, InfoTimestamp},
+        element::{
+            AckNack, Data, DataFrag, Gap, Heartbeat, HeartbeatFrag, InfoDestination, InfoTimestamp,
+            NackFrag,
+        },
         submessage_flag::{
-            AckNackFlag, DataFlag, GapFlag, HeartbeatFlag, InfoDestionationFlag, InfoTimestampFlag,
+            AckNackFlag, DataFlag, DataFragFlag, GapFlag, HeartbeatFlag, HeartbeatFragFlag,
+            InfoDestionationFlag, InfoTimestampFlag, NackFragFlag,
         },
         submessage_header::SubMessageHeader,
         EntitySubmessage, InterpreterSubmessage, SubMessage, SubMessageBody, SubMessageKind,
     },
     Header, Message,
 };
-use crate::rtps::cache::CacheChange;
-use crate::structure::{EntityId, GuidPrefix};
-use speedy::Endianness;
+use crate::rtps::cache::{CacheChange, ChangeKind};
+use crate::structure::{EntityId, GuidPrefix, ParameterId};
+use crate::utils::pad_len;
+use alloc::collections::BTreeSet;
+use bytes::Bytes;
+use speedy::{Endianness, Writable};
 
 pub struct MessageBuilder {
     submessages: Vec<SubMessage>,
@@ -137,6 +144,40 @@ impl MessageBuilder {
         self.submessages.push(hb_msg);
     }
 
+    /// inline QoS of DATA and DATA_FRAG carrying `cache_change`
+    fn inline_qos(endiannes: Endianness, cache_change: &CacheChange) -> Option<ParameterList> {
+        let mut param_list = ParameterList::default();
+        // rtps 2.3 spec, 9.6.3.3 KeyHash
+        // PID_KEY_HASH is sent as inline QoS so that the remote Reader can identify the instance.
+        if !cache_change.instance_handle().is_nil() {
+            param_list.push(
+                ParameterId::PID_KEY_HASH,
+                cache_change.instance_handle().key_hash().to_vec(),
+            );
+        }
+        // rtps 2.3 spec, 9.6.3.9 StatusInfo_t
+        // StatusInfo_t is octet[4] and the flags are in the last octet regardless of the endianness.
+        let status_info = cache_change.kind().status_info();
+        if status_info != 0 {
+            param_list.push(ParameterId::PID_STATUS_INFO, vec![0, 0, 0, status_info]);
+        }
+        // rtps 2.3 spec, 8.7.5 Coherent Sets
+        if let Some(coherent_set) = cache_change.coherent_set {
+            param_list.push(
+                ParameterId::PID_COHERENT_SET,
+                coherent_set
+                    .write_to_vec_with_ctx(endiannes)
+                    .expect("failed to serialize SequenceNumber"),
+            );
+        }
+        param_list.merge(&cache_change.inline_qos);
+        if param_list.is_empty() {
+            None
+        } else {
+            Some(param_list)
+        }
+    }
+
     pub fn data(
         &mut self,
         endiannes: Endianness,
@@ -148,17 +189,29 @@ impl MessageBuilder {
         let payload_length;
         let serialized_payload = cache_change.data_value();
         if let Some(payload) = serialized_payload {
-            data_flag |= DataFlag::Data;
+            // rtps 2.3 spec, 8.3.7.2 Data
+            // the SerializedPayload of a change which is not ALIVE is the serialized key of the instance.
+            if cache_change.kind() == ChangeKind::Alive {
+                data_flag |= DataFlag::Data;
+            } else {
+                data_flag |= DataFlag::Key;
+            }
             payload_length = 4 + payload.value.len();
         } else {
             payload_length = 0;
         }
-        let inline_qos_len = 0;
+        let inline_qos = Self::inline_qos(endiannes, cache_change);
+        let inline_qos_len = if let Some(param_list) = inline_qos.as_ref() {
+            data_flag |= DataFlag::InlineQos;
+            param_list.serialized_size()
+        } else {
+            0
+        };
         let data = Data::new(
             reader_id,
             writer_id,
             cache_change.sequence_number,
-            None,
+            inline_qos,
             serialized_payload.cloned(),
         );
         let data_body = SubMessageBody::Entity(EntitySubmessage::Data(data, data_flag));
@@ -197,6 +250,201 @@ impl MessageBuilder {
         self.submessages.push(gap_msg);
     }
 
+    /// GAP submessages notifying that `seq_nums` are irrelevant
+    ///
+    /// rtps 2.3 spec, 8.3.7.4 Gap
+    /// contiguous SequenceNumbers are coalesced into gapStart..gapList.base,
+    /// and the sparse ones following them are carried by the bitmap of gapList.
+    pub fn gaps(
+        &mut self,
+        endiannes: Endianness,
+        writer_id: EntityId,
+        reader_id: EntityId,
+        seq_nums: &[SequenceNumber],
+    ) {
+        let seq_nums: Vec<SequenceNumber> = seq_nums
+            .iter()
+            .copied()
+            .collect::<BTreeSet<SequenceNumber>>()
+            .into_iter()
+            .collect();
+        let mut i = 0;
+        while i < seq_nums.len() {
+            let gap_start = seq_nums[i];
+            let mut base = gap_start + SequenceNumber(1);
+            i += 1;
+            while i < seq_nums.len() && seq_nums[i] == base {
+                base += SequenceNumber(1);
+                i += 1;
+            }
+            // the bitmap can represent 256 SequenceNumbers from the base
+            let mut set = Vec::new();
+            while i < seq_nums.len() && seq_nums[i] < base + SequenceNumber(256) {
+                set.push(seq_nums[i]);
+                i += 1;
+            }
+            self.gap(
+                endiannes,
+                writer_id,
+                reader_id,
+                gap_start,
+                SequenceNumberSet::from_vec(base, set),
+            );
+        }
+    }
+
+    /// DATA_FRAG carrying `fragment`, which is `fragments_in_submessage` fragments of the `data_size` octets
+    /// SerializedPayload of `cache_change` from `fragment_starting_num`
+    ///
+    /// rtps 2.3 spec, 8.3.7.3 DataFrag
+    #[allow(clippy::too_many_arguments)]
+    pub fn data_frag(
+        &mut self,
+        endiannes: Endianness,
+        writer_id: EntityId,
+        reader_id: EntityId,
+        cache_change: &CacheChange,
+        fragment_starting_num: FragmentNumber,
+        fragments_in_submessage: u16,
+        fragment_size: u16,
+        data_size: u32,
+        fragment: Bytes,
+    ) {
+        let mut data_frag_flag = DataFragFlag::from_enndianness(endiannes);
+        if cache_change.kind() != ChangeKind::Alive {
+            data_frag_flag |= DataFragFlag::Key;
+        }
+        let inline_qos = Self::inline_qos(endiannes, cache_change);
+        let inline_qos_len = if let Some(param_list) = inline_qos.as_ref() {
+            data_frag_flag |= DataFragFlag::InlineQos;
+            param_list.serialized_size()
+        } else {
+            0
+        };
+        // extra_flags(2), octets_to_inlineQos(2), reader_id(4), writer_id(4), writer_sn(8),
+        // fragment_starting_num(4), fragments_in_submessage(2), fragment_size(2), data_size(4) octet
+        let data_frag_body_length = 2
+            + 2
+            + 4
+            + 4
+            + 8
+            + 4
+            + 2
+            + 2
+            + 4
+            + inline_qos_len
+            + fragment.len()
+            + pad_len(fragment.len());
+        let data_frag = DataFrag::new(
+            reader_id,
+            writer_id,
+            cache_change.sequence_number,
+            fragment_starting_num,
+            fragments_in_submessage,
+            data_size,
+            fragment_size,
+            inline_qos,
+            fragment,
+        );
+        let data_frag_body =
+            SubMessageBody::Entity(EntitySubmessage::DataFrag(data_frag, data_frag_flag));
+        let data_frag_header = SubMessageHeader::new(
+            SubMessageKind::DATA_FRAG as u8,
+            data_frag_flag.bits(),
+            data_frag_body_length as u16,
+        );
+        let data_frag_msg = SubMessage {
+            header: data_frag_header,
+            body: data_frag_body,
+        };
+        self.submessages.push(data_frag_msg);
+    }
+
+    pub fn heartbeat_frag(
+        &mut self,
+        endiannes: Endianness,
+        writer_id: EntityId,
+        reader_id: EntityId,
+        writer_sn: SequenceNumber,
+        last_fragment_num: FragmentNumber,
+        count: Count,
+    ) {
+        // reader_id(4), writer_id(4), writer_sn(8), last_fragment_num(4), count(4) octet
+        let heartbeat_frag_body_length = 24;
+        let hb_frag = HeartbeatFrag {
+            reader_id,
+            writer_id,
+            writer_sn,
+            last_fragment_num,
+            count,
+        };
+        let hb_frag_flag = HeartbeatFragFlag::from_enndianness(endiannes);
+        let hb_frag_body =
+            SubMessageBody::Entity(EntitySubmessage::HeartbeatFrag(hb_frag, hb_frag_flag));
+        let hb_frag_header = SubMessageHeader::new(
+            SubMessageKind::HEARTBEAT_FRAG as u8,
+            hb_frag_flag.bits(),
+            heartbeat_frag_body_length,
+        );
+        let hb_frag_msg = SubMessage {
+            header: hb_frag_header,
+            body: hb_frag_body,
+        };
+        self.submessages.push(hb_frag_msg);
+    }
+
+    pub fn nack_frag(
+        &mut self,
+        endiannes: Endianness,
+        writer_id: EntityId,
+        reader_id: EntityId,
+        writer_sn: SequenceNumber,
+        fragment_number_state: FragmentNumberSet,
+        count: Count,
+    ) {
+        // reader_id(4), writer_id(4), writer_sn(8), fragment_number_state, count(4) octet
+        let nack_frag_body_length = 20 + fragment_number_state.size();
+        let nack_frag = NackFrag {
+            reader_id,
+            writer_id,
+            writer_sn,
+            fragment_number_state,
+            count,
+        };
+        let nack_frag_flag = NackFragFlag::from_enndianness(endiannes);
+        let nack_frag_body =
+            SubMessageBody::Entity(EntitySubmessage::NackFrag(nack_frag, nack_frag_flag));
+        let nack_frag_header = SubMessageHeader::new(
+            SubMessageKind::NACK_FRAG as u8,
+            nack_frag_flag.bits(),
+            nack_frag_body_length,
+        );
+        let nack_frag_msg = SubMessage {
+            header: nack_frag_header,
+            body: nack_frag_body,
+        };
+        self.submessages.push(nack_frag_msg);
+    }
+
+    /// serialized size of the RTPS Message which will be built
+    pub fn len(&self) -> usize {
+        // RTPS Header (20 octets) + SubmessageHeader (4 octets) and body of each submessage
+        20 + self
+            .submessages
+            .iter()
+            .map(|s| 4 + s.header.get_content_len() as usize)
+            .sum::<usize>()
+    }
+
+    /// move the submessages of `other` to the end of this message
+    pub fn append(&mut self, mut other: MessageBuilder) {
+        self.submessages.append(&mut other.submessages);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.submessages.is_empty()
+    }
+
     pub fn build(self, self_guid_prefix: GuidPrefix) -> Message {
         Message {
             header: Header::new(self_guid_prefix),
@@ -204,3 +452,245 @@ impl MessageBuilder {
         }
     }
 }
+
+/// MessagePacker
+///
+/// packs submessages for the same destination into as few RTPS Messages as possible.
+/// Each Message does not exceed `max_message_size` unless a single group of submessages exceeds it.
+pub struct MessagePacker {
+    guid_prefix: GuidPrefix,
+    endianness: Endianness,
+    max_message_size: usize,
+    /// if Some, each Message starts with INFO_DST
+    destination: Option<GuidPrefix>,
+    message_builder: MessageBuilder,
+    /// whether message_builder has submessages other than INFO_DST
+    is_empty: bool,
+    messages: Vec<Vec<u8>>,
+}
+
+impl MessagePacker {
+    pub fn new(
+        guid_prefix: GuidPrefix,
+        endianness: Endianness,
+        max_message_size: usize,
+        destination: Option<GuidPrefix>,
+    ) -> Self {
+        Self {
+            guid_prefix,
+            endianness,
+            max_message_size,
+            destination,
+            message_builder: Self::new_message(endianness, destination),
+            is_empty: true,
+            messages: Vec::new(),
+        }
+    }
+
+    fn new_message(endianness: Endianness, destination: Option<GuidPrefix>) -> MessageBuilder {
+        let mut message_builder = MessageBuilder::new();
+        if let Some(guid_prefix) = destination {
+            message_builder.info_dst(endianness, guid_prefix);
+        }
+        message_builder
+    }
+
+    /// append a group of submessages which must be sent in the same Message (e.g. INFO_TS and DATA)
+    pub fn append(&mut self, submessages: MessageBuilder) {
+        if submessages.is_empty() {
+            return;
+        }
+        // the RTPS Header (20 octets) is counted in both lengths
+        if !self.is_empty
+            && self.message_builder.len() + submessages.len() - 20 > self.max_message_size
+        {
+            self.flush();
+        }
+        self.message_builder.append(submessages);
+        self.is_empty = false;
+    }
+
+    /// serialize the Message being packed
+    pub fn flush(&mut self) {
+        if self.is_empty {
+            return;
+        }
+        let message_builder = core::mem::replace(
+            &mut self.message_builder,
+            Self::new_message(self.endianness, self.destination),
+        );
+        let message_buf = message_builder
+            .build(self.guid_prefix)
+            .write_to_vec_with_ctx(self.endianness)
+            .expect("failed to serialize message");
+        self.messages.push(message_buf);
+        self.is_empty = true;
+    }
+
+    /// serialized Messages to send
+    pub fn finish(mut self) -> Vec<Vec<u8>> {
+        self.flush();
+        self.messages
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MessageBuilder, MessagePacker};
+    use crate::message::submessage::{
+        element::{SequenceNumber, Timestamp},
+        EntitySubmessage, InterpreterSubmessage, SubMessageBody, SubMessageKind,
+    };
+    use crate::message::Message;
+    use crate::structure::{EntityId, GuidPrefix};
+    use bytes::Bytes;
+    use speedy::Endianness;
+
+    #[test]
+    fn test_gaps() {
+        let mut message_builder = MessageBuilder::new();
+        let seq_nums: Vec<SequenceNumber> = [1, 2, 3, 5, 8, 3, 300, 301]
+            .iter()
+            .map(|sn| SequenceNumber(*sn))
+            .collect();
+        message_builder.gaps(
+            Endianness::LittleEndian,
+            EntityId::UNKNOW,
+            EntityId::UNKNOW,
+            &seq_nums,
+        );
+        let gaps: Vec<(i64, i64, Vec<i64>)> = message_builder
+            .submessages
+            .iter()
+            .map(|submsg| match &submsg.body {
+                SubMessageBody::Entity(EntitySubmessage::Gap(gap, _)) => (
+                    gap.gap_start.0,
+                    gap.gap_list.base().0,
+                    gap.gap_list.set().iter().map(|sn| sn.0).collect(),
+                ),
+                _ => panic!("not GAP"),
+            })
+            .collect();
+        assert_eq!(gaps, vec![(1, 4, vec![5, 8]), (300, 302, vec![])]);
+    }
+
+    /// INFO_TS (12 octets) + HEARTBEAT (32 octets), which must be sent in the same Message
+    fn timestamped_heartbeat(seq_num: i64) -> MessageBuilder {
+        let mut message_builder = MessageBuilder::new();
+        message_builder.info_ts(
+            Endianness::LittleEndian,
+            Some(Timestamp {
+                seconds: seq_num as u32,
+                fraction: 0,
+            }),
+        );
+        message_builder.heartbeat(
+            Endianness::LittleEndian,
+            false,
+            EntityId::UNKNOW,
+            EntityId::UNKNOW,
+            SequenceNumber(1),
+            SequenceNumber(seq_num),
+            seq_num as i32,
+            false,
+        );
+        message_builder
+    }
+
+    /// kinds of the submessages in each serialized Message
+    fn submessage_kinds(messages: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        messages
+            .iter()
+            .map(|buf| {
+                Message::new(Bytes::from(buf.clone()))
+                    .expect("failed to parse message")
+                    .submessages
+                    .iter()
+                    .map(|submsg| submsg.header.get_submessagekind() as u8)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_message_packer_split() {
+        // RTPS Header (20 octets) + 2 groups (44 octets each) fit in 110 octets
+        let mut packer =
+            MessagePacker::new(GuidPrefix::UNKNOW, Endianness::LittleEndian, 110, None);
+        for seq_num in 1..=5 {
+            packer.append(timestamped_heartbeat(seq_num));
+        }
+        let messages = packer.finish();
+        assert!(messages.iter().all(|buf| buf.len() <= 110));
+        let (ts, hb) = (
+            SubMessageKind::INFO_TS as u8,
+            SubMessageKind::HEARTBEAT as u8,
+        );
+        assert_eq!(
+            submessage_kinds(&messages),
+            vec![vec![ts, hb, ts, hb], vec![ts, hb, ts, hb], vec![ts, hb]]
+        );
+
+        // a group exceeding the limit by itself is sent alone
+        let mut packer = MessagePacker::new(GuidPrefix::UNKNOW, Endianness::LittleEndian, 40, None);
+        packer.append(timestamped_heartbeat(1));
+        packer.append(timestamped_heartbeat(2));
+        assert_eq!(
+            submessage_kinds(&packer.finish()),
+            vec![vec![ts, hb], vec![ts, hb]]
+        );
+    }
+
+    #[test]
+    fn test_message_packer_info_dst() {
+        // RTPS Header (20 octets) + INFO_DST (16 octets) + 2 groups (44 octets each) fit in 130 octets
+        let mut packer = MessagePacker::new(
+            GuidPrefix::UNKNOW,
+            Endianness::LittleEndian,
+            130,
+            Some(GuidPrefix::UNKNOW),
+        );
+        for seq_num in 1..=3 {
+            packer.append(timestamped_heartbeat(seq_num));
+        }
+        let messages = packer.finish();
+        let (dst, ts, hb) = (
+            SubMessageKind::INFO_DST as u8,
+            SubMessageKind::INFO_TS as u8,
+            SubMessageKind::HEARTBEAT as u8,
+        );
+        // each Message starts with INFO_DST, and INFO_TS is kept with its HEARTBEAT
+        assert_eq!(
+            submessage_kinds(&messages),
+            vec![vec![dst, ts, hb, ts, hb], vec![dst, ts, hb]]
+        );
+        // the timestamp of each group is not lost by the split
+        let timestamps: Vec<u32> = messages
+            .iter()
+            .flat_map(|buf| {
+                Message::new(Bytes::from(buf.clone()))
+                    .expect("failed to parse message")
+                    .submessages
+                    .into_iter()
+                    .filter_map(|submsg| match submsg.body {
+                        SubMessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(
+                            info_ts,
+                            _,
+                        )) => info_ts.timestamp.map(|ts| ts.seconds),
+                        _ => None,
+                    })
+                    .collect::<Vec<u32>>()
+            })
+            .collect();
+        assert_eq!(timestamps, vec![1, 2, 3]);
+
+        // nothing is sent if no submessage is appended
+        let packer = MessagePacker::new(
+            GuidPrefix::UNKNOW,
+            Endianness::LittleEndian,
+            130,
+            Some(GuidPrefix::UNKNOW),
+        );
+        assert!(packer.finish().is_empty());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::dds::qos::{
-    policy::{Durability, History, HistoryQosKind, LivelinessQosKind, Reliability},
+    policy::{Durability, History, HistoryQosKind, LivelinessQosKind, Partition, Reliability},
     DataReaderQosBuilder, DataWriterQosBuilder,
 };
 use crate::discovery::discovery_db::DiscoveryDB;
@@ -21,7 +21,7 @@ use crate::rtps::{
     reader::{Reader, ReaderTimer},
     writer::{Writer, WriterTimer},
 };
-use crate::structure::{EntityId, GuidPrefix, VendorId, GUID};
+use crate::structure::{EntityId, GuidPrefix, ParameterId, VendorId, GUID};
 use alloc::collections::BTreeMap;
 use alloc::fmt;
 use alloc::sync::Arc;
@@ -30,6 +30,36 @@ use log::{error, info, trace, warn};
 use mio_extras::channel as mio_channel;
 use speedy::Endianness;
 use std::error;
+use std::net::{IpAddr, SocketAddr};
+
+/// inline QoS of DATA or DATA_FRAG this implementation understands
+struct InlineQos {
+    change_kind: ChangeKind,
+    instance_handle: InstantHandle,
+    coherent_set: Option<SequenceNumber>,
+    topic_name: Option<String>,
+    partition: Option<Partition>,
+}
+
+impl Default for InlineQos {
+    fn default() -> Self {
+        Self {
+            change_kind: ChangeKind::Alive,
+            instance_handle: InstantHandle::NIL,
+            coherent_set: None,
+            topic_name: None,
+            partition: None,
+        }
+    }
+}
+
+impl InlineQos {
+    /// the Reader receives the change only if the topic name and the partition sent
+    /// as inline QoS match it
+    fn is_match(&self, reader: &Reader) -> bool {
+        reader.is_inline_qos_match(self.topic_name.as_deref(), self.partition.as_ref())
+    }
+}
 
 #[derive(Debug, Clone)]
 enum MessageError {
@@ -93,17 +123,57 @@ impl MessageReceiver {
         }
     }
 
-    fn reset(&mut self) {
-        self.source_version = ProtocolVersion::PROTOCOLVERSION;
-        self.source_vendor_id = VendorId::VENDORID_UNKNOW;
-        self.source_guid_prefix = GuidPrefix::UNKNOW;
-        self.dest_guid_prefix = GuidPrefix::UNKNOW;
-        self.unicast_reply_locator_list.clear();
-        self.multicast_reply_locator_list.clear();
+    /// rtps 2.3 spec, 8.3.4.1 Rules Followed by the Message Receiver, Table 8.16
+    /// initialize the state of the Message Receiver with the Message header and the source address
+    fn reset(&mut self, header: &Header, source_addr: SocketAddr) {
+        self.source_version = header.version.clone();
+        self.source_vendor_id = header.vendor_id;
+        self.source_guid_prefix = header.guid_prefix;
+        self.dest_guid_prefix = self.own_guid_prefix;
+        let (kind, address) = match source_addr.ip() {
+            IpAddr::V4(v4) => {
+                let mut address = [0; 16];
+                address[12..].copy_from_slice(&v4.octets());
+                (Locator::KIND_UDPV4, address)
+            }
+            IpAddr::V6(v6) => (Locator::KIND_UDPV6, v6.octets()),
+        };
+        self.unicast_reply_locator_list = vec![Locator::new(kind, Locator::PORT_INVALID, address)];
+        self.multicast_reply_locator_list = vec![Locator::new(
+            kind,
+            Locator::PORT_INVALID,
+            Locator::ADDRESS_INVALID,
+        )];
         self.have_timestamp = false;
         self.timestamp = Timestamp::TIME_INVALID;
     }
 
+    /// locators to send the responses to the source of the Message
+    ///
+    /// The initial reply locators have no valid port, so they are valid only if announced by INFO_REPLY.
+    /// If empty, the responses are sent to the locators announced by Discovery.
+    fn reply_locators(&self) -> Vec<Locator> {
+        let is_valid = |loc: &&Locator| {
+            loc.kind != Locator::KIND_INVALID
+                && loc.port != Locator::PORT_INVALID
+                && loc.address != Locator::ADDRESS_INVALID
+        };
+        let unicast: Vec<Locator> = self
+            .unicast_reply_locator_list
+            .iter()
+            .filter(is_valid)
+            .copied()
+            .collect();
+        if !unicast.is_empty() {
+            return unicast;
+        }
+        self.multicast_reply_locator_list
+            .iter()
+            .filter(is_valid)
+            .copied()
+            .collect()
+    }
+
     pub fn handle_packet(
         &mut self,
         messages: Vec<UdpMessage>,
@@ -144,7 +214,9 @@ impl MessageReceiver {
                 rtps_message.header.guid_prefix,
                 rtps_message.summary()
             );
-            if let Some(mut rt) = self.handle_parsed_packet(rtps_message, writers, readers) {
+            if let Some(mut rt) =
+                self.handle_parsed_packet(rtps_message, message.addr, writers, readers)
+            {
                 rtv.append(&mut rt);
             };
         }
@@ -158,15 +230,33 @@ impl MessageReceiver {
     fn handle_parsed_packet(
         &mut self,
         rtps_msg: Message,
+        source_addr: SocketAddr,
         writers: &mut BTreeMap<EntityId, Writer>,
         readers: &mut BTreeMap<EntityId, Reader>,
     ) -> Option<Vec<ReaderTimer>> {
-        self.reset();
-        self.dest_guid_prefix = self.own_guid_prefix;
-        self.source_guid_prefix = rtps_msg.header.guid_prefix;
+        // rtps 2.3 spec, 8.3.6.3 Validity
+        // the Message with the larger major version than this implementation is invalid.
+        if rtps_msg.header.version.major > ProtocolVersion::PROTOCOLVERSION.major {
+            warn!(
+                "receive RTPS message with unsupported version {}.{} from {}",
+                rtps_msg.header.version.major,
+                rtps_msg.header.version.minor,
+                rtps_msg.header.guid_prefix
+            );
+            return None;
+        }
+        self.reset(&rtps_msg.header, source_addr);
         let mut rtv = Vec::new();
         for submsg in rtps_msg.submessages {
             match submsg.body {
+                SubMessageBody::Entity(_) if self.dest_guid_prefix != self.own_guid_prefix => {
+                    // rtps 2.3 spec, 8.3.7.7 InfoDestination
+                    // the Submessages following INFO_DST are intended for the other Participant.
+                    trace!(
+                        "ignore submessage for other Participant {}",
+                        self.dest_guid_prefix
+                    );
+                }
                 SubMessageBody::Entity(e) => {
                     match self.handle_entity_submessage(e, writers, readers) {
                         Ok(rt) => {
@@ -218,7 +308,7 @@ impl MessageReceiver {
                 self.handle_data_submsg(data, flags, readers, writers)
             }
             EntitySubmessage::DataFrag(data_frag, flags) => {
-                Self::handle_datafrag_submsg(data_frag, flags).map(|_| None)
+                self.handle_datafrag_submsg(data_frag, flags, readers)
             }
             EntitySubmessage::Gap(gap, flags) => {
                 self.handle_gap_submsg(gap, flags, readers).map(|_| None)
@@ -226,12 +316,12 @@ impl MessageReceiver {
             EntitySubmessage::HeartBeat(heartbeat, flags) => self
                 .handle_heartbeat_submsg(heartbeat, flags, readers)
                 .map(|_| None),
-            EntitySubmessage::HeartbeatFrag(heartbeatfrag, flags) => {
-                Self::handle_heartbeatfrag_submsg(heartbeatfrag, flags).map(|_| None)
-            }
-            EntitySubmessage::NackFrag(nack_frag, flags) => {
-                Self::handle_nackfrag_submsg(nack_frag, flags).map(|_| None)
-            }
+            EntitySubmessage::HeartbeatFrag(heartbeatfrag, flags) => self
+                .handle_heartbeatfrag_submsg(heartbeatfrag, flags, readers)
+                .map(|_| None),
+            EntitySubmessage::NackFrag(nack_frag, flags) => self
+                .handle_nackfrag_submsg(nack_frag, flags, writers)
+                .map(|_| None),
         }
     }
     fn handle_interpreter_submessage(
@@ -282,15 +372,21 @@ impl MessageReceiver {
                 }
             }
             InterpreterSubmessage::InfoSource(info_souce, _flags) => {
+                // rtps 2.3 spec, 8.3.7.9.4 Change in state of the Receiver
                 self.source_guid_prefix = info_souce.guid_prefix;
                 self.source_version = info_souce.protocol_version;
                 self.source_vendor_id = info_souce.vendor_id;
-                self.unicast_reply_locator_list.clear();
-                self.multicast_reply_locator_list.clear();
+                self.unicast_reply_locator_list = vec![Locator::INVALID];
+                self.multicast_reply_locator_list = vec![Locator::INVALID];
                 self.have_timestamp = false;
             }
             InterpreterSubmessage::InfoDestination(info_dst, _flags) => {
-                self.dest_guid_prefix = info_dst.guid_prefix;
+                // rtps 2.3 spec, 8.3.7.7.4 Change in state of the Receiver
+                self.dest_guid_prefix = if info_dst.guid_prefix != GuidPrefix::UNKNOW {
+                    info_dst.guid_prefix
+                } else {
+                    self.own_guid_prefix
+                };
             }
         }
         Ok(())
@@ -333,7 +429,7 @@ impl MessageReceiver {
         }
 
         if let Some(w) = writers.get_mut(&ackanck.writer_id) {
-            Ok(w.handle_acknack(ackanck, reader_guid))
+            Ok(w.handle_acknack(ackanck, reader_guid, &self.reply_locators()))
         } else {
             Ok(None)
         }
@@ -373,14 +469,29 @@ impl MessageReceiver {
         }
         if flag.contains(DataFlag::Key) && !flag.contains(DataFlag::Data) {
             // the serializedPayload element is interpreted as the value of the key that identifies the registered instance of the data-object.
+            // the key is kept as the SerializedPayload of the change, and the DataReader
+            // deserializes only the key fields from it.
         }
-        if flag.contains(DataFlag::InlineQos) {
-            // the inlineQos element contains QoS values that override those of the RTPS Writer and should
-            // be used to process the update. For a complete list of possible in-line QoS parameters, see Table 8.80.
+        if flag.contains(DataFlag::Key) && flag.contains(DataFlag::Data) {
+            // rtps 2.3 spec, 9.4.5.3.1 Flags in the Submessage Header
+            // > D=1 and K=1 is an invalid combination in this version of the protocol.
             return Err(MessageError::Warn(
-                "received DATA with inlineQos, Umber DDS dosen't support inlineQos yet".to_string(),
+                "received DATA with both DataFlag and KeyFlag".to_string(),
             ));
         }
+        let inline_qos = match data.inline_qos.as_ref() {
+            // the inlineQos element contains QoS values that override those of the RTPS Writer and should
+            // be used to process the update. For a complete list of possible in-line QoS parameters, see Table 8.80.
+            Some(inline_qos) if flag.contains(DataFlag::InlineQos) => {
+                let endianness = if flag.contains(DataFlag::Endianness) {
+                    Endianness::LittleEndian
+                } else {
+                    Endianness::BigEndian
+                };
+                Self::parse_inline_qos(inline_qos, endianness, "DATA")?
+            }
+            _ => InlineQos::default(),
+        };
         if flag.contains(DataFlag::NonStandardPayload) {
             // the serializedPayload element is not formatted according to Section 10.
             // This flag is informational. It indicates that the SerializedPayload has been transformed as described in another specification
@@ -391,15 +502,32 @@ impl MessageReceiver {
         let writer_guid = GUID::new(self.source_guid_prefix, data.writer_id);
         let _reader_guid = GUID::new(self.dest_guid_prefix, data.reader_id);
 
+        // DDS 1.4 spec, 2.2.2.2.1.14 ignore_participant
+        // DDS 1.4 spec, 2.2.2.2.1.16 ignore_publication
+        if self.disc_db.is_ignored_endpoint(writer_guid) {
+            trace!(
+                "received DATA from ignored Writer\n\tWriter: {}",
+                writer_guid
+            );
+            return Ok(None);
+        }
+
         let ts = Timestamp::now().expect("failed to get Timestamp::now()");
-        let change = CacheChange::new(
-            ChangeKind::Alive,
+        let mut change = CacheChange::new(
+            inline_qos.change_kind,
             writer_guid,
             data.writer_sn,
             ts,
             data.serialized_payload.clone(),
-            InstantHandle {}, // TODO
+            inline_qos.instance_handle,
         );
+        if self.have_timestamp {
+            change.source_timestamp = self.timestamp;
+        }
+        change.coherent_set = inline_qos.coherent_set;
+        if let Some(params) = &data.inline_qos {
+            change.inline_qos = params.clone();
+        }
 
         if data.writer_id == EntityId::SPDP_BUILTIN_PARTICIPANT_ANNOUNCER
             || data.reader_id == EntityId::SPDP_BUILTIN_PARTICIPANT_DETECTOR
@@ -423,7 +551,7 @@ impl MessageReceiver {
             self.handle_participant_message(data, change, ts, readers)?;
         } else if data.reader_id == EntityId::UNKNOW {
             for reader in readers.values_mut() {
-                if reader.is_contain_writer(writer_guid) {
+                if reader.is_contain_writer(writer_guid) && inline_qos.is_match(reader) {
                     if let Some(mut rt) = reader.add_change(self.source_guid_prefix, change.clone())
                     {
                         rtv.append(&mut rt);
@@ -438,7 +566,13 @@ impl MessageReceiver {
         } else {
             match readers.get_mut(&data.reader_id) {
                 Some(r) => {
-                    if r.is_contain_writer(writer_guid) {
+                    if !inline_qos.is_match(r) {
+                        trace!(
+                            "received DATA with inline QoS not matching the Reader\n\tWriter: {}\n\tReader: {}",
+                            writer_guid,
+                            GUID::new(self.dest_guid_prefix, data.reader_id)
+                        );
+                    } else if r.is_contain_writer(writer_guid) {
                         if let Some(mut rt) = r.add_change(self.source_guid_prefix, change) {
                             rtv.append(&mut rt);
                         };
@@ -465,6 +599,93 @@ impl MessageReceiver {
             Ok(Some(rtv))
         }
     }
+    /// rtps 2.3 spec, 8.7.2.2.1 InlineQos
+    /// parse the inline QoS of DATA or DATA_FRAG.
+    fn parse_inline_qos(
+        inline_qos: &ParameterList,
+        endianness: Endianness,
+        submsg_name: &str,
+    ) -> Result<InlineQos, MessageError> {
+        let mut rtv = InlineQos::default();
+        // rtps 2.3 spec, 9.6.2.2.1 ParameterId space
+        // > If the M-bit is set, the receiver must understand the parameter,
+        // > otherwise the parameter may be ignored.
+        if let Some(pid) = inline_qos.parameter_ids().find(|pid| {
+            pid.value & 0x4000 != 0
+                && *pid != ParameterId::PID_KEY_HASH
+                && *pid != ParameterId::PID_COHERENT_SET
+                && *pid != ParameterId::PID_STATUS_INFO
+                && *pid != ParameterId::PID_TOPIC_NAME
+                && *pid != ParameterId::PID_PARTITION
+        }) {
+            return Err(MessageError::Warn(format!(
+                "received {submsg_name} with unsupported must understand inline QoS: 0x{:04x}",
+                pid.value
+            )));
+        }
+        if let Some(status_info) = inline_qos.get(ParameterId::PID_STATUS_INFO) {
+            if status_info.len() != 4 {
+                return Err(MessageError::Warn(format!(
+                    "received {submsg_name} with invalid PID_STATUS_INFO"
+                )));
+            }
+            rtv.change_kind = ChangeKind::from_status_info(status_info[3]);
+        }
+        if let Some(sn) = inline_qos.get(ParameterId::PID_COHERENT_SET) {
+            match SequenceNumber::read_from_buffer_with_ctx(endianness, sn) {
+                Ok(sn) => rtv.coherent_set = Some(sn),
+                Err(_) => {
+                    return Err(MessageError::Warn(format!(
+                        "received {submsg_name} with invalid PID_COHERENT_SET"
+                    )))
+                }
+            }
+        }
+        if let Some(key_hash) = inline_qos.get(ParameterId::PID_KEY_HASH) {
+            if key_hash.len() != 16 {
+                return Err(MessageError::Warn(format!(
+                    "received {submsg_name} with invalid PID_KEY_HASH"
+                )));
+            }
+            let mut kh = [0; 16];
+            kh.copy_from_slice(key_hash);
+            rtv.instance_handle = InstantHandle::new(kh);
+        }
+        if let Some(cdr_str) = inline_qos.get(ParameterId::PID_TOPIC_NAME) {
+            match Self::parse_cdr_string(cdr_str, endianness) {
+                Some(topic_name) => rtv.topic_name = Some(topic_name),
+                None => {
+                    return Err(MessageError::Warn(format!(
+                        "received {submsg_name} with invalid PID_TOPIC_NAME"
+                    )))
+                }
+            }
+        }
+        if let Some(partition) = inline_qos.get(ParameterId::PID_PARTITION) {
+            match Partition::read_from_buffer_with_ctx(endianness, partition) {
+                Ok(partition) => rtv.partition = Some(partition),
+                Err(_) => {
+                    return Err(MessageError::Warn(format!(
+                        "received {submsg_name} with invalid PID_PARTITION"
+                    )))
+                }
+            }
+        }
+        Ok(rtv)
+    }
+    /// CDR string: length including the null char, characters and the null char
+    fn parse_cdr_string(bytes: &[u8], endianness: Endianness) -> Option<String> {
+        let len_bytes: [u8; 4] = bytes.get(..4)?.try_into().ok()?;
+        let len = match endianness {
+            Endianness::LittleEndian => u32::from_le_bytes(len_bytes),
+            Endianness::BigEndian => u32::from_be_bytes(len_bytes),
+        } as usize;
+        if len == 0 {
+            return None;
+        }
+        let chars = bytes.get(4..4 + len - 1)?;
+        String::from_utf8(chars.to_vec()).ok()
+    }
     fn handle_participant_discovery(
         &self,
         guid_prefix: GuidPrefix,
@@ -828,12 +1049,27 @@ impl MessageReceiver {
                 ));
             }
         };
-        self.disc_db.write_remote_writer(
-            writer_proxy.remote_writer_guid,
-            ts,
-            writer_proxy.qos.liveliness().kind,
-        );
-        for (eid, reader) in readers.iter_mut() {
+        // DDS 1.4 spec, 2.2.2.2.1.15 ignore_topic
+        // DDS 1.4 spec, 2.2.2.2.1.16 ignore_publication
+        // The change is still added to the SEDP builtin reader to keep its reliable state.
+        let is_ignored = self
+            .disc_db
+            .is_ignored_endpoint(writer_proxy.remote_writer_guid)
+            || self.disc_db.is_ignored_topic(topic_name);
+        if is_ignored {
+            trace!(
+                "received DATA(w) of ignored Writer or Topic\n\tWriter: {}\n\tTopic: {}",
+                writer_proxy.remote_writer_guid,
+                topic_name
+            );
+        } else {
+            self.disc_db.write_remote_writer(
+                writer_proxy.remote_writer_guid,
+                ts,
+                writer_proxy.qos.liveliness().kind,
+            );
+        }
+        for (eid, reader) in readers.iter_mut().filter(|_| !is_ignored) {
             if reader.is_writer_match(topic_name, data_type) {
                 let remote_writer_topic_kind =
                     writer_proxy.remote_writer_guid.entity_id.topic_kind();
@@ -861,6 +1097,7 @@ impl MessageReceiver {
                     writer_proxy.default_multicast_locator_list.clone(),
                     writer_proxy.data_max_size_serialized,
                     writer_proxy.qos.clone(),
+                    writer_proxy.partition.clone(),
                 );
                 self.wlp_timer_sender
                     .send(*eid)
@@ -944,7 +1181,21 @@ impl MessageReceiver {
                 ));
             }
         };
-        for (eid, writer) in writers.iter_mut() {
+        // DDS 1.4 spec, 2.2.2.2.1.15 ignore_topic
+        // DDS 1.4 spec, 2.2.2.2.1.17 ignore_subscription
+        // The change is still added to the SEDP builtin reader to keep its reliable state.
+        let is_ignored = self
+            .disc_db
+            .is_ignored_endpoint(reader_proxy.remote_reader_guid)
+            || self.disc_db.is_ignored_topic(topic_name);
+        if is_ignored {
+            trace!(
+                "received DATA(r) of ignored Reader or Topic\n\tReader: {}\n\tTopic: {}",
+                reader_proxy.remote_reader_guid,
+                topic_name
+            );
+        }
+        for (eid, writer) in writers.iter_mut().filter(|_| !is_ignored) {
             if writer.is_reader_match(topic_name, data_type) {
                 let remote_reader_topic_kind =
                     reader_proxy.remote_reader_guid.entity_id.topic_kind();
@@ -971,6 +1222,7 @@ impl MessageReceiver {
                     reader_proxy.default_unicast_locator_list.clone(),
                     reader_proxy.default_multicast_locator_list.clone(),
                     reader_proxy.qos.clone(),
+                    reader_proxy.partition.clone(),
                 )
             }
         }
@@ -1076,12 +1328,106 @@ impl MessageReceiver {
         Ok(())
     }
     fn handle_datafrag_submsg(
-        _data_frag: DataFrag,
-        _flag: BitFlags<DataFragFlag>,
-    ) -> Result<(), MessageError> {
-        Err(MessageError::Warn(
-            "received DATA_FRAG submessage, Umber DDS dosen't support DATA_FRAG yet".to_string(),
-        ))
+        &mut self,
+        data_frag: DataFrag,
+        flag: BitFlags<DataFragFlag>,
+        readers: &mut BTreeMap<EntityId, Reader>,
+    ) -> Result<Option<Vec<ReaderTimer>>, MessageError> {
+        // rtps 2.3 spec 8.3.7.3 DataFrag
+
+        if self.source_guid_prefix == self.own_guid_prefix
+            && self.dest_guid_prefix != GuidPrefix::UNKNOW
+        {
+            trace!("message from same Participant & dest_guid_prefix is not UNKNOWN");
+            return Ok(None);
+        }
+
+        // validation
+        // rtps 2.3 spec 8.3.7.3.3 Validity
+        if data_frag.writer_sn < SequenceNumber(1)
+            || data_frag.writer_sn == SequenceNumber::SEQUENCENUMBER_UNKNOWN
+            || data_frag.fragment_size == 0
+            || data_frag.fragment_starting_num < 1
+            || data_frag.fragment_starting_num
+                > data_frag.data_size.div_ceil(data_frag.fragment_size as u32)
+            || data_frag.fragment_size as u32 > data_frag.data_size
+        {
+            return Err(MessageError::Warn(
+                "Invalid DataFrag Submessage".to_string(),
+            ));
+        }
+
+        let inline_qos = match data_frag.inline_qos.as_ref() {
+            Some(inline_qos) if flag.contains(DataFragFlag::InlineQos) => {
+                let endianness = if flag.contains(DataFragFlag::Endianness) {
+                    Endianness::LittleEndian
+                } else {
+                    Endianness::BigEndian
+                };
+                Self::parse_inline_qos(inline_qos, endianness, "DATA_FRAG")?
+            }
+            _ => InlineQos::default(),
+        };
+
+        let writer_guid = GUID::new(self.source_guid_prefix, data_frag.writer_id);
+
+        // DDS 1.4 spec, 2.2.2.2.1.14 ignore_participant
+        // DDS 1.4 spec, 2.2.2.2.1.16 ignore_publication
+        if self.disc_db.is_ignored_endpoint(writer_guid) {
+            trace!(
+                "received DATA_FRAG from ignored Writer\n\tWriter: {}",
+                writer_guid
+            );
+            return Ok(None);
+        }
+
+        if data_frag.writer_id.is_builtin() {
+            return Err(MessageError::Warn(format!(
+                "received DATA_FRAG from builtin Writer, Umber DDS dosen't support fragmented discovery data yet\n\tWriter: {writer_guid}"
+            )));
+        }
+
+        let ts = Timestamp::now().expect("failed to get Timestamp::now()");
+        // the SerializedPayload is set when all fragments are received
+        let mut change = CacheChange::new(
+            inline_qos.change_kind,
+            writer_guid,
+            data_frag.writer_sn,
+            ts,
+            None,
+            inline_qos.instance_handle,
+        );
+        if self.have_timestamp {
+            change.source_timestamp = self.timestamp;
+        }
+        change.coherent_set = inline_qos.coherent_set;
+        if let Some(params) = &data_frag.inline_qos {
+            change.inline_qos = params.clone();
+        }
+
+        let mut rtv = Vec::new();
+        for (reid, reader) in readers.iter_mut() {
+            if (data_frag.reader_id == EntityId::UNKNOW || data_frag.reader_id == *reid)
+                && reader.is_contain_writer(writer_guid)
+                && inline_qos.is_match(reader)
+            {
+                if let Some(mut rt) =
+                    reader.add_data_frag(self.source_guid_prefix, change.clone(), &data_frag)
+                {
+                    rtv.append(&mut rt);
+                }
+                self.disc_db.write_remote_writer(
+                    writer_guid,
+                    ts,
+                    reader.get_matched_writer_qos(writer_guid).liveliness().kind,
+                );
+            }
+        }
+        if rtv.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(rtv))
+        }
     }
     fn handle_gap_submsg(
         &self,
@@ -1150,6 +1496,7 @@ impl MessageReceiver {
         }
 
         let ts = Timestamp::now().expect("failed to get Timestamp::now()");
+        let reply_locators = self.reply_locators();
 
         macro_rules! update_liveliness_if_need {
             ($r: expr, $ts: expr) => {
@@ -1168,7 +1515,7 @@ impl MessageReceiver {
                 EntityId::SEDP_BUILTIN_PUBLICATIONS_ANNOUNCER => {
                     match readers.get_mut(&EntityId::SEDP_BUILTIN_PUBLICATIONS_DETECTOR) {
                         Some(r) => {
-                            r.handle_heartbeat(writer_guid, flag, &heartbeat);
+                            r.handle_heartbeat(writer_guid, flag, &heartbeat, &reply_locators);
                             update_liveliness_if_need!(r, ts);
                         }
                         None => {
@@ -1179,7 +1526,7 @@ impl MessageReceiver {
                 EntityId::SEDP_BUILTIN_SUBSCRIPTIONS_ANNOUNCER => {
                     match readers.get_mut(&EntityId::SEDP_BUILTIN_SUBSCRIPTIONS_DETECTOR) {
                         Some(r) => {
-                            r.handle_heartbeat(writer_guid, flag, &heartbeat);
+                            r.handle_heartbeat(writer_guid, flag, &heartbeat, &reply_locators);
                             update_liveliness_if_need!(r, ts);
                         }
                         None => {
@@ -1191,7 +1538,7 @@ impl MessageReceiver {
                     for reader in readers.values_mut() {
                         if reader.is_contain_writer(GUID::new(self.source_guid_prefix, writer_eid))
                         {
-                            reader.handle_heartbeat(writer_guid, flag, &heartbeat);
+                            reader.handle_heartbeat(writer_guid, flag, &heartbeat, &reply_locators);
                             update_liveliness_if_need!(reader, ts);
                         }
                     }
@@ -1200,7 +1547,7 @@ impl MessageReceiver {
         } else {
             match readers.get_mut(&heartbeat.reader_id) {
                 Some(r) => {
-                    r.handle_heartbeat(writer_guid, flag, &heartbeat);
+                    r.handle_heartbeat(writer_guid, flag, &heartbeat, &reply_locators);
                     update_liveliness_if_need!(r, ts);
                 }
                 None => {
@@ -1214,20 +1561,69 @@ impl MessageReceiver {
         Ok(())
     }
     fn handle_heartbeatfrag_submsg(
-        _heartbeat_frag: HeartbeatFrag,
+        &self,
+        heartbeat_frag: HeartbeatFrag,
         _flag: BitFlags<HeartbeatFragFlag>,
+        readers: &mut BTreeMap<EntityId, Reader>,
     ) -> Result<(), MessageError> {
-        Err(MessageError::Warn(
-            "received HEARTBEAT_FRAG submessage, Umber DDS dosen't support HEARTBEAT_FRAG yet"
-                .to_string(),
-        ))
+        // rtps 2.3 spec 8.3.7.6 HeartbeatFrag
+
+        if self.source_guid_prefix == self.own_guid_prefix
+            && self.dest_guid_prefix != GuidPrefix::UNKNOW
+        {
+            trace!("message from same Participant & dest_guid_prefix is not UNKNOWN");
+            return Ok(());
+        }
+
+        let writer_guid = GUID::new(self.source_guid_prefix, heartbeat_frag.writer_id);
+
+        // validation
+        if heartbeat_frag.writer_sn < SequenceNumber(1)
+            || heartbeat_frag.writer_sn == SequenceNumber::SEQUENCENUMBER_UNKNOWN
+            || heartbeat_frag.last_fragment_num < 1
+        {
+            return Err(MessageError::Warn(format!(
+                "Invalid HeartbeatFrag Submessage from Writer\n\tWriter: {writer_guid}",
+            )));
+        }
+
+        let reply_locators = self.reply_locators();
+        for (reid, reader) in readers.iter_mut() {
+            if (heartbeat_frag.reader_id == EntityId::UNKNOW || heartbeat_frag.reader_id == *reid)
+                && reader.is_contain_writer(writer_guid)
+            {
+                reader.handle_heartbeat_frag(writer_guid, &heartbeat_frag, &reply_locators);
+            }
+        }
+        Ok(())
     }
     fn handle_nackfrag_submsg(
-        _nack_frag: NackFrag,
+        &self,
+        nack_frag: NackFrag,
         _flag: BitFlags<NackFragFlag>,
+        writers: &mut BTreeMap<EntityId, Writer>,
     ) -> Result<(), MessageError> {
-        Err(MessageError::Warn(
-            "received NACK_FRAG submessage, Umber DDS dosen't support NACK_FRAG yet".to_string(),
-        ))
+        // rtps 2.3 spec 8.3.7.5 NackFrag
+
+        if self.source_guid_prefix == self.own_guid_prefix
+            && self.dest_guid_prefix != GuidPrefix::UNKNOW
+        {
+            trace!("message from same Participant & dest_guid_prefix is not UNKNOWN");
+            return Ok(());
+        }
+
+        let reader_guid = GUID::new(self.source_guid_prefix, nack_frag.reader_id);
+
+        // validation
+        if !nack_frag.is_valid() {
+            return Err(MessageError::Warn(format!(
+                "Invalid NackFrag Submessage from Reader {reader_guid}",
+            )));
+        }
+
+        if let Some(w) = writers.get_mut(&nack_frag.writer_id) {
+            w.handle_nackfrag(nack_frag, reader_guid, &self.reply_locators());
+        }
+        Ok(())
     }
 }
@@ -169,6 +169,52 @@ impl fmt::Debug for SequenceNumberSet {
 }
 
 pub type FragmentNumberSet = NumberSet<FragmentNumber>;
+impl FragmentNumberSet {
+    pub fn set(&self) -> Vec<FragmentNumber> {
+        let mut set = Vec::new();
+        for (map_line, map) in self.bitmap.iter().enumerate() {
+            let bitmap_end = min(32, self.num_bits - map_line as u32 * 32);
+            for offset in 0..bitmap_end {
+                // if bit m is set
+                if (map & (1 << (31 - offset))) == 1 << (31 - offset) {
+                    set.push(self.bitmap_base + 32 * map_line as u32 + offset);
+                }
+            }
+        }
+        set
+    }
+    pub fn from_vec(base: FragmentNumber, set: Vec<FragmentNumber>) -> Self {
+        let mut num_bits: u32 = 0;
+        let mut bitmap: Vec<u32> = Vec::new();
+        for frag_num in set {
+            let offset_from_base = frag_num - base;
+            num_bits = max(num_bits, offset_from_base + 1);
+            let line = offset_from_base / 32;
+            let offset_in_line = offset_from_base % 32;
+            bitmap.resize(line as usize + 1, 0);
+            bitmap[line as usize] |= 1 << (31 - offset_in_line);
+        }
+        Self {
+            bitmap_base: base,
+            num_bits,
+            bitmap,
+        }
+    }
+    pub fn size(&self) -> u16 {
+        // bitmap_base: 4
+        // num_bits: 4
+        // bitmap: bitmap.len() * 4
+        8 + self.bitmap.len() as u16 * 4
+    }
+    pub fn is_valid(&self) -> bool {
+        // rtps 2.3 spec 9.4.2.8 FragmentNumberSet
+        // bitmapBase >= 1
+        // 0 <= numBits <= 256
+        self.bitmap_base >= 1
+            && self.num_bits <= 256
+            && self.bitmap.len() as u32 == self.num_bits.div_ceil(32)
+    }
+}
 
 #[derive(Readable, Writable, Clone)]
 pub struct NumberSet<T> {
@@ -178,7 +224,7 @@ pub struct NumberSet<T> {
     pub bitmap: Vec<u32>,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct Parameter {
     parameter_id: ParameterId,
     // length: i16,
@@ -206,11 +252,54 @@ impl<C: Context> Writable<C> for Parameter {
     }
 }
 
-#[derive(Default, PartialEq)]
+#[derive(Default, Clone, PartialEq, Eq)]
 pub struct ParameterList {
     parameters: Vec<Parameter>,
 }
 
+impl ParameterList {
+    pub fn push(&mut self, parameter_id: ParameterId, value: Vec<u8>) {
+        self.parameters.push(Parameter {
+            parameter_id,
+            value,
+        });
+    }
+
+    /// Return the value of the first Parameter whose id is `parameter_id`.
+    pub fn get(&self, parameter_id: ParameterId) -> Option<&[u8]> {
+        self.parameters
+            .iter()
+            .find(|p| p.parameter_id == parameter_id)
+            .map(|p| p.value.as_slice())
+    }
+
+    /// Append the Parameters of `other` whose ids are not in this list.
+    pub fn merge(&mut self, other: &ParameterList) {
+        for p in &other.parameters {
+            if self.get(p.parameter_id).is_none() {
+                self.parameters.push(p.clone());
+            }
+        }
+    }
+
+    pub fn parameter_ids(&self) -> impl Iterator<Item = ParameterId> + '_ {
+        self.parameters.iter().map(|p| p.parameter_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parameters.is_empty()
+    }
+
+    /// serialized size including the PID_SENTINEL
+    pub fn serialized_size(&self) -> usize {
+        self.parameters
+            .iter()
+            .map(|p| 4 + p.value.len() + pad_len(p.value.len()))
+            .sum::<usize>()
+            + 4
+    }
+}
+
 impl<'a, C: Context> Readable<'a, C> for ParameterList {
     fn read_from<R: speedy::Reader<'a, C>>(reader: &mut R) -> Result<Self, C::Error> {
         let mut parameter_list = ParameterList::default();
@@ -245,7 +334,7 @@ impl<C: Context> Writable<C> for ParameterList {
     }
 }
 
-#[derive(PartialEq, Eq, Readable, Writable, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Readable, Writable, Clone, Copy, Debug)]
 pub struct Timestamp {
     // time in seconds
     pub seconds: u32,
@@ -528,6 +617,15 @@ impl SerializedPayload {
         buf.freeze()
     }
 
+    /// SerializedPayload of the data already serialized with the representation `rep_id`
+    pub fn new(rep_id: RepresentationIdentifier, serialized_data: Vec<u8>) -> Self {
+        Self {
+            representation_identifier: rep_id,
+            representation_options: [0; 2],
+            value: Bytes::from(serialized_data),
+        }
+    }
+
     pub fn new_from_cdr_data<W: Writable<Endianness>>(
         data: &W,
         rep_id: RepresentationIdentifier,
@@ -571,9 +669,10 @@ impl<C: Context> Writable<C> for SerializedPayload {
 
 #[cfg(test)]
 mod test {
-    use super::{SequenceNumber, SequenceNumberSet};
+    use super::{FragmentNumberSet, ParameterList, SequenceNumber, SequenceNumberSet};
+    use crate::structure::ParameterId;
     use crate::utils::pad_len;
-    use speedy::{Context, Endianness, Writable};
+    use speedy::{Context, Endianness, Readable, Writable};
 
     #[derive(Clone)]
     struct Shape {
@@ -634,6 +733,15 @@ mod test {
         assert_eq!(v, correct);
     }
 
+    #[test]
+    fn test_fragment_number_set() {
+        let frag_num_set = FragmentNumberSet::from_vec(3, vec![3, 4, 40]);
+        assert!(frag_num_set.is_valid());
+        assert_eq!(frag_num_set.num_bits, 38);
+        assert_eq!(frag_num_set.size(), 16);
+        assert_eq!(frag_num_set.set(), vec![3, 4, 40]);
+    }
+
     #[test]
     fn test_serialize_cdr() {
         let test_shape = Shape {
@@ -652,4 +760,45 @@ mod test {
         let ser = Vec::from(SERIALIZED);
         assert_eq!(test_serialized, ser);
     }
+
+    #[test]
+    fn test_parameter_list_key_hash() {
+        let key_hash = [
+            0x42, 0x4C, 0x55, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let mut param_list = ParameterList::default();
+        param_list.push(ParameterId::PID_KEY_HASH, key_hash.to_vec());
+        let serialized = param_list
+            .write_to_vec_with_ctx(Endianness::LittleEndian)
+            .unwrap();
+        // PID_KEY_HASH(2), length(2), key_hash(16), PID_SENTINEL(2), length(2)
+        assert_eq!(serialized.len(), param_list.serialized_size());
+        assert_eq!(serialized.len(), 24);
+
+        let deserialized =
+            ParameterList::read_from_buffer_with_ctx(Endianness::LittleEndian, &serialized)
+                .unwrap();
+        assert_eq!(
+            deserialized.get(ParameterId::PID_KEY_HASH),
+            Some(key_hash.as_slice())
+        );
+        assert_eq!(deserialized.get(ParameterId::PID_STATUS_INFO), None);
+    }
+
+    #[test]
+    fn test_parameter_list_merge() {
+        let mut param_list = ParameterList::default();
+        param_list.push(ParameterId::PID_STATUS_INFO, vec![0, 0, 0, 1]);
+        let mut other = ParameterList::default();
+        other.push(ParameterId::PID_STATUS_INFO, vec![0, 0, 0, 2]);
+        other.push(ParameterId::PID_TOPIC_NAME, vec![1, 0, 0, 0, 0, 0, 0, 0]);
+        param_list.merge(&other);
+        assert_eq!(
+            param_list.get(ParameterId::PID_STATUS_INFO),
+            Some([0, 0, 0, 1].as_slice())
+        );
+        assert!(param_list.get(ParameterId::PID_TOPIC_NAME).is_some());
+        assert_eq!(param_list.parameter_ids().count(), 2);
+    }
 }
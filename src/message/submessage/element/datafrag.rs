@@ -1,6 +1,7 @@
 use crate::error::{IoError, IoResult};
 use crate::message::submessage::{element::*, submessage_flag::DataFragFlag};
 use crate::structure::EntityId;
+use crate::utils::pad_len;
 use bytes::Bytes;
 use enumflags2::BitFlags;
 use speedy::{Endianness, Error, Readable};
@@ -20,10 +21,35 @@ pub struct DataFrag {
     /// SerializedPayload. The headers at the beginning of SerializedPayload
     /// appear only at the first fragment. The fragmentation mechanism here
     /// should treat serialized_payload as an opaque stream of bytes."
-    pub serialized_payload: SerializedPayload,
+    pub serialized_payload: Bytes,
 }
 
 impl DataFrag {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        reader_id: EntityId,
+        writer_id: EntityId,
+        writer_sn: SequenceNumber,
+        fragment_starting_num: FragmentNumber,
+        fragments_in_submessage: u16,
+        data_size: u32,
+        fragment_size: u16,
+        inline_qos: Option<ParameterList>,
+        serialized_payload: Bytes,
+    ) -> Self {
+        Self {
+            reader_id,
+            writer_id,
+            writer_sn,
+            fragment_starting_num,
+            fragments_in_submessage,
+            data_size,
+            fragment_size,
+            inline_qos,
+            serialized_payload,
+        }
+    }
+
     pub fn deserialize(buffer: &Bytes, flags: BitFlags<DataFragFlag>) -> IoResult<Self> {
         let mut readed_byte = 0;
         let endiannes = if flags.contains(DataFragFlag::Endianness) {
@@ -89,8 +115,8 @@ impl DataFrag {
         };
         // TODO: Validity checks
         //
-        let serialized_payload =
-            SerializedPayload::from_bytes(&buffer.clone().split_off(readed_byte)).unwrap();
+        // the fragment may be followed by padding for alignment of the next submessage
+        let serialized_payload = buffer.slice(readed_byte.min(buffer.len())..);
 
         Ok(Self {
             reader_id,
@@ -108,15 +134,12 @@ impl DataFrag {
 
 impl<C: Context> Writable<C> for DataFrag {
     fn write_to<T: ?Sized + Writer<C>>(&self, writer: &mut T) -> Result<(), C::Error> {
+        // extraFlags
         writer.write_u16(0)?;
-        if self.inline_qos.is_some() && !self.inline_qos.as_ref().unwrap().parameters.is_empty() {
-            todo!()
-        } else if self.inline_qos.is_some()
-            && self.inline_qos.as_ref().unwrap().parameters.is_empty()
-            || self.inline_qos.is_none()
-        {
-            writer.write_u16(24)?;
-        }
+        // octetsToInlineQos
+        // In RTPS 2.3, reader_id(4) + writer_id(4) + writer_sn(8) + fragment_starting_num(4)
+        // + fragments_in_submessage(2) + fragment_size(2) + data_size(4) = 28
+        writer.write_u16(28)?;
         writer.write_value(&self.reader_id)?;
         writer.write_value(&self.writer_id)?;
         writer.write_value(&self.writer_sn)?;
@@ -124,10 +147,13 @@ impl<C: Context> Writable<C> for DataFrag {
         writer.write_value(&self.fragments_in_submessage)?;
         writer.write_value(&self.fragment_size)?;
         writer.write_value(&self.data_size)?;
-        if self.inline_qos.is_some() && !self.inline_qos.as_ref().unwrap().parameters.is_empty() {
-            writer.write_value(&self.inline_qos)?;
+        if let Some(inline_qos) = self.inline_qos.as_ref() {
+            writer.write_value(inline_qos)?;
         }
-        writer.write_value(&self.serialized_payload)?;
+        writer.write_bytes(&self.serialized_payload)?;
+        // padding for alignment of the next submessage
+        const ZEROS: [u8; 3] = [0; 3];
+        writer.write_bytes(&ZEROS[..pad_len(self.serialized_payload.len())])?;
         Ok(())
     }
 }
@@ -10,3 +10,10 @@ pub struct NackFrag {
     pub fragment_number_state: FragmentNumberSet,
     pub count: Count,
 }
+
+impl NackFrag {
+    pub fn is_valid(&self) -> bool {
+        // rtps 2.3 spec, 8.3.7.5.3 Validity
+        self.writer_sn.0 > 0 && self.fragment_number_state.is_valid()
+    }
+}
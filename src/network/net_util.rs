@@ -14,7 +14,6 @@ const D3: u16 = 11;
 
 pub struct UdpMessage {
     pub message: BytesMut,
-    #[allow(dead_code)]
     pub addr: SocketAddr,
 }
 
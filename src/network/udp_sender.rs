@@ -2,14 +2,55 @@ use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use std::net::UdpSocket;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr}; // RustDDS use mio::net::UdpSocket here. I dont'n know why they don't use
                                               // std::net::UdpSocket so, I use std::net::UdpSocket.
-use log::error;
+use alloc::collections::BTreeMap;
+use core::cell::RefCell;
+use log::{error, info};
 use std::io;
 
-pub struct UdpSender {
+/// Priority of the traffic set to the sender sockets
+///
+/// TransportPriority QoS of a DataWriter is mapped to this by `ParticipantConfig::transport_priority_mapping`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SocketPriority {
+    /// Differentiated Services Code Point (6 bits) set to the IP header.
+    ///
+    /// IP_TOS of the socket is set to `dscp << 2`.
+    pub dscp: u8,
+    /// SO_PRIORITY of the socket, which is used by Linux to select the queue of the traffic.
+    ///
+    /// Values greater than 6 require CAP_NET_ADMIN. This is ignored on other platforms.
+    pub so_priority: u32,
+}
+
+/// default mapping from TransportPriority to SocketPriority
+///
+/// TransportPriority 0 (default) uses the sockets without priority.
+/// Other values are used for DSCP and SO_PRIORITY, clamped to 0..=63 and 0..=6 respectively.
+pub fn default_transport_priority_mapping(transport_priority: i32) -> Option<SocketPriority> {
+    if transport_priority == 0 {
+        None
+    } else {
+        Some(SocketPriority {
+            dscp: transport_priority.clamp(0, 63) as u8,
+            so_priority: transport_priority.clamp(0, 6) as u32,
+        })
+    }
+}
+
+struct SenderSockets {
     unicast_socket: UdpSocket,
     multicast_sockets: Vec<UdpSocket>,
 }
 
+pub struct UdpSender {
+    sockets: SenderSockets,
+    local_interfaces: Vec<Ipv4Addr>,
+    /// sender sockets for each SocketPriority, opened when first used
+    ///
+    /// None if failed to open the sockets.
+    priority_sockets: RefCell<BTreeMap<SocketPriority, Option<SenderSockets>>>,
+}
+
 impl UdpSender {
     pub fn new(sender_port: u16, local_interfaces: Vec<Ipv4Addr>) -> io::Result<Self> {
         // if 0.0.0.0 is binded to sender socket, source IP is decided automatic
@@ -22,13 +63,13 @@ impl UdpSender {
 
         let mut multicast_sockets: Vec<UdpSocket> = Vec::new();
         // The DDS implementation sends multicast datagrams to all interfaces because it cannot determine which interfaces the nodes joined to the multicast group are connected to and which they are not.
-        for li in local_interfaces {
+        for li in &local_interfaces {
             let raw_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
                 .expect("failed to open raw_socket");
             raw_socket
-                .set_multicast_if_v4(&li)
+                .set_multicast_if_v4(li)
                 .expect("failed to set multicast_if_v4 to raw_socket");
-            let sockaddr = SockAddr::from(SocketAddr::new(IpAddr::V4(li), 0));
+            let sockaddr = SockAddr::from(SocketAddr::new(IpAddr::V4(*li), 0));
             raw_socket
                 .bind(&sockaddr)
                 .unwrap_or_else(|_| panic!("failed to bind {sockaddr:?} to raw_socket"));
@@ -39,25 +80,105 @@ impl UdpSender {
             multicast_sockets.push(mc_socket);
         }
         Ok(Self {
+            sockets: SenderSockets {
+                unicast_socket,
+                multicast_sockets,
+            },
+            local_interfaces,
+            priority_sockets: RefCell::new(BTreeMap::new()),
+        })
+    }
+
+    fn open_priority_socket(
+        priority: SocketPriority,
+        interface: Option<Ipv4Addr>,
+    ) -> io::Result<UdpSocket> {
+        let raw_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        raw_socket.set_tos((priority.dscp as u32) << 2)?;
+        #[cfg(target_os = "linux")]
+        raw_socket.set_priority(priority.so_priority)?;
+        let sockaddr = match interface {
+            Some(li) => {
+                raw_socket.set_multicast_if_v4(&li)?;
+                SockAddr::from(SocketAddr::new(IpAddr::V4(li), 0))
+            }
+            None => SockAddr::from(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)),
+        };
+        raw_socket.bind(&sockaddr)?;
+        raw_socket.set_multicast_loop_v4(true)?;
+        Ok(UdpSocket::from(raw_socket))
+    }
+
+    fn open_priority_sockets(&self, priority: SocketPriority) -> io::Result<SenderSockets> {
+        let unicast_socket = Self::open_priority_socket(priority, None)?;
+        let mut multicast_sockets = Vec::new();
+        for li in &self.local_interfaces {
+            multicast_sockets.push(Self::open_priority_socket(priority, Some(*li))?);
+        }
+        info!("opened sender sockets with {:?}", priority);
+        Ok(SenderSockets {
             unicast_socket,
             multicast_sockets,
         })
     }
 
-    pub fn send_to_unicast(&self, data: &[u8], addr: Ipv4Addr, port: u16) {
-        if let Err(e) = self.unicast_socket.send_to(data, (addr, port)) {
-            error!("failed to send data to {}:{} because '{:?}'", addr, port, e);
+    /// send data with the sockets of `priority`
+    ///
+    /// If the sockets of `priority` can not be opened, the sockets without priority are used.
+    fn with_sockets<F: Fn(&SenderSockets)>(&self, priority: Option<SocketPriority>, f: F) {
+        let priority = match priority {
+            Some(p) => p,
+            None => return f(&self.sockets),
+        };
+        let mut priority_sockets = self.priority_sockets.borrow_mut();
+        let sockets = priority_sockets.entry(priority).or_insert_with(|| {
+            match self.open_priority_sockets(priority) {
+                Ok(sockets) => Some(sockets),
+                Err(e) => {
+                    error!(
+                        "failed to open sender sockets with {:?} because '{:?}'. use sockets without priority",
+                        priority, e
+                    );
+                    None
+                }
+            }
+        });
+        match sockets {
+            Some(s) => f(s),
+            None => f(&self.sockets),
         }
     }
 
-    pub fn send_to_multicast(&self, data: &[u8], multicast_group: Ipv4Addr, port: u16) {
-        for msocket in &self.multicast_sockets {
-            if let Err(e) = msocket.send_to(data, (multicast_group, port)) {
-                error!(
-                    "failed to send data to {}:{} because '{:?}'",
-                    multicast_group, port, e
-                );
+    pub fn send_to_unicast(
+        &self,
+        data: &[u8],
+        addr: Ipv4Addr,
+        port: u16,
+        priority: Option<SocketPriority>,
+    ) {
+        self.with_sockets(priority, |sockets| {
+            if let Err(e) = sockets.unicast_socket.send_to(data, (addr, port)) {
+                error!("failed to send data to {}:{} because '{:?}'", addr, port, e);
             }
-        }
+        });
+    }
+
+    pub fn send_to_multicast(
+        &self,
+        data: &[u8],
+        multicast_group: Ipv4Addr,
+        port: u16,
+        priority: Option<SocketPriority>,
+    ) {
+        self.with_sockets(priority, |sockets| {
+            for msocket in &sockets.multicast_sockets {
+                if let Err(e) = msocket.send_to(data, (multicast_group, port)) {
+                    error!(
+                        "failed to send data to {}:{} because '{:?}'",
+                        multicast_group, port, e
+                    );
+                }
+            }
+        });
     }
 }
@@ -1,5 +1,11 @@
-use crate::dds::qos::policy::{History, HistoryQosKind, ResourceLimits, LENGTH_UNLIMITED};
-use crate::message::submessage::element::{SequenceNumber, SerializedPayload, Timestamp};
+use crate::dds::key::KeyHash;
+use crate::dds::qos::policy::{
+    DestinationOrder, History, HistoryQosKind, ResourceLimits, LENGTH_UNLIMITED,
+};
+use crate::message::submessage::element::{
+    ParameterList, SequenceNumber, SerializedPayload, Timestamp,
+};
+use crate::rtps::reader::SampleRejectedStatusKind;
 use crate::structure::GUID;
 use alloc::collections::{BTreeMap, BTreeSet};
 use log::{debug, warn};
@@ -9,6 +15,8 @@ use thiserror::Error;
 pub(crate) enum AddChangeErr {
     #[error("add_change blocked: {0}")]
     WouldBlock(String),
+    #[error("add_change rejected: {0:?}")]
+    Rejected(SampleRejectedStatusKind),
 }
 
 #[derive(PartialEq, Eq, Clone)]
@@ -17,8 +25,16 @@ pub struct CacheChange {
     pub writer_guid: GUID,
     pub sequence_number: SequenceNumber,
     pub timestamp: Timestamp,
+    /// Timestamp given by the Writer (INFO_TS).
+    /// Same as `timestamp` if the Writer did not send it.
+    pub source_timestamp: Timestamp,
+    /// SequenceNumber of the first change in the coherent set this change belongs to.
+    /// `SEQUENCENUMBER_UNKNOWN` means the end of a coherent set.
+    pub coherent_set: Option<SequenceNumber>,
     data_value: Option<SerializedPayload>,
-    // inline_qos: ParameterList,
+    /// inline QoS received with the change, or sent with the change in addition to
+    /// PID_KEY_HASH, PID_STATUS_INFO and PID_COHERENT_SET derived from the other fields.
+    pub inline_qos: ParameterList,
     instance_handle: InstantHandle, // In DDS, the value of the fields
                                     // labeled as ‘key’ within the data
                                     // uniquely identify each data-
@@ -39,7 +55,10 @@ impl CacheChange {
             writer_guid,
             sequence_number,
             timestamp,
+            source_timestamp: timestamp,
+            coherent_set: None,
             data_value,
+            inline_qos: ParameterList::default(),
             instance_handle,
         }
     }
@@ -47,6 +66,24 @@ impl CacheChange {
     pub fn data_value(&self) -> Option<&SerializedPayload> {
         self.data_value.as_ref()
     }
+
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    pub fn instance_handle(&self) -> InstantHandle {
+        self.instance_handle
+    }
+
+    pub fn set_instance_handle(&mut self, instance_handle: InstantHandle) {
+        self.instance_handle = instance_handle;
+    }
+
+    /// Whether the change has no data and only notifies the end of a coherent set.
+    pub fn is_coherent_set_end(&self) -> bool {
+        self.data_value.is_none()
+            && self.coherent_set == Some(SequenceNumber::SEQUENCENUMBER_UNKNOWN)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Copy)]
@@ -113,12 +150,86 @@ pub enum ChangeKind {
     Alive,
     _AliveFiltered,
     _NotAlive,
-    _NotAliveDisposed,
-    _NotAliveUnregistered,
+    NotAliveDisposed,
+    NotAliveUnregistered,
+    /// rtps 2.5 spec, 8.2.1.2 Types used by the RTPS Entities
+    NotAliveDisposedUnregistered,
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
-pub struct InstantHandle {/* TODO */}
+impl ChangeKind {
+    /// rtps 2.3 spec, 9.6.3.9 StatusInfo_t (PID_STATUS_INFO)
+    pub const STATUS_INFO_DISPOSED: u8 = 0x01;
+    pub const STATUS_INFO_UNREGISTERED: u8 = 0x02;
+
+    /// flags of StatusInfo_t which represent the kind
+    pub fn status_info(&self) -> u8 {
+        match self {
+            Self::NotAliveDisposed => Self::STATUS_INFO_DISPOSED,
+            Self::NotAliveUnregistered => Self::STATUS_INFO_UNREGISTERED,
+            Self::NotAliveDisposedUnregistered => {
+                Self::STATUS_INFO_DISPOSED | Self::STATUS_INFO_UNREGISTERED
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn from_status_info(flags: u8) -> Self {
+        let disposed = flags & Self::STATUS_INFO_DISPOSED != 0;
+        let unregistered = flags & Self::STATUS_INFO_UNREGISTERED != 0;
+        match (disposed, unregistered) {
+            (true, true) => Self::NotAliveDisposedUnregistered,
+            (true, false) => Self::NotAliveDisposed,
+            (false, true) => Self::NotAliveUnregistered,
+            (false, false) => Self::Alive,
+        }
+    }
+
+    pub fn is_disposed(&self) -> bool {
+        matches!(
+            self,
+            Self::NotAliveDisposed | Self::NotAliveDisposedUnregistered
+        )
+    }
+
+    pub fn is_unregistered(&self) -> bool {
+        matches!(
+            self,
+            Self::NotAliveUnregistered | Self::NotAliveDisposedUnregistered
+        )
+    }
+}
+
+/// Identifies the instance (the data-object with the same key) a change belongs to.
+///
+/// The handle holds the KeyHash of the instance.
+/// Changes of a keyless Topic, or changes whose key is unknown, have `InstantHandle::NIL`.
+#[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Debug, Default)]
+pub struct InstantHandle {
+    key_hash: [u8; 16],
+}
+
+impl InstantHandle {
+    pub const NIL: Self = Self { key_hash: [0; 16] };
+
+    pub fn new(key_hash: [u8; 16]) -> Self {
+        Self { key_hash }
+    }
+
+    pub fn from_key_hash(key_hash: Option<KeyHash>) -> Self {
+        match key_hash {
+            Some(kh) => Self::new(kh.value()),
+            None => Self::NIL,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        *self == Self::NIL
+    }
+
+    pub fn key_hash(&self) -> [u8; 16] {
+        self.key_hash
+    }
+}
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct HCKey {
@@ -168,6 +279,8 @@ pub(crate) struct HistoryCache {
     pub changes: BTreeMap<HCKey, CacheChange>,
     pub ts2key: Vec<HCKey>,
     kind2key: BTreeMap<ChangeKind, BTreeSet<HCKey>>,
+    /// keys of changes of each instance in the order they were added
+    instance2key: BTreeMap<InstantHandle, Vec<HCKey>>,
     hc_type: HistoryCacheType,
     /// only use type Writer
     /// rtps 2.3 spec, 8.4.1.1 Example Behavior assumes
@@ -183,6 +296,17 @@ pub(crate) struct HistoryCache {
     taken_key: BTreeSet<HCKey>,
     /// only use type Reader
     ready_key: BTreeSet<HCKey>,
+    /// only use type Reader
+    /// Changes in coherent sets which are not completed yet.
+    /// They are not made ready by `flush`.
+    held_key: BTreeSet<HCKey>,
+    /// only use type Reader
+    /// While the Subscriber is between `begin_access` and `end_access`,
+    /// flushed changes are buffered here and made ready at `end_access`.
+    is_accessing: bool,
+    pending_ready_key: BTreeSet<HCKey>,
+    /// the changes which only notify the end of a coherent set
+    coherent_set_end_key: BTreeSet<HCKey>,
     pub last_added: BTreeMap<GUID, Timestamp>,
     min_seq_num: Option<SequenceNumber>,
     max_seq_num: Option<SequenceNumber>,
@@ -204,11 +328,16 @@ impl HistoryCache {
             changes: BTreeMap::new(),
             ts2key: Vec::new(),
             kind2key: BTreeMap::new(),
+            instance2key: BTreeMap::new(),
             last_added: BTreeMap::new(),
             hc_type,
             unprocessed_seqnum: BTreeSet::new(),
             taken_key: BTreeSet::new(),
             ready_key: BTreeSet::new(),
+            held_key: BTreeSet::new(),
+            is_accessing: false,
+            pending_ready_key: BTreeSet::new(),
+            coherent_set_end_key: BTreeSet::new(),
             min_seq_num: None,
             max_seq_num: None,
         }
@@ -257,72 +386,95 @@ impl HistoryCache {
                 */
             }
         } else {
-            let max_samples = resource_limits.max_samples;
-            if max_samples != LENGTH_UNLIMITED && self.changes.len() + 1 >= max_samples as usize {
-                // reach ResourceLimits
-                // DDS v1.4 spec, 2.2.3.19 RESOURCE_LIMITS
-                // The behavior in this case depends on the setting for the RELIABILITY QoS.
-                // If reliability is BEST_EFFORT then the Service is allowed to drop samples.
-                // If the reliability is RELIABLE, the Service will block the DataWriter or
-                // discard the sample at the DataReader 28 in order not to lose existing samples.
-                match self.hc_type {
-                    HistoryCacheType::Writer => {
-                        if is_reliable {
-                            // block until some change removed from self
-                            // if block here, nobody can access self.
-                            return Err(AddChangeErr::WouldBlock(
-                                "resource_limits.max_samples reached".to_string(),
-                            ));
-                        } else {
-                            // remove oldest sample
-                            warn!("BestEffort Writer HistoryCache reached ResourceLimits, remove {:?}", self.ts2key[0]);
-                            self.remove_change(&self.ts2key[0].clone(), false);
-                        }
-                    }
-                    HistoryCacheType::Reader => {
-                        if is_reliable {
-                            // discard change
-                            return Ok(());
-                        } else {
-                            // remove oldest sample
-                            warn!(
-                                "BestEffort Reader HistoryCache reached ResourceLimits, remove {:?}",
-                                self.ts2key[0]
-                            );
-                            self.remove_change(&self.ts2key[0].clone(), false);
-                        }
-                    }
-                    HistoryCacheType::Dummy => unreachable!(),
+            let instance = change.instance_handle;
+            // the end of a coherent set belongs to no instance, so it is excluded from
+            // HistoryQosKind::KeepLast and ResourceLimits not to evict the changes in the set.
+            let is_coherent_set_end = change.is_coherent_set_end();
+            if !is_coherent_set_end && history.kind == HistoryQosKind::KeepLast {
+                // DDS 1.4 sepc, 2.2.3.18 HISTORY
+                // > If the kind is set to KEEP_LAST, then the Service will only attempt to keep the latest values of the instance and discard the older ones.
+                //
+                // keep the latest hdepth changes of the instance including the change to be added
+                let hdepth = history.depth.max(1) as usize;
+                let instance_len = self.instance_len(instance);
+                if instance_len >= hdepth {
+                    let removable = self.removable_keys_of_instance(instance);
+                    removable
+                        .iter()
+                        .take(instance_len + 1 - hdepth)
+                        .for_each(|key| {
+                            debug!("remove change with {} from {} HistoryCache due to HistoryQosKind::KeepLast", key, self.hc_type);
+                            self.remove_change(key, false);
+                        });
                 }
             }
+            // DDS v1.4 spec, 2.2.3.19 RESOURCE_LIMITS
+            let max_instance = resource_limits.max_instance;
+            if max_instance != LENGTH_UNLIMITED
+                && !is_coherent_set_end
+                && self.instance_len(instance) == 0
+                && self.instance2key.len() >= max_instance as usize
+            {
+                self.reach_resource_limits(
+                    is_reliable,
+                    SampleRejectedStatusKind::RejectedByInstancesLimit,
+                    None,
+                )?;
+            }
+            let max_samples_per_instanse = resource_limits.max_samples_per_instanse;
+            if max_samples_per_instanse != LENGTH_UNLIMITED
+                && !is_coherent_set_end
+                && self.instance_len(instance) >= max_samples_per_instanse as usize
+            {
+                let oldest = self.removable_keys_of_instance(instance).first().copied();
+                self.reach_resource_limits(
+                    is_reliable,
+                    SampleRejectedStatusKind::RejectedBySamplesPerInstanceLimit,
+                    oldest,
+                )?;
+            }
+            let max_samples = resource_limits.max_samples;
+            if max_samples != LENGTH_UNLIMITED
+                && !is_coherent_set_end
+                && self.changes.len() >= max_samples as usize
+            {
+                let oldest = self
+                    .ts2key
+                    .iter()
+                    .find(|k| !self.unprocessed_seqnum.contains(&k.seq_num))
+                    .copied();
+                self.reach_resource_limits(
+                    is_reliable,
+                    SampleRejectedStatusKind::RejectedBySamplesLimit,
+                    oldest,
+                )?;
+            }
+            // The change ends the preceding coherent set by itself, so the ends of the coherent sets
+            // before it are no longer needed. The Reader receives GAP for them.
+            // This keeps at most one end of a coherent set for each Writer in the HistoryCache.
+            let superseded: Vec<HCKey> = self
+                .coherent_set_end_key
+                .iter()
+                .filter(|k| k.guid == key.guid && k.seq_num < seq_num)
+                .copied()
+                .collect();
+            superseded.iter().for_each(|k| {
+                debug!(
+                    "remove the end of a coherent set with {} from {} HistoryCache",
+                    k, self.hc_type
+                );
+                self.remove_change(k, false);
+            });
             self.last_added.insert(key.guid, change.timestamp);
             self.ts2key.push(key);
             self.kind2key.entry(change.kind).or_default().insert(key);
+            if is_coherent_set_end {
+                self.coherent_set_end_key.insert(key);
+            } else {
+                self.instance2key.entry(instance).or_default().push(key);
+            }
             self.changes.insert(key, change);
             debug!("add change with {} to {} HistoryCache", key, self.hc_type);
-            if let HistoryCacheType::Reader = self.hc_type {
-                if history.kind == HistoryQosKind::KeepLast {
-                    // DDS 1.4 sepc, 2.2.3.18 HISTORY
-                    // > If the kind is set to KEEP_LAST, then the Service will only attempt to keep the latest values of the instance and discard the older ones.↲
-                    //
-                    // Umber DDS do not implement instance.
-                    // So, `instance = Topic` in this implementation.
-                    //
-                    // keep the hdepth largest keys and delete the rest
-                    let hdepth = history.depth;
-                    let todo_delete: Vec<HCKey> = self
-                        .changes
-                        .keys()
-                        .rev()
-                        .skip(hdepth as usize)
-                        .cloned()
-                        .collect();
-                    todo_delete.iter().for_each(|key| {
-                        debug!("remove change with {} from {} HistoryCache due to HistoryQosKind::KeepLast", key, self.hc_type);
-                        self.remove_change(key, false);
-                    });
-                }
-            }
             if let HistoryCacheType::Writer = self.hc_type {
                 debug!(
                     "add change with {} to unprocessed_seqnum in {} HistoryCache",
@@ -334,6 +486,72 @@ impl HistoryCache {
         }
     }
 
+    fn instance_len(&self, instance: InstantHandle) -> usize {
+        self.instance2key
+            .get(&instance)
+            .map_or(0, |keys| keys.len())
+    }
+
+    /// keys of the changes of the instance which can be removed, from the oldest one
+    fn removable_keys_of_instance(&self, instance: InstantHandle) -> Vec<HCKey> {
+        self.instance2key
+            .get(&instance)
+            .map(|keys| {
+                keys.iter()
+                    .filter(|k| !self.unprocessed_seqnum.contains(&k.seq_num))
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The change to be added reaches ResourceLimits.
+    ///
+    /// DDS v1.4 spec, 2.2.3.19 RESOURCE_LIMITS
+    /// The behavior in this case depends on the setting for the RELIABILITY QoS.
+    /// If reliability is BEST_EFFORT then the Service is allowed to drop samples.
+    /// If the reliability is RELIABLE, the Service will block the DataWriter or
+    /// discard the sample at the DataReader in order not to lose existing samples.
+    ///
+    /// + oldest: the change which can be dropped to make room for the change to be added.
+    fn reach_resource_limits(
+        &mut self,
+        is_reliable: bool,
+        reason: SampleRejectedStatusKind,
+        oldest: Option<HCKey>,
+    ) -> Result<(), AddChangeErr> {
+        match (&self.hc_type, oldest) {
+            (HistoryCacheType::Writer, Some(oldest)) if !is_reliable => {
+                // remove oldest sample
+                warn!(
+                    "BestEffort Writer HistoryCache reached ResourceLimits ({:?}), remove {}",
+                    reason, oldest
+                );
+                self.remove_change(&oldest, false);
+                Ok(())
+            }
+            (HistoryCacheType::Writer, _) => {
+                // block until some change removed from self
+                // if block here, nobody can access self.
+                Err(AddChangeErr::WouldBlock(format!("{reason:?}")))
+            }
+            (HistoryCacheType::Reader, Some(oldest)) if !is_reliable => {
+                // remove oldest sample
+                warn!(
+                    "BestEffort Reader HistoryCache reached ResourceLimits ({:?}), remove {}",
+                    reason, oldest
+                );
+                self.remove_change(&oldest, false);
+                Ok(())
+            }
+            (HistoryCacheType::Reader, _) => {
+                // discard change
+                Err(AddChangeErr::Rejected(reason))
+            }
+            (HistoryCacheType::Dummy, _) => unreachable!(),
+        }
+    }
+
     pub fn get_unprocessed(&mut self) -> BTreeSet<SequenceNumber> {
         if let HistoryCacheType::Writer = self.hc_type {
             core::mem::take(&mut self.unprocessed_seqnum)
@@ -342,6 +560,12 @@ impl HistoryCache {
         }
     }
 
+    /// whether the change has been added by the DataWriter but not taken by the RTPS Writer yet,
+    /// e.g. while the publications of the Publisher are suspended
+    pub fn is_unprocessed(&self, seq_num: SequenceNumber) -> bool {
+        self.unprocessed_seqnum.contains(&seq_num)
+    }
+
     /// for BestEffort Reader
     /// Returns the keys of Changes taken from the DataReader
     pub fn get_taken(&mut self) -> BTreeSet<HCKey> {
@@ -387,7 +611,14 @@ impl HistoryCache {
         if let HistoryCacheType::Reader = self.hc_type {
             let mut is_flushed = false;
             for key in self.changes.keys() {
-                self.ready_key.insert(*key);
+                if self.held_key.contains(key) {
+                    continue;
+                }
+                if self.is_accessing {
+                    self.pending_ready_key.insert(*key);
+                } else {
+                    self.ready_key.insert(*key);
+                }
                 is_flushed = true;
             }
             is_flushed
@@ -396,20 +627,58 @@ impl HistoryCache {
         }
     }
 
-    pub fn get_ready_changes(&self) -> (Vec<HCKey>, Vec<&CacheChange>) {
-        if let Some(keys) = self.kind2key.get(&ChangeKind::Alive) {
-            let mut res: Vec<(HCKey, &CacheChange)> = keys
-                .iter()
-                .filter(|k| self.ready_key.contains(k))
-                .map(|k| (*k, self.changes.get(k).unwrap_or_else(|| panic!("Access to HistoryCache changes occurs for keys included in kind2key but not in changes: {}", k))))
-                .collect();
-            res.sort_by_key(|(key, _cache)| *key);
-            res.into_iter().unzip()
-        } else {
-            (Vec::new(), Vec::new())
+    /// for Reader
+    /// Hold the change until its coherent set is completed.
+    pub fn hold(&mut self, key: HCKey) {
+        self.held_key.insert(key);
+    }
+
+    /// for Reader
+    /// Release the changes in a completed coherent set.
+    /// They are made ready by the next `flush`.
+    pub fn release(&mut self, keys: &[HCKey]) {
+        for key in keys {
+            self.held_key.remove(key);
         }
     }
 
+    /// for Reader
+    /// DDS 1.4 spec, 2.2.2.5.2.8 begin_access
+    pub fn begin_access(&mut self) {
+        self.is_accessing = true;
+    }
+
+    /// for Reader
+    /// DDS 1.4 spec, 2.2.2.5.2.9 end_access
+    pub fn end_access(&mut self) {
+        self.is_accessing = false;
+        let pending = core::mem::take(&mut self.pending_ready_key);
+        self.ready_key.extend(pending);
+    }
+
+    /// Returns the changes ready to be taken.
+    /// The changes which are not ALIVE are also returned, so that the DataReader can notify
+    /// the disposal or unregistration of the instance.
+    /// They are ordered by source timestamp if `destination_order` is BySourceTimestamp,
+    /// and otherwise by SequenceNumber.
+    pub fn get_ready_changes(
+        &self,
+        destination_order: DestinationOrder,
+    ) -> (Vec<HCKey>, Vec<&CacheChange>) {
+        let mut res: Vec<(HCKey, &CacheChange)> = self
+            .ready_key
+            .iter()
+            .map(|k| (*k, self.changes.get(k).unwrap_or_else(|| panic!("Access to HistoryCache changes occurs for keys included in ready_key but not in changes: {}", k))))
+            .collect();
+        match destination_order {
+            DestinationOrder::ByReceptionTimestamp => res.sort_by_key(|(key, _cache)| *key),
+            DestinationOrder::BySourceTimestamp => {
+                res.sort_by_key(|(key, cache)| (cache.source_timestamp, *key))
+            }
+        }
+        res.into_iter().unzip()
+    }
+
     /*
     pub fn get_alive_changes(&self) -> (Vec<HCKey>, Vec<&CacheChange>) {
         /*
@@ -442,6 +711,21 @@ impl HistoryCache {
         }
     }
 
+    /// remove all changes of the instance
+    pub fn remove_instance(&mut self, instance_handle: InstantHandle) {
+        if let HistoryCacheType::Reader = self.hc_type {
+            let todo_remove: Vec<HCKey> = self
+                .changes
+                .iter()
+                .filter(|(_k, c)| c.instance_handle() == instance_handle)
+                .map(|(k, _c)| *k)
+                .collect();
+            todo_remove
+                .iter()
+                .for_each(|k| self.remove_change(k, false));
+        }
+    }
+
     pub fn remove_change_if_exist(&mut self, key: &HCKey) {
         if self.changes.contains_key(key) {
             self.remove_change(key, false);
@@ -463,6 +747,15 @@ impl HistoryCache {
                     self.taken_key.insert(*key);
                 }
                 self.ready_key.remove(key);
+                self.held_key.remove(key);
+                self.pending_ready_key.remove(key);
+            }
+            self.coherent_set_end_key.remove(key);
+            if let Some(v) = self.instance2key.get_mut(&c.instance_handle) {
+                v.retain(|k| k != key);
+                if v.is_empty() {
+                    self.instance2key.remove(&c.instance_handle);
+                }
             }
             if let Some(v) = self.kind2key.get_mut(&c.kind) {
                 if !v.remove(key) {
@@ -522,3 +815,93 @@ impl HistoryCache {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn change(seq_num: i64, instance: u8) -> CacheChange {
+        CacheChange::new(
+            ChangeKind::Alive,
+            GUID::UNKNOW,
+            SequenceNumber(seq_num),
+            Timestamp::now().expect("failed to get Timestamp::now()"),
+            None,
+            InstantHandle::new([instance; 16]),
+        )
+    }
+
+    #[test]
+    fn test_keep_last_per_instance() {
+        let mut hc = HistoryCache::new(HistoryCacheType::Reader);
+        let history = History::new(HistoryQosKind::KeepLast, 1);
+        for (seq_num, instance) in [(1, 1), (2, 2), (3, 1), (4, 1)] {
+            hc.add_change(
+                change(seq_num, instance),
+                true,
+                ResourceLimits::default(),
+                history,
+            )
+            .unwrap();
+        }
+        let seq_nums: Vec<i64> = hc.changes.keys().map(|k| k.seq_num.0).collect();
+        assert_eq!(seq_nums, vec![2, 4]);
+    }
+
+    #[test]
+    fn test_resource_limits_per_instance() {
+        let mut hc = HistoryCache::new(HistoryCacheType::Reader);
+        let history = History::new(HistoryQosKind::KeepAll, 0);
+        let resource_limits = ResourceLimits {
+            max_samples: LENGTH_UNLIMITED,
+            max_instance: 1,
+            max_samples_per_instanse: 2,
+        };
+        hc.add_change(change(1, 1), true, resource_limits, history)
+            .unwrap();
+        hc.add_change(change(2, 1), true, resource_limits, history)
+            .unwrap();
+        assert!(matches!(
+            hc.add_change(change(3, 1), true, resource_limits, history),
+            Err(AddChangeErr::Rejected(
+                SampleRejectedStatusKind::RejectedBySamplesPerInstanceLimit
+            ))
+        ));
+        assert!(matches!(
+            hc.add_change(change(4, 2), true, resource_limits, history),
+            Err(AddChangeErr::Rejected(
+                SampleRejectedStatusKind::RejectedByInstancesLimit
+            ))
+        ));
+        // BestEffort Reader drops the oldest sample of the instance
+        hc.add_change(change(5, 1), false, resource_limits, history)
+            .unwrap();
+        let seq_nums: Vec<i64> = hc.changes.keys().map(|k| k.seq_num.0).collect();
+        assert_eq!(seq_nums, vec![2, 5]);
+    }
+
+    #[test]
+    fn test_coherent_set_end_keeps_changes() {
+        let mut hc = HistoryCache::new(HistoryCacheType::Reader);
+        let history = History::new(HistoryQosKind::KeepLast, 1);
+        // instance 0 is InstantHandle::NIL
+        let mut data = change(1, 0);
+        data.coherent_set = Some(SequenceNumber(1));
+        hc.add_change(data, true, ResourceLimits::default(), history)
+            .unwrap();
+        let mut end = change(2, 0);
+        end.coherent_set = Some(SequenceNumber::SEQUENCENUMBER_UNKNOWN);
+        hc.add_change(end, true, ResourceLimits::default(), history)
+            .unwrap();
+        let seq_nums: Vec<i64> = hc.changes.keys().map(|k| k.seq_num.0).collect();
+        assert_eq!(seq_nums, vec![1, 2]);
+
+        // the next change ends the set by itself, so the end of the set is removed
+        let mut data = change(3, 1);
+        data.coherent_set = Some(SequenceNumber(3));
+        hc.add_change(data, true, ResourceLimits::default(), history)
+            .unwrap();
+        let seq_nums: Vec<i64> = hc.changes.keys().map(|k| k.seq_num.0).collect();
+        assert_eq!(seq_nums, vec![1, 3]);
+    }
+}
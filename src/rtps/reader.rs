@@ -1,5 +1,9 @@
 use crate::dds::{
-    qos::{policy::ReliabilityQosKind, DataReaderQosPolicies, DataWriterQosPolicies},
+    key::KeyHash,
+    qos::{
+        policy::{DestinationOrder, Ownership, Partition, Presentation, ReliabilityQosKind},
+        DataReaderQosPolicies, DataWriterQosPolicies,
+    },
     Topic,
 };
 use crate::discovery::{
@@ -8,11 +12,16 @@ use crate::discovery::{
 };
 use crate::message::message_builder::MessageBuilder;
 use crate::message::submessage::{
-    element::{Gap, Heartbeat, Locator, SequenceNumber, SequenceNumberSet, Timestamp},
+    element::{
+        Count, DataFrag, FragmentNumber, FragmentNumberSet, Gap, Heartbeat, HeartbeatFrag, Locator,
+        SequenceNumber, SequenceNumberSet, SerializedPayload, Timestamp,
+    },
     submessage_flag::HeartbeatFlag,
 };
 use crate::network::udp_sender::UdpSender;
-use crate::rtps::cache::{CacheChange, HCKey, HistoryCache, HistoryCacheType};
+use crate::rtps::cache::{
+    AddChangeErr, CacheChange, ChangeKind, HCKey, HistoryCache, HistoryCacheType, InstantHandle,
+};
 use crate::structure::{
     Duration, EntityId, GuidPrefix, RTPSEntity, ReaderProxy, TopicKind, WriterProxy, GUID,
 };
@@ -20,6 +29,8 @@ use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::rc::Rc;
 use alloc::sync::Arc;
 use awkernel_sync::rwlock::RwLock;
+use bytes::Bytes;
+use core::cmp::min;
 use core::net::Ipv4Addr;
 use core::time::Duration as StdDuration;
 use core::time::Duration as CoreDuration;
@@ -29,17 +40,192 @@ use mio_extras::channel as mio_channel;
 use speedy::{Endianness, Writable};
 
 pub enum ReaderTimer {
-    Heartbeat(EntityId, GUID),              // self.entity_id, Writer GUID
-    Deadline(EntityId, GUID, CoreDuration), // self.entity_id, Writer GUID, deadline.period
+    Heartbeat(EntityId, GUID), // self.entity_id, Writer GUID
+    Deadline(EntityId, InstantHandle, CoreDuration), // self.entity_id, instance, deadline.period
+    CancelDeadline(EntityId, InstantHandle), // self.entity_id, instance
     Lifespan(EntityId, HCKey, Timestamp, CoreDuration), // self.entity_id, HCKey of the change, source Timestamp, lifespan.period
+    Autopurge(EntityId, InstantHandle, Timestamp, CoreDuration), // self.entity_id, instance, Timestamp of the state change, autopurge delay
+    Fragment(EntityId, HCKey, Timestamp), // self.entity_id, HCKey of the fragmented change, reception Timestamp of the first fragment
 }
 
+/// maximum size of a SerializedPayload reassembled from DATA_FRAG
+const MAX_FRAGMENTED_DATA_SIZE: u32 = 64 * 1024 * 1024;
+/// maximum number of changes reassembled at the same time for each Writer
+const MAX_FRAGMENTED_CHANGES_PER_WRITER: usize = 16;
+/// maximum total size of the fragments buffered for reassembly in each Reader
+const MAX_REASSEMBLY_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+/// a change whose fragments are not all received in this duration is discarded
+pub const FRAGMENT_REASSEMBLY_TIMEOUT: CoreDuration = CoreDuration::from_secs(10);
+
 enum ReaderState {
     Initial,
     Waiting(BTreeSet<SequenceNumber>),
     Expect(SequenceNumber),
 }
 
+/// DDS 1.4 spec, 2.2.2.5.1.3 instance_state
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum InstanceStateKind {
+    Alive,
+    NotAliveDisposed,
+    NotAliveNoWriters,
+}
+
+struct InstanceState {
+    kind: InstanceStateKind,
+    /// Writers which have written the instance and have not unregistered it
+    writers: BTreeSet<GUID>,
+    /// time when the kind was changed last
+    changed_at: Timestamp,
+}
+
+/// rtps 2.3 spec, 8.7.5 Coherent Sets
+#[derive(Default)]
+struct CoherentSet {
+    /// SequenceNumber of the first change after the set.
+    /// It is known when the Reader receives a change which is not in the set.
+    end: Option<SequenceNumber>,
+    /// SequenceNumbers in the set received by the Reader,
+    /// including changes which are not added to the HistoryCache.
+    received: BTreeSet<SequenceNumber>,
+    /// keys of changes in the set held in the HistoryCache
+    keys: Vec<HCKey>,
+}
+
+/// DDS 1.4 spec, 2.2.3.9 OWNERSHIP
+/// Writer which owns each instance, used for EXCLUSIVE Ownership
+#[derive(Default)]
+struct InstanceOwners {
+    owners: BTreeMap<InstantHandle, GUID>,
+}
+
+impl InstanceOwners {
+    /// Return true if the Writer owns the instance after the arbitration.
+    /// Each instance is owned by the alive Writer with the highest strength.
+    /// If the strengths are the same, the Writer with the smaller GUID owns the instance.
+    /// + strength_of: the strength of the Writer, None if the Writer is no longer alive.
+    fn arbitrate(
+        &mut self,
+        instance: InstantHandle,
+        writer_guid: GUID,
+        strength_of: impl Fn(GUID) -> Option<i32>,
+    ) -> bool {
+        let Some(writer_strength) = strength_of(writer_guid) else {
+            return false;
+        };
+        let is_owner = match self.owners.get(&instance) {
+            Some(owner) if *owner == writer_guid => true,
+            Some(owner) => match strength_of(*owner) {
+                Some(owner_strength) => {
+                    writer_strength > owner_strength
+                        || (writer_strength == owner_strength && writer_guid < *owner)
+                }
+                // the owner is no longer alive
+                None => true,
+            },
+            None => true,
+        };
+        if is_owner {
+            self.owners.insert(instance, writer_guid);
+        }
+        is_owner
+    }
+
+    /// release the instance so that the next Writer writing it gets ownership
+    fn release_instance(&mut self, instance: InstantHandle) {
+        self.owners.remove(&instance);
+    }
+
+    /// release all instances owned by the Writer
+    fn release_writer(&mut self, writer_guid: GUID) {
+        self.owners.retain(|_, owner| *owner != writer_guid);
+    }
+}
+
+/// rtps 2.3 spec, 8.4.14.1 Large Data
+/// a change received by DATA_FRAG which is not reassembled yet
+struct FragmentedChange {
+    /// the change carried by the fragments, without SerializedPayload
+    change: CacheChange,
+    /// size of SerializedPayload including the encapsulation header
+    data_size: u32,
+    fragment_size: u16,
+    /// received fragments, the buffer is allocated for each fragment
+    fragments: BTreeMap<FragmentNumber, Bytes>,
+}
+
+impl FragmentedChange {
+    fn new(change: CacheChange, data_size: u32, fragment_size: u16) -> Self {
+        Self {
+            change,
+            data_size,
+            fragment_size,
+            fragments: BTreeMap::new(),
+        }
+    }
+
+    fn fragment_count(&self) -> u32 {
+        self.data_size.div_ceil(self.fragment_size as u32)
+    }
+
+    /// size of the fragments buffered for reassembly
+    fn buffered_size(&self) -> usize {
+        self.fragments.values().map(|f| f.len()).sum()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.fragments.len() as u32 == self.fragment_count()
+    }
+
+    /// store the fragments in `serialized_payload` which starts from `fragment_starting_num`
+    fn add_fragments(
+        &mut self,
+        fragment_starting_num: FragmentNumber,
+        fragments_in_submessage: u16,
+        serialized_payload: &[u8],
+    ) {
+        let fragment_size = self.fragment_size as usize;
+        for i in 0..fragments_in_submessage as usize {
+            let frag_num = fragment_starting_num.saturating_add(i as FragmentNumber);
+            if frag_num == 0 || frag_num > self.fragment_count() {
+                break;
+            }
+            if self.fragments.contains_key(&frag_num) {
+                // duplicate fragment
+                continue;
+            }
+            let start = (frag_num as usize - 1) * fragment_size;
+            let len = min(fragment_size, self.data_size as usize - start);
+            // the serialized_payload of DATA_FRAG may be followed by padding
+            let src_start = i * fragment_size;
+            let src_end = src_start + len;
+            if src_end > serialized_payload.len() {
+                break;
+            }
+            self.fragments.insert(
+                frag_num,
+                Bytes::copy_from_slice(&serialized_payload[src_start..src_end]),
+            );
+        }
+    }
+
+    /// concatenate the fragments, must be called after all fragments are received
+    fn assemble(&self) -> Bytes {
+        let mut payload = Vec::with_capacity(self.data_size as usize);
+        for fragment in self.fragments.values() {
+            payload.extend_from_slice(fragment);
+        }
+        Bytes::from(payload)
+    }
+
+    /// fragments up to `last_fragment_num` not received yet
+    fn missing_fragments(&self, last_fragment_num: FragmentNumber) -> Vec<FragmentNumber> {
+        (1..=min(last_fragment_num, self.fragment_count()))
+            .filter(|frag_num| !self.fragments.contains_key(frag_num))
+            .collect()
+    }
+}
+
 /// RTPS StatefulReader
 pub struct Reader {
     // Entity
@@ -59,15 +245,48 @@ pub struct Reader {
     // This implementation spesific
     topic: Topic,
     qos: DataReaderQosPolicies,
+    /// Partition of the Subscriber this Reader belongs to
+    partition: Partition,
+    /// Presentation of the Subscriber this Reader belongs to
+    presentation: Presentation,
+    /// Writers which match except for Partition.
+    /// They are re-evaluated when the Partition is changed.
+    partition_mismatched_writers: BTreeMap<GUID, WriterProxy>,
     endianness: Endianness,
     reader_state_notifier: mio_channel::Sender<DataReaderStatusChanged>,
     udp_sender: Rc<UdpSender>,
+    disc_db: DiscoveryDB,
     // for reodering
     writer_communication_state: BTreeMap<GUID, ReaderState>,
+    /// reception time of the last change delivered for each instance
+    /// used for TimeBasedFilter
+    last_delivered_ts: BTreeMap<InstantHandle, Timestamp>,
+    /// source timestamp of the last change delivered for each instance
+    /// used for DestinationOrder BySourceTimestamp
+    last_delivered_source_ts: BTreeMap<InstantHandle, Timestamp>,
+    /// Writer which owns each instance
+    /// used for EXCLUSIVE Ownership
+    instance_owners: InstanceOwners,
+    /// coherent sets not completed yet for each Writer, keyed by the first SequenceNumber of the set
+    coherent_sets: BTreeMap<GUID, BTreeMap<SequenceNumber, CoherentSet>>,
+    /// state of each instance
+    /// used for ReaderDataLifecycle
+    instances: BTreeMap<InstantHandle, InstanceState>,
+    /// Autopurge and CancelDeadline timers which are not set yet.
+    /// instance_state can be changed on many paths, so these are taken by the EventLoop.
+    pending_timers: Vec<ReaderTimer>,
+    /// total number of samples rejected by ResourceLimits
+    sample_rejected_count: i32,
+    requested_deadline_missed_count: i32,
+    /// changes being reassembled from DATA_FRAG
+    fragmented_changes: BTreeMap<HCKey, FragmentedChange>,
+    nack_frag_count: Count,
+    /// KeyHash of the SerializedPayload, computed with the type of the DataReader
+    payload_key_hash: fn(&SerializedPayload, bool) -> Option<KeyHash>,
 }
 
 impl Reader {
-    pub fn new(ri: ReaderIngredients, udp_sender: Rc<UdpSender>) -> Self {
+    pub fn new(ri: ReaderIngredients, udp_sender: Rc<UdpSender>, disc_db: DiscoveryDB) -> Self {
         let mut msg = String::new();
         msg += "\tunicast locators\n";
         for loc in &ri.unicast_locator_list {
@@ -97,10 +316,25 @@ impl Reader {
             unmatched_writers: BTreeMap::new(),
             topic: ri.topic,
             qos: ri.qos,
+            partition: ri.partition,
+            presentation: ri.presentation,
+            partition_mismatched_writers: BTreeMap::new(),
             endianness: Endianness::LittleEndian,
             reader_state_notifier: ri.reader_state_notifier,
+            payload_key_hash: ri.payload_key_hash,
             udp_sender,
+            disc_db,
             writer_communication_state: BTreeMap::new(),
+            last_delivered_ts: BTreeMap::new(),
+            last_delivered_source_ts: BTreeMap::new(),
+            instance_owners: InstanceOwners::default(),
+            coherent_sets: BTreeMap::new(),
+            instances: BTreeMap::new(),
+            pending_timers: Vec::new(),
+            sample_rejected_count: 0,
+            requested_deadline_missed_count: 0,
+            fragmented_changes: BTreeMap::new(),
+            nack_frag_count: 0,
         }
     }
 
@@ -116,7 +350,7 @@ impl Reader {
     }
 
     pub fn sedp_data(&self) -> DiscoveredReaderData {
-        let proxy = ReaderProxy::new(
+        let mut proxy = ReaderProxy::new(
             self.guid,
             self.expectsinline_qos,
             self.unicast_locator_list.clone(),
@@ -127,6 +361,7 @@ impl Reader {
             Arc::new(RwLock::new(HistoryCache::new(HistoryCacheType::Dummy))),
             true,
         );
+        proxy.partition = self.partition.clone();
         let sub_data = self.topic.sub_builtin_topic_data();
         DiscoveredReaderData::new(proxy, sub_data)
     }
@@ -134,7 +369,7 @@ impl Reader {
     pub fn add_change(
         &mut self,
         source_guid_prefix: GuidPrefix,
-        change: CacheChange,
+        mut change: CacheChange,
     ) -> Option<Vec<ReaderTimer>> {
         let writer_guid = GUID::new(source_guid_prefix, change.writer_guid.entity_id);
         if let Some(wp) = self.unmatched_writers.remove(&writer_guid) {
@@ -166,42 +401,50 @@ impl Reader {
                 return None;
             }
         };
-        let deadline_period = self.qos.deadline().period;
-        let mut rt: Vec<ReaderTimer> = Vec::new();
-        if lifespan.0 != Duration::INFINITE {
-            rt.push(ReaderTimer::Lifespan(
-                self.entity_id(),
-                HCKey {
-                    guid: writer_guid,
-                    seq_num: change.sequence_number,
-                },
-                change.timestamp,
-                lifespan.0.into(),
-            ))
-        }
-        if deadline_period != Duration::INFINITE {
-            rt.push(ReaderTimer::Deadline(
-                self.guid.entity_id,
-                writer_guid,
-                deadline_period.into(),
-            ));
+        if self.topic_kind == TopicKind::WithKey
+            && change.instance_handle().is_nil()
+            && !self.guid.entity_id.is_builtin()
+        {
+            // rtps 2.3 spec, 9.6.4.8 KeyHash
+            // the Writer may not send PID_KEY_HASH, then the instance is identified by the key in the SerializedPayload.
+            let is_key = change.kind() != ChangeKind::Alive;
+            if let Some(key_hash) = change
+                .data_value()
+                .and_then(|d| (self.payload_key_hash)(d, is_key))
+            {
+                change.set_instance_handle(InstantHandle::from_key_hash(Some(key_hash)));
+            }
         }
+        let is_filtered = change.is_coherent_set_end()
+            || !self.is_instance_owner(&change, writer_guid)
+            || self.is_older_than_delivered(&change)
+            || self.is_filtered_by_time(&change);
+        let mut rt: Vec<ReaderTimer> = Vec::new();
         if self.is_reliable() {
             // Reliable Reader Behavior
-            if let Err(e) = self.reader_cache.write().add_change(
-                change.clone(),
-                self.is_reliable(),
-                self.qos.resource_limits(),
-                self.qos.history(),
-            ) {
-                debug!(
-                    "failed to add change to Reader: {}\n\tReader: {}\n\tWriter: {}",
-                    e, self.guid, change.writer_guid
-                );
-                return if rt.is_empty() { None } else { Some(rt) };
+            // A filtered change (e.g. dropped by TimeBasedFilter) is not added to the HistoryCache,
+            // but it is handled as received so that it is acknowledged
+            // and the changes after it are not blocked.
+            if !is_filtered {
+                if let Err(e) = self.reader_cache.write().add_change(
+                    change.clone(),
+                    self.is_reliable(),
+                    self.qos.resource_limits(),
+                    self.qos.history(),
+                ) {
+                    debug!(
+                        "failed to add change to Reader: {}\n\tReader: {}\n\tWriter: {}",
+                        e, self.guid, change.writer_guid
+                    );
+                    self.notify_sample_rejected(e, &change);
+                    return None;
+                }
+                self.set_delivered(&change);
             }
-            match self.writer_communication_state.get_mut(&writer_guid) {
-                Some(ReaderState::Initial) => (),
+            rt = self.accept_change(writer_guid, &change, is_filtered, lifespan.0);
+            let is_released = self.update_coherent_sets(writer_guid, &change, !is_filtered);
+            let is_flushed = match self.writer_communication_state.get_mut(&writer_guid) {
+                Some(ReaderState::Initial) => false,
                 Some(ReaderState::Waiting(wait_list)) => {
                     wait_list.remove(&change.sequence_number);
                     if wait_list.is_empty() {
@@ -209,6 +452,9 @@ impl Reader {
                         self.reader_state_notifier
                             .send(DataReaderStatusChanged::DataAvailable)
                             .expect("failed to send data via chennel 'reader_state_notifier'");
+                        true
+                    } else {
+                        false
                     }
                 }
                 Some(ReaderState::Expect(seq_num)) if change.sequence_number == *seq_num => {
@@ -217,12 +463,21 @@ impl Reader {
                         .send(DataReaderStatusChanged::DataAvailable)
                         .expect("failed to send data via channel 'reader_state_notifier'");
                     *seq_num += SequenceNumber(1);
+                    true
                 }
-                Some(ReaderState::Expect(_seq_num)) => { /* nothing to do */ }
-                None => (),
+                Some(ReaderState::Expect(_seq_num)) => false,
+                None => false,
             };
+            if is_released && !is_flushed {
+                // the change completed a coherent set, whose changes have been held
+                self.flush_released_changes();
+            }
             if let Some(writer_proxy) = self.matched_writers.get_mut(&writer_guid) {
-                writer_proxy.received_change_set(change.sequence_number);
+                if is_filtered {
+                    writer_proxy.irrelevant_change_set(change.sequence_number);
+                } else {
+                    writer_proxy.received_change_set(change.sequence_number);
+                }
             } else {
                 warn!(
                     "reached unreachable state: Reliable Reader attempted to add change from unmatched Writer\n\tReader: {}\n\tWriter: {}",
@@ -264,22 +519,30 @@ impl Reader {
                     flag = change.sequence_number >= expected_seq_num;
                 }
                 if flag {
-                    if let Err(e) = self.reader_cache.write().add_change(
-                        change.clone(),
-                        self.is_reliable(),
-                        self.qos.resource_limits(),
-                        self.qos.history(),
-                    ) {
-                        warn!(
-                            "failed to add change to Reader: {}\n\tReader: {}\n\tWriter: {}",
-                            e, self.guid, change.writer_guid
-                        );
-                        return if rt.is_empty() { None } else { Some(rt) };
+                    if !is_filtered {
+                        if let Err(e) = self.reader_cache.write().add_change(
+                            change.clone(),
+                            self.is_reliable(),
+                            self.qos.resource_limits(),
+                            self.qos.history(),
+                        ) {
+                            warn!(
+                                "failed to add change to Reader: {}\n\tReader: {}\n\tWriter: {}",
+                                e, self.guid, change.writer_guid
+                            );
+                            self.notify_sample_rejected(e, &change);
+                            return None;
+                        }
+                        self.set_delivered(&change);
+                    }
+                    rt = self.accept_change(writer_guid, &change, is_filtered, lifespan.0);
+                    let is_released = self.update_coherent_sets(writer_guid, &change, !is_filtered);
+                    if !is_filtered || is_released {
+                        self.reader_cache.write().flush();
+                        self.reader_state_notifier
+                            .send(DataReaderStatusChanged::DataAvailable)
+                            .expect("failed to send data via channell 'reader_state_notifier'");
                     }
-                    self.reader_cache.write().flush();
-                    self.reader_state_notifier
-                        .send(DataReaderStatusChanged::DataAvailable)
-                        .expect("failed to send data via channell 'reader_state_notifier'");
                     let writer_proxy_mut = self
                         .matched_writers
                         .get_mut(&writer_guid)
@@ -305,6 +568,421 @@ impl Reader {
         }
     }
 
+    /// Update the instance_state and start the timers for the change which is added to the HistoryCache,
+    /// or handled as received without being added because it is filtered.
+    fn accept_change(
+        &mut self,
+        writer_guid: GUID,
+        change: &CacheChange,
+        is_filtered: bool,
+        lifespan: Duration,
+    ) -> Vec<ReaderTimer> {
+        if change.kind().is_unregistered() && !is_filtered {
+            // the owner unregistered the instance, so ownership is released.
+            self.instance_owners
+                .release_instance(change.instance_handle());
+        }
+        if !change.is_coherent_set_end() {
+            self.update_instance_state(writer_guid, change, is_filtered);
+        }
+        let deadline_period = self.qos.deadline().period;
+        let mut rt: Vec<ReaderTimer> = Vec::new();
+        if lifespan != Duration::INFINITE && !is_filtered {
+            rt.push(ReaderTimer::Lifespan(
+                self.entity_id(),
+                HCKey {
+                    guid: writer_guid,
+                    seq_num: change.sequence_number,
+                },
+                change.timestamp,
+                lifespan.into(),
+            ))
+        }
+        // DDS 1.4 spec, 2.2.3.7 DEADLINE
+        // the deadline of the instance is reset by each sample,
+        // and it is no longer tracked after the instance is disposed.
+        if deadline_period != Duration::INFINITE && !is_filtered {
+            if change.kind().is_disposed() {
+                rt.push(ReaderTimer::CancelDeadline(
+                    self.guid.entity_id,
+                    change.instance_handle(),
+                ));
+            } else if change.kind() == ChangeKind::Alive {
+                // the deadline restarted here must not be cancelled by the pending CancelDeadline
+                let instance = change.instance_handle();
+                self.pending_timers
+                    .retain(|t| !matches!(t, ReaderTimer::CancelDeadline(_, i) if *i == instance));
+                rt.push(ReaderTimer::Deadline(
+                    self.guid.entity_id,
+                    change.instance_handle(),
+                    deadline_period.into(),
+                ));
+            }
+        }
+        rt
+    }
+
+    /// rtps 2.3 spec, 8.7.5 Coherent Sets
+    /// Track the coherent set the change belongs to, and hold the change in the HistoryCache
+    /// until the whole set arrives if the Subscriber requests `coherent_access`.
+    /// Return true if some coherent set is completed.
+    fn update_coherent_sets(
+        &mut self,
+        writer_guid: GUID,
+        change: &CacheChange,
+        is_added: bool,
+    ) -> bool {
+        if !self.presentation.coherent_access {
+            return false;
+        }
+        let seq_num = change.sequence_number;
+        let sets = self.coherent_sets.entry(writer_guid).or_default();
+        if let Some(start) = change
+            .coherent_set
+            .filter(|sn| *sn != SequenceNumber::SEQUENCENUMBER_UNKNOWN)
+        {
+            let set = sets.entry(start).or_default();
+            set.received.insert(seq_num);
+            if is_added {
+                let key = HCKey::new(writer_guid, seq_num);
+                self.reader_cache.write().hold(key);
+                set.keys.push(key);
+            }
+        }
+        // a change which is not in a set ends the set if it follows the set.
+        for (start, set) in sets.iter_mut() {
+            if *start < seq_num && change.coherent_set != Some(*start) {
+                set.end = Some(match set.end {
+                    Some(end) => min(end, seq_num),
+                    None => seq_num,
+                });
+            }
+        }
+        self.release_coherent_sets(writer_guid)
+    }
+
+    /// Changes in a GAP are irrelevant to the Reader, but they are part of the coherent set.
+    /// Return true if some coherent set is completed.
+    fn update_coherent_sets_with_gap(
+        &mut self,
+        writer_guid: GUID,
+        gap_seq_nums: &[SequenceNumber],
+    ) -> bool {
+        if !self.presentation.coherent_access {
+            return false;
+        }
+        if let Some(sets) = self.coherent_sets.get_mut(&writer_guid) {
+            for (start, set) in sets.iter_mut() {
+                set.received
+                    .extend(gap_seq_nums.iter().filter(|sn| **sn >= *start));
+            }
+        }
+        self.release_coherent_sets(writer_guid)
+    }
+
+    /// make the changes in the released coherent sets ready, and notify the DataReader
+    fn flush_released_changes(&mut self) {
+        if self.reader_cache.write().flush() {
+            self.reader_state_notifier
+                .send(DataReaderStatusChanged::DataAvailable)
+                .expect("failed to send data via channel 'reader_state_notifier'");
+        }
+    }
+
+    /// Release the changes in the completed coherent sets.
+    /// A BestEffort Reader never receives lost changes,
+    /// so the coherent sets which lack some changes are discarded.
+    fn release_coherent_sets(&mut self, writer_guid: GUID) -> bool {
+        let is_reliable = self.is_reliable();
+        let Some(sets) = self.coherent_sets.get_mut(&writer_guid) else {
+            return false;
+        };
+        let mut is_released = false;
+        let mut hc = self.reader_cache.write();
+        sets.retain(|start, set| {
+            let Some(end) = set.end else {
+                return true;
+            };
+            let received = set.received.range(*start..end).count() as i64;
+            if received == (end - *start).0 {
+                trace!(
+                    "coherent set [{}, {}) completed\n\tReader: {}\n\tWriter: {}",
+                    start.0,
+                    end.0,
+                    self.guid,
+                    writer_guid
+                );
+                hc.release(&set.keys);
+                is_released = true;
+                false
+            } else if !is_reliable {
+                debug!(
+                    "discard incomplete coherent set [{}, {})\n\tReader: {}\n\tWriter: {}",
+                    start.0, end.0, self.guid, writer_guid
+                );
+                for key in &set.keys {
+                    hc.remove_change_if_exist(key);
+                }
+                false
+            } else {
+                true
+            }
+        });
+        is_released
+    }
+
+    /// Discard coherent sets from the Writer which are not completed.
+    fn discard_coherent_sets(&mut self, writer_guid: GUID) {
+        if let Some(sets) = self.coherent_sets.remove(&writer_guid) {
+            let mut hc = self.reader_cache.write();
+            for key in sets.values().flat_map(|set| set.keys.iter()) {
+                hc.remove_change_if_exist(key);
+            }
+        }
+    }
+
+    /// DDS 1.4 spec, 2.2.3.9 OWNERSHIP
+    /// Return true if the Writer of the change owns the instance.
+    /// With EXCLUSIVE Ownership, each instance is owned by the alive Writer with the highest strength.
+    fn is_instance_owner(&mut self, change: &CacheChange, writer_guid: GUID) -> bool {
+        if self.qos.ownership() != Ownership::Exclusive {
+            return true;
+        }
+        let matched_writers = &self.matched_writers;
+        let is_owner =
+            self.instance_owners
+                .arbitrate(change.instance_handle(), writer_guid, |guid| {
+                    matched_writers
+                        .get(&guid)
+                        .map(|wp| wp.qos.ownership_strength().0)
+                });
+        if is_owner {
+            trace!(
+                "Writer owns instance {:?}\n\tReader: {}\n\tWriter: {}",
+                change.instance_handle(),
+                self.guid,
+                writer_guid
+            );
+        } else {
+            trace!(
+                "Reader drop change from Writer without ownership, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                change.sequence_number.0,
+                self.guid,
+                writer_guid
+            );
+        }
+        is_owner
+    }
+
+    fn notify_sample_rejected(&mut self, err: AddChangeErr, change: &CacheChange) {
+        if let AddChangeErr::Rejected(reason) = err {
+            self.sample_rejected_count += 1;
+            self.reader_state_notifier
+                .send(DataReaderStatusChanged::SampleRejected(
+                    SampleRejectedStatus::new(
+                        self.sample_rejected_count,
+                        1,
+                        reason,
+                        change.instance_handle(),
+                    ),
+                ))
+                .expect("failed to send data via channel 'reader_state_notifier'");
+        }
+    }
+
+    /// DDS 1.4 spec, 2.2.3.22 READER_DATA_LIFECYCLE
+    /// Update the instance_state by the change, and request to purge the instance
+    /// if it becomes NOT_ALIVE_DISPOSED or NOT_ALIVE_NO_WRITERS.
+    fn update_instance_state(
+        &mut self,
+        writer_guid: GUID,
+        change: &CacheChange,
+        is_filtered: bool,
+    ) {
+        let kind = change.kind();
+        let instance = change.instance_handle();
+        let now = Timestamp::now().expect("failed to get Timestamp::now()");
+        let state = self.instances.entry(instance).or_insert(InstanceState {
+            kind: InstanceStateKind::Alive,
+            writers: BTreeSet::new(),
+            changed_at: now,
+        });
+        if kind == ChangeKind::Alive {
+            state.writers.insert(writer_guid);
+        } else if kind.is_unregistered() {
+            state.writers.remove(&writer_guid);
+        }
+        let new_kind = if is_filtered {
+            // a filtered change dosen't change the instance_state except for unregistration
+            if state.kind == InstanceStateKind::Alive && state.writers.is_empty() {
+                InstanceStateKind::NotAliveNoWriters
+            } else {
+                state.kind
+            }
+        } else if kind.is_disposed() {
+            InstanceStateKind::NotAliveDisposed
+        } else if kind == ChangeKind::Alive {
+            InstanceStateKind::Alive
+        } else if state.kind == InstanceStateKind::Alive && state.writers.is_empty() {
+            InstanceStateKind::NotAliveNoWriters
+        } else {
+            state.kind
+        };
+        self.set_instance_state(instance, new_kind, now);
+    }
+
+    /// Remove the Writer from the Writers of each instance,
+    /// the instances which lose all the Writers become NOT_ALIVE_NO_WRITERS.
+    fn remove_writer_from_instances(&mut self, writer_guid: GUID) {
+        let now = Timestamp::now().expect("failed to get Timestamp::now()");
+        let mut no_writers = Vec::new();
+        for (instance, state) in self.instances.iter_mut() {
+            if state.writers.remove(&writer_guid)
+                && state.writers.is_empty()
+                && state.kind == InstanceStateKind::Alive
+            {
+                no_writers.push(*instance);
+            }
+        }
+        for instance in no_writers {
+            self.set_instance_state(instance, InstanceStateKind::NotAliveNoWriters, now);
+        }
+    }
+
+    fn set_instance_state(
+        &mut self,
+        instance: InstantHandle,
+        kind: InstanceStateKind,
+        now: Timestamp,
+    ) {
+        let Some(state) = self.instances.get_mut(&instance) else {
+            return;
+        };
+        if state.kind == kind {
+            return;
+        }
+        debug!(
+            "instance {:?} changed state: {:?} -> {:?}\n\tReader: {}",
+            instance, state.kind, kind, self.guid
+        );
+        state.kind = kind;
+        state.changed_at = now;
+        // DDS 1.4 spec, 2.2.3.7 DEADLINE
+        // the deadline of the instance is no longer tracked after it becomes NOT_ALIVE.
+        if kind != InstanceStateKind::Alive {
+            self.pending_timers
+                .push(ReaderTimer::CancelDeadline(self.guid.entity_id, instance));
+        }
+        let delay = match kind {
+            InstanceStateKind::Alive => return,
+            InstanceStateKind::NotAliveDisposed => {
+                self.qos
+                    .reader_data_lifecycle()
+                    .autopurge_dispose_samples_delay
+            }
+            InstanceStateKind::NotAliveNoWriters => {
+                self.qos
+                    .reader_data_lifecycle()
+                    .autopurge_nowriter_samples_delay
+            }
+        };
+        if delay != Duration::INFINITE {
+            self.pending_timers.push(ReaderTimer::Autopurge(
+                self.entity_id(),
+                instance,
+                now,
+                delay.into(),
+            ));
+        }
+    }
+
+    /// Take the Autopurge and CancelDeadline timers requested since the last call
+    pub fn take_pending_timers(&mut self) -> Vec<ReaderTimer> {
+        core::mem::take(&mut self.pending_timers)
+    }
+
+    /// Purge the instance from the HistoryCache if its instance_state has not been changed
+    /// since the Autopurge timer was set.
+    pub fn autopurge_instance(&mut self, instance: InstantHandle, changed_at: Timestamp) {
+        match self.instances.get(&instance) {
+            Some(state)
+                if state.changed_at == changed_at && state.kind != InstanceStateKind::Alive =>
+            {
+                debug!(
+                    "Reader purge instance {:?} whose state is {:?}\n\tReader: {}",
+                    instance, state.kind, self.guid
+                );
+            }
+            _ => return,
+        }
+        self.instances.remove(&instance);
+        self.last_delivered_ts.remove(&instance);
+        self.last_delivered_source_ts.remove(&instance);
+        self.instance_owners.release_instance(instance);
+        self.reader_cache.write().remove_instance(instance);
+        self.pending_timers
+            .push(ReaderTimer::CancelDeadline(self.guid.entity_id, instance));
+    }
+
+    /// Release all instances owned by the Writer
+    /// so that ownership fails over to another Writer.
+    fn release_ownership(&mut self, writer_guid: GUID) {
+        self.instance_owners.release_writer(writer_guid);
+    }
+
+    /// DDS 1.4 spec, 2.2.3.17 DESTINATION_ORDER
+    /// Return true if DestinationOrder is BySourceTimestamp and the change is older than
+    /// the last change delivered for the same instance.
+    fn is_older_than_delivered(&self, change: &CacheChange) -> bool {
+        if self.qos.destination_order() != DestinationOrder::BySourceTimestamp {
+            return false;
+        }
+        match self.last_delivered_source_ts.get(&change.instance_handle()) {
+            Some(last_ts) if change.source_timestamp < *last_ts => {
+                trace!(
+                    "Reader drop change older than the last delivered one, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                    change.sequence_number.0,
+                    self.guid,
+                    change.writer_guid
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn set_delivered(&mut self, change: &CacheChange) {
+        let instance = change.instance_handle();
+        if change.kind() == ChangeKind::Alive {
+            self.last_delivered_ts.insert(instance, change.timestamp);
+        }
+        self.last_delivered_source_ts
+            .insert(instance, change.source_timestamp);
+    }
+
+    /// DDS 1.4 spec, 2.2.3.12 TIME_BASED_FILTER
+    /// Return true if the change arrived sooner than minimum_separation
+    /// after the last change delivered for the same instance.
+    /// Only ALIVE changes are filtered, so that the change of the instance_state is not lost.
+    fn is_filtered_by_time(&self, change: &CacheChange) -> bool {
+        let minimum_separation = self.qos.time_based_filter().minimun_separation;
+        if minimum_separation == Duration::ZERO || change.kind() != ChangeKind::Alive {
+            return false;
+        }
+        match self.last_delivered_ts.get(&change.instance_handle()) {
+            Some(last_ts) if change.timestamp - *last_ts < minimum_separation.into() => {
+                trace!(
+                    "Reader drop change by TimeBasedFilter, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                    change.sequence_number.0,
+                    self.guid,
+                    change.writer_guid
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn matched_writer_add(
         &mut self,
         remote_writer_guid: GUID,
@@ -321,6 +999,7 @@ impl Reader {
             Vec::new(),
             data_max_size_serialized,
             qos,
+            Partition::default(),
         );
     }
 
@@ -334,7 +1013,40 @@ impl Reader {
         default_multicast_locator_list: Vec<Locator>,
         data_max_size_serialized: i32,
         qos: DataWriterQosPolicies,
+        partition: Partition,
     ) -> Option<ReaderTimer> {
+        if self.disc_db.is_ignored_endpoint(remote_writer_guid) {
+            debug!(
+                "Reader ignored Writer\n\tReader: {}\n\tWriter: {}",
+                self.guid, remote_writer_guid
+            );
+            return None;
+        }
+        // DDS 1.4 spec, 2.2.3.13 PARTITION
+        // Writer and Reader communicate only if they have at least one partition in common.
+        if !self.partition.is_match(&partition) {
+            self.delete_writer_proxy_with_guid(remote_writer_guid);
+            debug!(
+                "Reader found Writer in different Partition\n\tReader: {}\n\tWriter: {}\n\tpartition: {:?}",
+                self.guid, remote_writer_guid, partition.name
+            );
+            let mut proxy = WriterProxy::new(
+                remote_writer_guid,
+                unicast_locator_list,
+                multicast_locator_list,
+                default_unicast_locator_list,
+                default_multicast_locator_list,
+                data_max_size_serialized,
+                qos,
+                Arc::new(RwLock::new(HistoryCache::new(HistoryCacheType::Dummy))),
+            );
+            proxy.partition = partition;
+            self.partition_mismatched_writers
+                .insert(remote_writer_guid, proxy);
+            return None;
+        }
+        self.partition_mismatched_writers
+            .remove(&remote_writer_guid);
         let rt: Option<ReaderTimer>;
         if let std::collections::btree_map::Entry::Vacant(e) =
             self.matched_writers.entry(remote_writer_guid)
@@ -357,7 +1069,7 @@ impl Reader {
                 self.guid, remote_writer_guid
             );
 
-            e.insert(WriterProxy::new(
+            let mut proxy = WriterProxy::new(
                 remote_writer_guid,
                 unicast_locator_list,
                 multicast_locator_list,
@@ -366,7 +1078,9 @@ impl Reader {
                 data_max_size_serialized,
                 qos,
                 self.reader_cache.clone(),
-            ));
+            );
+            proxy.partition = partition;
+            e.insert(proxy);
 
             self.writer_communication_state
                 .insert(remote_writer_guid, ReaderState::Initial);
@@ -395,11 +1109,14 @@ impl Reader {
                 ))
                 .expect("failed to send data via channel 'reader_state_notifier'");
 
+            // DDS 1.4 spec, 2.2.3.7 DEADLINE
+            // Deadline is tracked per instance. A keyless Topic has only one instance,
+            // so its deadline starts when a Writer is matched.
             let deadline_period = self.qos.deadline().period;
-            if deadline_period != Duration::INFINITE {
+            if deadline_period != Duration::INFINITE && self.topic_kind == TopicKind::NoKey {
                 rt = Some(ReaderTimer::Deadline(
                     self.guid.entity_id,
-                    remote_writer_guid,
+                    InstantHandle::NIL,
                     deadline_period.into(),
                 ));
             } else {
@@ -425,11 +1142,58 @@ impl Reader {
             update_proxy_if_need!(default_unicast_locator_list);
             update_proxy_if_need!(default_multicast_locator_list);
             update_proxy_if_need!(data_max_size_serialized);
+            update_proxy_if_need!(partition);
             rt = None;
         }
         rt
     }
 
+    /// Apply the new Partition of the Subscriber and re-evaluate matching with known Writers.
+    pub fn set_partition(&mut self, partition: Partition) -> Vec<ReaderTimer> {
+        let mut rts = Vec::new();
+        if self.partition == partition {
+            return rts;
+        }
+        info!(
+            "Reader change Partition: {:?} -> {:?}\n\tReader: {}",
+            self.partition.name, partition.name, self.guid
+        );
+        self.partition = partition;
+        let to_unmatch: Vec<WriterProxy> = self
+            .matched_writers
+            .values()
+            .chain(self.unmatched_writers.values())
+            .filter(|wp| !self.partition.is_match(&wp.partition))
+            .cloned()
+            .collect();
+        for wp in to_unmatch {
+            self.delete_writer_proxy_with_guid(wp.remote_writer_guid);
+            self.partition_mismatched_writers
+                .insert(wp.remote_writer_guid, wp);
+        }
+        let to_match: Vec<WriterProxy> = self
+            .partition_mismatched_writers
+            .values()
+            .filter(|wp| self.partition.is_match(&wp.partition))
+            .cloned()
+            .collect();
+        for wp in to_match {
+            if let Some(rt) = self.matched_writer_add_with_default_locator(
+                wp.remote_writer_guid,
+                wp.unicast_locator_list,
+                wp.multicast_locator_list,
+                wp.default_unicast_locator_list,
+                wp.default_multicast_locator_list,
+                wp.data_max_size_serialized,
+                wp.qos,
+                wp.partition,
+            ) {
+                rts.push(rt);
+            }
+        }
+        rts
+    }
+
     pub fn is_writer_match(&self, topic_name: &str, data_type: &str) -> bool {
         self.topic.name() == topic_name && self.topic.type_desc() == data_type
     }
@@ -443,6 +1207,8 @@ impl Reader {
 
     fn matched_writer_unmatch(&mut self, guid: GUID) {
         if let Some(writer_proxy) = self.matched_writers.remove(&guid) {
+            self.release_ownership(guid);
+            self.remove_writer_from_instances(guid);
             debug!(
                 "writer unmatched\n\tReader: {}, Writer: {}",
                 self.guid, writer_proxy.remote_writer_guid
@@ -485,6 +1251,8 @@ impl Reader {
                 self.guid, guid
             );
             self.writer_communication_state.remove(&guid);
+            self.discard_coherent_sets(guid);
+            self.fragmented_changes.retain(|key, _| key.guid != guid);
             self.send_sub_unmatch(guid);
         } else {
             warn!(
@@ -501,8 +1269,12 @@ impl Reader {
                 "reader delete matched wirter\n\tReader: {}\n\tWriter: {}",
                 self.guid, guid
             );
+            self.release_ownership(guid);
+            self.remove_writer_from_instances(guid);
             self.reader_cache.write().remove_change_from_writer(&guid);
             self.writer_communication_state.remove(&guid);
+            self.discard_coherent_sets(guid);
+            self.fragmented_changes.retain(|key, _| key.guid != guid);
             self.reader_state_notifier
                 .send(DataReaderStatusChanged::LivelinessChanged(
                     LivelinessChangedStatus::new(
@@ -544,6 +1316,297 @@ impl Reader {
         for d in to_delete {
             self.unmatched_writer_remove(d);
         }
+        self.partition_mismatched_writers
+            .retain(|guid, _| guid.guid_prefix != guid_prefix);
+    }
+
+    pub fn delete_writer_proxy_with_guid(&mut self, guid: GUID) {
+        if self.matched_writers.contains_key(&guid) {
+            self.matched_writer_remove(guid);
+        } else if self.unmatched_writers.contains_key(&guid) {
+            self.unmatched_writer_remove(guid);
+        }
+        self.partition_mismatched_writers.remove(&guid);
+    }
+
+    /// rtps 2.3 spec, 8.4.14.1 Large Data
+    ///
+    /// Reassemble the change from the fragments in DATA_FRAG, and add it when all fragments are received.
+    /// `change` is the change carried by the DATA_FRAG without SerializedPayload.
+    pub fn add_data_frag(
+        &mut self,
+        source_guid_prefix: GuidPrefix,
+        change: CacheChange,
+        data_frag: &DataFrag,
+    ) -> Option<Vec<ReaderTimer>> {
+        let writer_guid = GUID::new(source_guid_prefix, change.writer_guid.entity_id);
+        let key = HCKey::new(writer_guid, change.sequence_number);
+        let mut rt = Vec::new();
+        match self.matched_writers.get(&writer_guid) {
+            Some(wp) if change.sequence_number <= wp.available_changes_max() => {
+                // the change has already been received
+                return None;
+            }
+            Some(_) => (),
+            None if self.unmatched_writers.contains_key(&writer_guid) => (),
+            None => {
+                warn!("attempt to add DATA_FRAG to Reader from unmatched writers\n\tReader: {}\n\tWriter: {}", self.guid, writer_guid);
+                return None;
+            }
+        }
+        if !self.fragmented_changes.contains_key(&key) {
+            if data_frag.data_size > MAX_FRAGMENTED_DATA_SIZE {
+                warn!(
+                    "Reader received DATA_FRAG whose data_size({}) exceeds the limit({}), seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                    data_frag.data_size, MAX_FRAGMENTED_DATA_SIZE, change.sequence_number.0, self.guid, writer_guid
+                );
+                return None;
+            }
+            if data_frag.fragment_size == 0 {
+                warn!(
+                    "Reader received DATA_FRAG whose fragment_size is 0, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                    change.sequence_number.0, self.guid, writer_guid
+                );
+                return None;
+            }
+            let in_progress: Vec<HCKey> = self
+                .fragmented_changes
+                .keys()
+                .filter(|k| k.guid == writer_guid)
+                .copied()
+                .collect();
+            if in_progress.len() >= MAX_FRAGMENTED_CHANGES_PER_WRITER {
+                // discard the oldest change
+                debug!(
+                    "Reader discarded fragmented change, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                    in_progress[0].seq_num.0, self.guid, writer_guid
+                );
+                self.fragmented_changes.remove(&in_progress[0]);
+            }
+            rt.push(ReaderTimer::Fragment(
+                self.entity_id(),
+                key,
+                change.timestamp,
+            ));
+            self.fragmented_changes.insert(
+                key,
+                FragmentedChange::new(change, data_frag.data_size, data_frag.fragment_size),
+            );
+        }
+        let fragmented_change = self
+            .fragmented_changes
+            .get_mut(&key)
+            .expect("failed to get fragmented_change");
+        if fragmented_change.data_size != data_frag.data_size
+            || fragmented_change.fragment_size != data_frag.fragment_size
+        {
+            warn!(
+                "Reader received DATA_FRAG inconsistent with previous fragments, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                key.seq_num.0, self.guid, writer_guid
+            );
+            return if rt.is_empty() { None } else { Some(rt) };
+        }
+        fragmented_change.add_fragments(
+            data_frag.fragment_starting_num,
+            data_frag.fragments_in_submessage,
+            &data_frag.serialized_payload,
+        );
+        self.limit_reassembly_buffer(key);
+        let fragmented_change = match self.fragmented_changes.get(&key) {
+            Some(fragmented_change) => fragmented_change,
+            None => return if rt.is_empty() { None } else { Some(rt) },
+        };
+        trace!(
+            "Reader received {}/{} fragments of seq_num: {}\n\tReader: {}\n\tWriter: {}",
+            fragmented_change.fragments.len(),
+            fragmented_change.fragment_count(),
+            key.seq_num.0,
+            self.guid,
+            writer_guid
+        );
+        if fragmented_change.is_complete() {
+            let fragmented_change = self
+                .fragmented_changes
+                .remove(&key)
+                .expect("failed to remove fragmented_change");
+            let payload = fragmented_change.assemble();
+            let serialized_payload = if payload.len() > 4 {
+                SerializedPayload::from_bytes(&payload).ok()
+            } else {
+                None
+            };
+            if serialized_payload.is_none() {
+                warn!(
+                    "Reader reassembled invalid SerializedPayload from DATA_FRAG, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                    key.seq_num.0, self.guid, writer_guid
+                );
+                return if rt.is_empty() { None } else { Some(rt) };
+            }
+            let template = fragmented_change.change;
+            let mut change = CacheChange::new(
+                template.kind(),
+                template.writer_guid,
+                template.sequence_number,
+                template.timestamp,
+                serialized_payload,
+                template.instance_handle(),
+            );
+            change.source_timestamp = template.source_timestamp;
+            change.coherent_set = template.coherent_set;
+            if let Some(mut timers) = self.add_change(source_guid_prefix, change) {
+                rt.append(&mut timers);
+            }
+        }
+        if rt.is_empty() {
+            None
+        } else {
+            Some(rt)
+        }
+    }
+
+    /// discard the oldest fragmented changes until the buffered fragments fit in MAX_REASSEMBLY_BUFFER_SIZE
+    ///
+    /// the change of `current` is discarded last
+    fn limit_reassembly_buffer(&mut self, current: HCKey) {
+        let mut buffered: usize = self
+            .fragmented_changes
+            .values()
+            .map(|fc| fc.buffered_size())
+            .sum();
+        while buffered > MAX_REASSEMBLY_BUFFER_SIZE {
+            let oldest = self
+                .fragmented_changes
+                .iter()
+                .filter(|(k, _)| **k != current)
+                .min_by_key(|(_, fc)| fc.change.timestamp)
+                .map(|(k, _)| *k)
+                .unwrap_or(current);
+            let discarded = self
+                .fragmented_changes
+                .remove(&oldest)
+                .expect("failed to remove fragmented_change");
+            debug!(
+                "Reader discarded fragmented change because the reassembly buffer is full, seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                oldest.seq_num.0, self.guid, oldest.guid
+            );
+            buffered -= discarded.buffered_size();
+        }
+    }
+
+    /// discard the change not reassembled in FRAGMENT_REASSEMBLY_TIMEOUT
+    pub fn handle_fragment_timeout(&mut self, key: HCKey, first_received: Timestamp) {
+        if let Some(fragmented_change) = self.fragmented_changes.get(&key) {
+            if fragmented_change.change.timestamp == first_received {
+                debug!(
+                    "Reader discarded fragmented change because of timeout, received {}/{} fragments of seq_num: {}\n\tReader: {}\n\tWriter: {}",
+                    fragmented_change.fragments.len(),
+                    fragmented_change.fragment_count(),
+                    key.seq_num.0,
+                    self.guid,
+                    key.guid
+                );
+                self.fragmented_changes.remove(&key);
+            }
+        }
+    }
+
+    /// rtps 2.3 spec, 8.4.14.1.4 Handling of HeartbeatFrag
+    ///
+    /// request the missing fragments of the change by NACK_FRAG
+    pub fn handle_heartbeat_frag(
+        &mut self,
+        writer_guid: GUID,
+        heartbeat_frag: &HeartbeatFrag,
+        reply_locators: &[Locator],
+    ) {
+        if !self.is_reliable() {
+            return;
+        }
+        if let Some(writer_proxy) = self.matched_writers.get_mut(&writer_guid) {
+            writer_proxy.reply_locator_list = reply_locators.to_vec();
+        }
+        let key = HCKey::new(writer_guid, heartbeat_frag.writer_sn);
+        let missing = match self.fragmented_changes.get(&key) {
+            Some(fragmented_change) => {
+                fragmented_change.missing_fragments(heartbeat_frag.last_fragment_num)
+            }
+            // If no fragment of the change is received, the change is requested by ACKNACK.
+            None => return,
+        };
+        self.send_nack_frag(writer_guid, heartbeat_frag.writer_sn, missing);
+    }
+
+    /// rtps 2.3 spec, 8.4.14.1 Large Data
+    ///
+    /// request the missing fragments of the partially received changes in the range of HEARTBEAT by NACK_FRAG
+    fn request_missing_fragments(
+        &mut self,
+        writer_guid: GUID,
+        first_sn: SequenceNumber,
+        last_sn: SequenceNumber,
+    ) {
+        let requests: Vec<(SequenceNumber, Vec<FragmentNumber>)> = self
+            .fragmented_changes
+            .iter()
+            .filter(|(key, _)| {
+                key.guid == writer_guid && first_sn <= key.seq_num && key.seq_num <= last_sn
+            })
+            .map(|(key, fragmented_change)| {
+                (
+                    key.seq_num,
+                    fragmented_change.missing_fragments(fragmented_change.fragment_count()),
+                )
+            })
+            .collect();
+        for (writer_sn, missing) in requests {
+            self.send_nack_frag(writer_guid, writer_sn, missing);
+        }
+    }
+
+    fn send_nack_frag(
+        &mut self,
+        writer_guid: GUID,
+        writer_sn: SequenceNumber,
+        missing: Vec<FragmentNumber>,
+    ) {
+        if missing.is_empty() {
+            return;
+        }
+        let writer_proxy = match self.matched_writers.get(&writer_guid) {
+            Some(wp) => wp,
+            None => return,
+        };
+        let ll_u = match Self::get_unicast_ll_from_proxy(self.guid, writer_proxy) {
+            Some(ll_u) => ll_u,
+            None => return,
+        };
+        // rtps 2.3 spec, 9.4.2.8 FragmentNumberSet
+        // the bitmap can represent 256 fragments from the base
+        let base = missing[0];
+        let missing: Vec<FragmentNumber> =
+            missing.into_iter().filter(|f| *f < base + 256).collect();
+        self.nack_frag_count += 1;
+        let mut message_builder = MessageBuilder::new();
+        message_builder.info_dst(self.endianness, writer_guid.guid_prefix);
+        message_builder.nack_frag(
+            self.endianness,
+            writer_guid.entity_id,
+            self.guid.entity_id,
+            writer_sn,
+            FragmentNumberSet::from_vec(base, missing),
+            self.nack_frag_count,
+        );
+        let message = message_builder.build(self.guid.guid_prefix);
+        let message_buf = message
+            .write_to_vec_with_ctx(self.endianness)
+            .expect("failed to serialize message");
+        for loc in ll_u {
+            self.send_msg_to_locator(
+                loc,
+                &message_buf,
+                &format!("nack_frag {{ seq_num: {} }}", writer_sn.0),
+            );
+        }
     }
 
     pub fn handle_gap(&mut self, writer_guid: GUID, gap: &Gap) {
@@ -578,15 +1641,21 @@ impl Reader {
         }
 
         if let Some(writer_proxy) = self.matched_writers.get_mut(&writer_guid) {
+            let mut gap_seq_nums = Vec::new();
             let mut seq_num = gap.gap_start;
             while seq_num < gap.gap_list.base() {
                 writer_proxy.irrelevant_change_set(seq_num);
                 remove_seqnum_from_wait_list!(seq_num);
+                gap_seq_nums.push(seq_num);
                 seq_num += SequenceNumber(1);
             }
             for seq_num in gap.gap_list.set() {
                 writer_proxy.irrelevant_change_set(seq_num);
                 remove_seqnum_from_wait_list!(seq_num);
+                gap_seq_nums.push(seq_num);
+            }
+            if self.update_coherent_sets_with_gap(writer_guid, &gap_seq_nums) {
+                self.flush_released_changes();
             }
         } else {
             warn!(
@@ -601,6 +1670,7 @@ impl Reader {
         writer_guid: GUID,
         hb_flag: BitFlags<HeartbeatFlag>,
         heartbeat: &Heartbeat,
+        reply_locators: &[Locator],
     ) -> Option<ReaderTimer> {
         let rt: Option<ReaderTimer>;
         if let Some(wp) = self.unmatched_writers.remove(&writer_guid) {
@@ -640,6 +1710,7 @@ impl Reader {
 
             writer_proxy.missing_changes_update(heartbeat.first_sn, heartbeat.last_sn);
             writer_proxy.lost_changes_update(heartbeat.first_sn);
+            writer_proxy.reply_locator_list = reply_locators.to_vec();
         } else {
             warn!(
                 "reader attempted to handle Heartbeat from unmatched Writer\n\tReader: {}\n\tWriter: {}",
@@ -648,6 +1719,9 @@ impl Reader {
             rt = None;
             return rt;
         }
+        if self.is_reliable() && !hb_flag.contains(HeartbeatFlag::Liveliness) {
+            self.request_missing_fragments(writer_guid, heartbeat.first_sn, heartbeat.last_sn);
+        }
         if !hb_flag.contains(HeartbeatFlag::Final) {
             // to must_send_ack
             // Transition: T5
@@ -802,12 +1876,14 @@ impl Reader {
                     msg_buf,
                     Ipv4Addr::new(addr[12], addr[13], addr[14], addr[15]),
                     port as u16,
+                    None,
                 );
             } else {
                 self.udp_sender.send_to_unicast(
                     msg_buf,
                     Ipv4Addr::new(addr[12], addr[13], addr[14], addr[15]),
                     port as u16,
+                    None,
                 );
             }
         } else {
@@ -815,10 +1891,15 @@ impl Reader {
         }
     }
 
+    /// locators to send ACKNACK and NACK_FRAG to the Writer
     fn get_unicast_ll_from_proxy(
         my_guid: GUID,
         writer_proxy: &WriterProxy,
     ) -> Option<&Vec<Locator>> {
+        if !writer_proxy.reply_locator_list.is_empty() {
+            // the Writer sent HEARTBEAT by the Message with INFO_REPLY
+            return Some(&writer_proxy.reply_locator_list);
+        }
         let ll_u = writer_proxy.get_unicast_locator_list();
         if ll_u.is_empty() {
             let ll_m = writer_proxy.get_multicast_locator_list();
@@ -842,13 +1923,24 @@ impl Reader {
         ((ipv4_addr[12] >> 4) ^ 0b1110) == 0
     }
 
-    pub fn notify_reqested_deadline_missed(&self, writer_guid: GUID) {
+    pub fn notify_reqested_deadline_missed(&mut self, instance: InstantHandle) {
+        // DDS 1.4 spec, 2.2.3.9.2 EXCLUSIVE kind
+        // the owner which missed its deadline loses ownership of the instance.
+        self.instance_owners.release_instance(instance);
+        self.requested_deadline_missed_count += 1;
         self.reader_state_notifier
             .send(DataReaderStatusChanged::RequestedDeadlineMissed(
-                writer_guid,
+                RequestedDeadlineMissedStatus::new(
+                    self.requested_deadline_missed_count,
+                    1,
+                    instance,
+                ),
             ))
             .expect("failed to send data via channel 'reader_state_notifier'");
-        info!("Reader requested deadline missed\n\tReader: {}", self.guid);
+        info!(
+            "Reader requested deadline missed\n\tReader: {}\n\tinstance: {:?}",
+            self.guid, instance
+        );
     }
 
     pub fn heartbeat_response_delay(&self) -> StdDuration {
@@ -861,6 +1953,16 @@ impl Reader {
         self.matched_writers.contains_key(&writer_guid)
             || self.unmatched_writers.contains_key(&writer_guid)
     }
+    /// whether the topic name and the partition received as inline QoS match this Reader.
+    /// None means the Writer didn't send it.
+    pub fn is_inline_qos_match(
+        &self,
+        topic_name: Option<&str>,
+        partition: Option<&Partition>,
+    ) -> bool {
+        topic_name.is_none_or(|name| name == self.topic.name())
+            && partition.is_none_or(|p| self.partition.is_match(p))
+    }
     pub fn get_matched_writer_qos(&self, writer_guid: GUID) -> &DataWriterQosPolicies {
         if let Some(wp) = self.matched_writers.get(&writer_guid) {
             &wp.qos
@@ -925,9 +2027,9 @@ impl Reader {
 ///
 /// The content for each variant has not been implemented yet, but it is planned to be implemented in the future.
 pub enum DataReaderStatusChanged {
-    SampleRejected,
+    SampleRejected(SampleRejectedStatus),
     LivelinessChanged(LivelinessChangedStatus),
-    RequestedDeadlineMissed(GUID),
+    RequestedDeadlineMissed(RequestedDeadlineMissedStatus),
     RequestedIncompatibleQos(String),
     DataAvailable,
     SampleLost,
@@ -962,6 +2064,60 @@ impl SubscriptionMatchedStatus {
     }
 }
 
+/// DDS 1.4 spec, 2.2.4.1 Communication Status, REQUESTED_DEADLINE_MISSED
+pub struct RequestedDeadlineMissedStatus {
+    pub total_count: i32,
+    pub total_count_change: i32,
+    pub last_instance_handle: InstantHandle,
+}
+
+impl RequestedDeadlineMissedStatus {
+    pub fn new(
+        total_count: i32,
+        total_count_change: i32,
+        last_instance_handle: InstantHandle,
+    ) -> Self {
+        Self {
+            total_count,
+            total_count_change,
+            last_instance_handle,
+        }
+    }
+}
+
+/// DDS 1.4 spec, 2.2.4.1 Communication Status, SAMPLE_REJECTED
+pub struct SampleRejectedStatus {
+    pub total_count: i32,
+    pub total_count_change: i32,
+    pub last_reason: SampleRejectedStatusKind,
+    pub last_instance_handle: InstantHandle,
+}
+
+impl SampleRejectedStatus {
+    pub fn new(
+        total_count: i32,
+        total_count_change: i32,
+        last_reason: SampleRejectedStatusKind,
+        last_instance_handle: InstantHandle,
+    ) -> Self {
+        Self {
+            total_count,
+            total_count_change,
+            last_reason,
+            last_instance_handle,
+        }
+    }
+}
+
+/// reason why the sample was rejected, which is limited by ResourceLimits
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleRejectedStatusKind {
+    NotRejected,
+    RejectedByInstancesLimit,
+    RejectedBySamplesLimit,
+    RejectedBySamplesPerInstanceLimit,
+}
+
 pub struct LivelinessChangedStatus {
     pub alive_count: i32,
     pub not_alive_count: i32,
@@ -1004,7 +2160,11 @@ pub(crate) struct ReaderIngredients {
     // This implementation spesific
     pub topic: Topic,
     pub qos: DataReaderQosPolicies,
+    pub partition: Partition,
+    pub presentation: Presentation,
     pub reader_state_notifier: mio_channel::Sender<DataReaderStatusChanged>,
+    /// compute the KeyHash of the change sent without PID_KEY_HASH
+    pub payload_key_hash: fn(&SerializedPayload, bool) -> Option<KeyHash>,
 }
 
 impl RTPSEntity for Reader {
@@ -1012,3 +2172,112 @@ impl RTPSEntity for Reader {
         self.guid
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{FragmentedChange, InstanceOwners};
+    use crate::message::submessage::element::{SequenceNumber, Timestamp};
+    use crate::rtps::cache::{CacheChange, ChangeKind, InstantHandle};
+    use crate::structure::{EntityId, EntityKind, GuidPrefix, GUID};
+
+    fn writer(key: u8) -> GUID {
+        GUID::new(
+            GuidPrefix::UNKNOW,
+            EntityId::new_with_entity_kind([0, 0, key], EntityKind::WRITER_WITH_KEY_USER_DEFIND),
+        )
+    }
+
+    /// strength of the alive Writers
+    fn strength_of(alive: &[(GUID, i32)]) -> impl Fn(GUID) -> Option<i32> + '_ {
+        |guid| alive.iter().find(|(g, _)| *g == guid).map(|(_, s)| *s)
+    }
+
+    #[test]
+    fn test_ownership_hand_over() {
+        let instance = InstantHandle::new([1; 16]);
+        let (strong, weak) = (writer(2), writer(1));
+        let mut alive = vec![(strong, 10), (weak, 5)];
+        let mut owners = InstanceOwners::default();
+
+        // the first Writer gets ownership, and the stronger Writer takes it over
+        assert!(owners.arbitrate(instance, weak, strength_of(&alive)));
+        assert!(owners.arbitrate(instance, strong, strength_of(&alive)));
+        assert!(!owners.arbitrate(instance, weak, strength_of(&alive)));
+
+        // the owner unregistered the instance
+        owners.release_instance(instance);
+        assert!(owners.arbitrate(instance, weak, strength_of(&alive)));
+        assert!(owners.arbitrate(instance, strong, strength_of(&alive)));
+
+        // the owner lost liveliness
+        alive.retain(|(g, _)| *g != strong);
+        assert!(owners.arbitrate(instance, weak, strength_of(&alive)));
+        assert!(!owners.arbitrate(instance, strong, strength_of(&alive)));
+
+        // the owner missed the deadline or was unmatched
+        alive.push((strong, 10));
+        assert!(owners.arbitrate(instance, strong, strength_of(&alive)));
+        owners.release_writer(strong);
+        assert!(owners.arbitrate(instance, weak, strength_of(&alive)));
+
+        // the Writer with the smaller GUID wins between the same strength
+        alive = vec![(strong, 5), (weak, 5)];
+        owners.release_writer(weak);
+        assert!(owners.arbitrate(instance, strong, strength_of(&alive)));
+        assert!(owners.arbitrate(instance, weak, strength_of(&alive)));
+        assert!(!owners.arbitrate(instance, strong, strength_of(&alive)));
+    }
+
+    /// 10 bytes payload divided into fragments of 4 bytes: [0..4], [4..8], [8..10]
+    fn fragmented_change() -> (FragmentedChange, Vec<u8>) {
+        let change = CacheChange::new(
+            ChangeKind::Alive,
+            writer(1),
+            SequenceNumber(1),
+            Timestamp::TIME_ZERO,
+            None,
+            InstantHandle::NIL,
+        );
+        let payload: Vec<u8> = (0..10).collect();
+        (FragmentedChange::new(change, 10, 4), payload)
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order_fragments() {
+        let (mut fc, payload) = fragmented_change();
+        assert_eq!(fc.fragment_count(), 3);
+        // the last fragment may be followed by padding
+        fc.add_fragments(3, 1, &[8, 9, 0, 0]);
+        fc.add_fragments(1, 1, &payload[0..4]);
+        assert!(!fc.is_complete());
+        fc.add_fragments(2, 1, &payload[4..8]);
+        assert!(fc.is_complete());
+        assert_eq!(&fc.assemble()[..], &payload[..]);
+    }
+
+    #[test]
+    fn test_reassemble_duplicate_fragments() {
+        let (mut fc, payload) = fragmented_change();
+        fc.add_fragments(1, 2, &payload[0..8]);
+        // a duplicate fragment doesn't overwrite the received one
+        fc.add_fragments(2, 1, &[0xff; 4]);
+        assert_eq!(fc.buffered_size(), 8);
+        assert!(!fc.is_complete());
+        fc.add_fragments(2, 2, &payload[4..10]);
+        assert!(fc.is_complete());
+        assert_eq!(&fc.assemble()[..], &payload[..]);
+    }
+
+    #[test]
+    fn test_missing_fragments() {
+        let (mut fc, payload) = fragmented_change();
+        assert_eq!(fc.missing_fragments(2), vec![1, 2]);
+        fc.add_fragments(2, 1, &payload[4..8]);
+        assert_eq!(fc.missing_fragments(3), vec![1, 3]);
+        // fragments out of the payload and truncated fragments are ignored
+        fc.add_fragments(4, 1, &[0; 4]);
+        fc.add_fragments(3, 1, &payload[8..9]);
+        assert_eq!(fc.missing_fragments(u32::MAX), vec![1, 3]);
+        assert_eq!(fc.buffered_size(), 4);
+    }
+}
@@ -1,31 +1,60 @@
-use crate::message::submessage::element::Locator;
-use crate::rtps::cache::CacheChange;
+use crate::message::submessage::element::{Locator, SequenceNumber};
+use crate::structure::GUID;
+use alloc::collections::BTreeSet;
 
-#[derive(PartialEq, Eq)]
+/// rtps 2.3 spec, 8.4.7.5 RTPS ReaderLocator
+/// > Valuetype used by the RTPS StatelessWriter to keep track of the locators of all matching remote Readers.
+///
+/// This implementation uses StatelessWriter only for BestEffort, so `requested_changes`
+/// used to answer ACKNACK is not kept.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct ReaderLocator {
     pub locator: Locator,
-    pub requested_changes: Vec<CacheChange>,
-    pub unsent_changes: Vec<CacheChange>,
+    unsent_changes: BTreeSet<SequenceNumber>,
     pub expects_inline_qos: bool,
+    /// matched Readers which receive the changes sent to the locator
+    pub readers: BTreeSet<GUID>,
 }
 
-#[allow(dead_code)]
 impl ReaderLocator {
-    pub fn new(
-        locator: Locator,
-        unsent_changes: Vec<CacheChange>,
-        expects_inline_qos: bool,
-    ) -> Self {
+    pub fn new(locator: Locator, expects_inline_qos: bool) -> Self {
         Self {
             locator,
-            requested_changes: Vec::new(),
-            unsent_changes,
+            unsent_changes: BTreeSet::new(),
             expects_inline_qos,
+            readers: BTreeSet::new(),
         }
     }
-    pub fn next_request_change() {}
-    pub fn next_unsent_change() {}
-    pub fn requested_changes() {}
-    pub fn requested_changes_set() {}
-    pub fn unsent_changes() {}
+
+    pub fn add_unsent_change(&mut self, seq_num: SequenceNumber) {
+        self.unsent_changes.insert(seq_num);
+    }
+
+    /// the unsent change with the lowest SequenceNumber, which is no longer unsent after this call
+    pub fn next_unsent_change(&mut self) -> Option<SequenceNumber> {
+        self.unsent_changes.pop_first()
+    }
+
+    pub fn unsent_changes_reset(&mut self, seq_nums: impl Iterator<Item = SequenceNumber>) {
+        self.unsent_changes = seq_nums.collect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReaderLocator;
+    use crate::message::submessage::element::{Locator, SequenceNumber};
+
+    #[test]
+    fn test_next_unsent_change() {
+        let mut rl = ReaderLocator::new(Locator::new_from_ipv4(7401, [239, 255, 0, 1]), false);
+        rl.add_unsent_change(SequenceNumber(3));
+        rl.add_unsent_change(SequenceNumber(1));
+        rl.add_unsent_change(SequenceNumber(3));
+        assert_eq!(rl.next_unsent_change(), Some(SequenceNumber(1)));
+        assert_eq!(rl.next_unsent_change(), Some(SequenceNumber(3)));
+        assert_eq!(rl.next_unsent_change(), None);
+        rl.unsent_changes_reset([SequenceNumber(2), SequenceNumber(4)].into_iter());
+        assert_eq!(rl.next_unsent_change(), Some(SequenceNumber(2)));
+    }
 }
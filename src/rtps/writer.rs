@@ -1,33 +1,37 @@
 use crate::dds::{
     qos::{
-        policy::{Durability, HistoryQosKind, ReliabilityQosKind},
+        policy::{Durability, HistoryQosKind, Partition, ReliabilityQosKind, LENGTH_UNLIMITED},
         DataReaderQosPolicies, DataWriterQosPolicies,
     },
     Topic,
 };
 use crate::discovery::{
+    discovery_db::DiscoveryDB,
     structure::data::{DiscoveredWriterData, ParticipantMessageData, ParticipantMessageKind},
     ParticipantMessageCmd,
 };
 use crate::message::{
-    message_builder::MessageBuilder,
+    message_builder::{MessageBuilder, MessagePacker},
     submessage::element::{
-        AckNack, Count, Locator, SequenceNumber, SequenceNumberSet, SerializedPayload, Timestamp,
+        AckNack, Count, FragmentNumber, Locator, NackFrag, ParameterList, SequenceNumber,
+        SerializedPayload, Timestamp,
     },
 };
-use crate::network::udp_sender::UdpSender;
+use crate::network::udp_sender::{SocketPriority, UdpSender};
 use crate::rtps::cache::{
     CacheChange, ChangeForReaderStatusKind, ChangeKind, HCKey, HistoryCache, HistoryCacheType,
     InstantHandle,
 };
 use crate::rtps::reader_locator::ReaderLocator;
 use crate::structure::{
-    Duration, EntityId, GuidPrefix, RTPSEntity, ReaderProxy, TopicKind, WriterProxy, GUID,
+    Duration, EntityId, GuidPrefix, ParameterId, RTPSEntity, ReaderProxy, TopicKind, WriterProxy,
+    GUID,
 };
 use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::rc::Rc;
 use alloc::sync::Arc;
 use awkernel_sync::rwlock::RwLock;
+use core::cmp::{max, min};
 use core::net::Ipv4Addr;
 use core::time::Duration as CoreDuration;
 use log::{debug, error, info, trace, warn};
@@ -35,12 +39,28 @@ use mio_extras::channel as mio_channel;
 use mio_v06::Token;
 use speedy::{Endianness, Writable};
 
+/// upper limit of the size of an RTPS Message batching DATA submessages by LatencyBudget
+///
+/// Ethernet MTU (1500 octets) minus IPv4 header (20 octets) and UDP header (8 octets)
+const BATCH_MESSAGE_SIZE: usize = 1472;
+
+/// the first period of the fast HEARTBEAT is `heartbeat_period / FAST_HEARTBEAT_DIVISOR`
+const FAST_HEARTBEAT_DIVISOR: u32 = 8;
+
 pub enum WriterTimer {
-    Nack(EntityId, GUID),             // self.entity_id, Reader GUID
-    Deadline(EntityId, CoreDuration), // self.entity_id, deadline.period
+    Nack(EntityId, GUID),                            // self.entity_id, Reader GUID
+    Deadline(EntityId, InstantHandle, CoreDuration), // self.entity_id, instance, deadline.period
+    CancelDeadline(EntityId, InstantHandle),         // self.entity_id, instance
+    Lifespan(EntityId, SequenceNumber, Timestamp, CoreDuration), // self.entity_id, SequenceNumber of the change, source Timestamp, lifespan.period
+    LatencyBudget(EntityId, CoreDuration), // self.entity_id, latency_budget.duration
+    Heartbeat(EntityId, CoreDuration),     // self.entity_id, period until the next HEARTBEAT
 }
 
-/// RTPS StatefulWriter
+/// RTPS Writer
+///
+/// A BestEffort user-defined Writer works as a StatelessWriter, which sends each change once
+/// to each unique locator of the matched Readers and the fixed locators.
+/// Other Writers work as a StatefulWriter, which keeps the state of each change for each matched Reader.
 pub struct Writer {
     // Entity
     guid: GUID,
@@ -57,22 +77,47 @@ pub struct Writer {
     writer_cache: Arc<RwLock<HistoryCache>>,
     data_max_size_serialized: i32,
     // StatelessWriter
-    _reader_locators: Vec<ReaderLocator>,
+    reader_locators: BTreeMap<Locator, ReaderLocator>,
+    /// locators the StatelessWriter sends changes to without discovery
+    fixed_locators: Vec<Locator>,
     // StatefulWriter
     matched_readers: BTreeMap<GUID, ReaderProxy>,
     total_matched_readers: BTreeSet<GUID>,
     // This implementation spesific
     topic: Topic,
     qos: DataWriterQosPolicies,
+    /// Partition of the Publisher this Writer belongs to
+    partition: Partition,
+    /// Readers which match except for Partition.
+    /// They are re-evaluated when the Partition is changed.
+    partition_mismatched_readers: BTreeMap<GUID, ReaderProxy>,
     endianness: Endianness,
+    /// priority of the sender sockets mapped from TransportPriority
+    socket_priority: Option<SocketPriority>,
     pub writer_command_receiver: mio_channel::Receiver<WriterCmd>,
     writer_state_notifier: mio_channel::Sender<DataWriterStatusChanged>,
     participant_msg_cmd_sender: mio_channel::SyncSender<ParticipantMessageCmd>,
     udp_sender: Rc<UdpSender>,
+    disc_db: DiscoveryDB,
     hb_counter: Count,
+    hb_frag_counter: Count,
+    /// count of the last NACK_FRAG received from each Reader
+    nack_frag_counts: BTreeMap<GUID, Count>,
+    /// SerializedPayload larger than this is sent by DATA_FRAG
+    fragment_size: u16,
+    /// upper limit of the size of an RTPS Message packing multiple submessages
+    max_message_size: usize,
     an_state: AckNackState,
     unmatch_count: i32,
+    offered_deadline_missed_count: i32,
     is_alive: bool,
+    /// whether DATA are batched and waiting for the LatencyBudget timer
+    is_batching: bool,
+    /// estimated size of the batched DATA
+    batched_size: usize,
+    /// period of the fast HEARTBEAT sent while some changes are not acknowledged.
+    /// None if all changes are acknowledged and HEARTBEAT is sent every heartbeat_period.
+    fast_heartbeat_period: Option<CoreDuration>,
 }
 
 #[derive(PartialEq, Eq)]
@@ -83,7 +128,11 @@ enum AckNackState {
 }
 
 impl Writer {
-    pub fn new(wi: WriterIngredients, udp_sender: Rc<UdpSender>) -> (Self, Option<WriterTimer>) {
+    pub fn new(
+        wi: WriterIngredients,
+        udp_sender: Rc<UdpSender>,
+        disc_db: DiscoveryDB,
+    ) -> (Self, Option<WriterTimer>) {
         let mut msg = String::new();
         msg += "\tunicast locators\n";
         for loc in &wi.unicast_locator_list {
@@ -102,10 +151,14 @@ impl Writer {
         );
         let writer_cache = wi.whc;
         writer_cache.write().add_empty_change(wi.guid);
+        // DDS 1.4 spec, 2.2.3.7 DEADLINE
+        // Deadline is tracked per instance. A keyless Topic has only one instance,
+        // so its deadline starts when the Writer is created.
         let deadline_period = wi.qos.deadline().period;
-        let wt = if deadline_period != Duration::INFINITE {
+        let wt = if deadline_period != Duration::INFINITE && wi.topic.kind() == TopicKind::NoKey {
             Some(WriterTimer::Deadline(
                 wi.guid.entity_id,
+                InstantHandle::NIL,
                 deadline_period.into(),
             ))
         } else {
@@ -124,20 +177,37 @@ impl Writer {
                 _nack_suppression_duration: wi.nack_suppression_duration,
                 writer_cache,
                 data_max_size_serialized: wi.data_max_size_serialized,
-                _reader_locators: Vec::new(),
+                reader_locators: wi
+                    .fixed_locators
+                    .iter()
+                    .map(|loc| (*loc, ReaderLocator::new(*loc, false)))
+                    .collect(),
+                fixed_locators: wi.fixed_locators,
                 matched_readers: BTreeMap::new(),
                 total_matched_readers: BTreeSet::new(),
                 topic: wi.topic,
                 qos: wi.qos,
+                partition: wi.partition,
+                partition_mismatched_readers: BTreeMap::new(),
                 endianness: Endianness::LittleEndian,
+                socket_priority: wi.socket_priority,
                 writer_command_receiver: wi.writer_command_receiver,
                 writer_state_notifier: wi.writer_state_notifier,
                 participant_msg_cmd_sender: wi.participant_msg_cmd_sender,
                 udp_sender,
+                disc_db,
                 hb_counter: 0,
+                hb_frag_counter: 0,
+                nack_frag_counts: BTreeMap::new(),
+                fragment_size: wi.fragment_size,
+                max_message_size: wi.max_message_size,
                 an_state: AckNackState::Waiting,
                 unmatch_count: 0,
+                offered_deadline_missed_count: 0,
                 is_alive: true,
+                is_batching: false,
+                batched_size: 0,
+                fast_heartbeat_period: None,
             },
             wt,
         )
@@ -148,7 +218,7 @@ impl Writer {
     }
 
     pub fn sedp_data(&self) -> DiscoveredWriterData {
-        let proxy = WriterProxy::new(
+        let mut proxy = WriterProxy::new(
             self.guid,
             self.unicast_locator_list.clone(),
             self.multicast_locator_list.clone(),
@@ -158,6 +228,7 @@ impl Writer {
             self.qos.clone(),
             Arc::new(RwLock::new(HistoryCache::new(HistoryCacheType::Dummy))),
         );
+        proxy.partition = self.partition.clone();
         let pub_data = self.topic.pub_builtin_topic_data();
         DiscoveredWriterData::new(proxy, pub_data)
     }
@@ -169,22 +240,101 @@ impl Writer {
         }
     }
 
-    pub fn _reader_locator_add(&mut self, locator: ReaderLocator) {
-        // StatelessWriter
-        self._reader_locators.push(locator)
+    /// whether this Writer works as a StatelessWriter
+    ///
+    /// Built-in BestEffort Writers are StatefulWriters,
+    /// because the SPDP Writer sends DATA to each discovered Participant.
+    pub fn is_stateless(&self) -> bool {
+        !self.is_reliable() && !self.entity_id().is_builtin()
     }
 
-    pub fn _reader_locator_remove(&mut self, locator: ReaderLocator) {
-        // StatelessWriter
-        if let Some(loc_pos) = self._reader_locators.iter().position(|rl| *rl == locator) {
-            self._reader_locators.remove(loc_pos);
+    /// Recompute the ReaderLocators of the StatelessWriter from the fixed locators
+    /// and the locators of the matched Readers.
+    ///
+    /// Multicast locators of the Readers are preferred, so that a change is sent once
+    /// to all Readers joining the same multicast group.
+    /// Each Reader is reached through as few locators as possible, so that it doesn't receive duplicates.
+    ///
+    /// With TransientLocal or higher Durability, the kept history is sent to the locators
+    /// through which new Readers are reached.
+    fn update_reader_locators(&mut self) {
+        if !self.is_stateless() {
+            return;
         }
-    }
-
-    pub fn _unsent_changes_reset(&mut self) {
-        // StatelessWriter
-        for rl in &mut self._reader_locators {
-            rl.unsent_changes = self.writer_cache.read().changes.values().cloned().collect();
+        // (expects_inline_qos, Readers reached through the locator)
+        let mut locators: BTreeMap<Locator, (bool, BTreeSet<GUID>)> = self
+            .fixed_locators
+            .iter()
+            .map(|loc| (*loc, (false, BTreeSet::new())))
+            .collect();
+        let reader_locators: Vec<(GUID, Vec<Locator>)> = self
+            .matched_readers
+            .values()
+            .filter_map(|reader_proxy| {
+                Self::get_multicast_ll_from_proxy(self.guid, reader_proxy)
+                    .map(|ll| (reader_proxy.remote_reader_guid, ll))
+            })
+            .collect();
+        for (_reid, loc) in Self::min_message_cover(&reader_locators) {
+            // the change sent to the locator is received by all Readers which have it
+            let (expects_inline_qos, readers) =
+                locators.entry(loc).or_insert((false, BTreeSet::new()));
+            for (guid, _ll) in reader_locators
+                .iter()
+                .filter(|(_guid, ll)| ll.contains(&loc))
+            {
+                *expects_inline_qos |= self.matched_readers[guid].expects_inline_qos;
+                readers.insert(*guid);
+            }
+        }
+        self.reader_locators
+            .retain(|loc, _| locators.contains_key(loc));
+        // the changes released to the RTPS Writer.
+        // Volatile Writer removes changes after sending them, so they are the ones waiting to be sent.
+        let released: Vec<SequenceNumber> = {
+            let writer_cache = self.writer_cache.read();
+            writer_cache
+                .changes
+                .keys()
+                .map(|k| k.seq_num)
+                .filter(|seq_num| !writer_cache.is_unprocessed(*seq_num))
+                .collect()
+        };
+        let is_durable = self.qos.durability() != Durability::Volatile;
+        let mut is_replayed = false;
+        for (loc, (expects_inline_qos, readers)) in locators {
+            match self.reader_locators.get_mut(&loc) {
+                Some(rl) => {
+                    rl.expects_inline_qos = expects_inline_qos;
+                    // rtps 2.3 spec, 8.4.7.5 RTPS ReaderLocator, unsent_changes_reset
+                    // a new Reader joining the locator receives the kept history
+                    if is_durable && !released.is_empty() && !readers.is_subset(&rl.readers) {
+                        debug!(
+                            "StatelessWriter resend history to ReaderLocator {}\n\tWriter: {}",
+                            loc, self.guid
+                        );
+                        rl.unsent_changes_reset(released.iter().copied());
+                        is_replayed = true;
+                    }
+                    rl.readers = readers;
+                }
+                None => {
+                    debug!(
+                        "StatelessWriter add ReaderLocator {}\n\tWriter: {}",
+                        loc, self.guid
+                    );
+                    // rtps 2.3 spec, 8.4.7.5 RTPS ReaderLocator, unsent_changes_reset
+                    let mut rl = ReaderLocator::new(loc, expects_inline_qos);
+                    rl.unsent_changes_reset(released.iter().copied());
+                    rl.readers = readers;
+                    self.reader_locators.insert(loc, rl);
+                    is_replayed |= is_durable && !released.is_empty();
+                }
+            }
+        }
+        // the history is sent to the late-joining Readers without waiting for the next write
+        if is_replayed {
+            self.send_unsent_changes();
         }
     }
 
@@ -197,9 +347,7 @@ impl Writer {
         while let Ok(cmd) = self.writer_command_receiver.try_recv() {
             match cmd {
                 WriterCmd::WriteData => {
-                    if let Some(wt) = self.handle_write_data_cmd() {
-                        wtv.push(wt);
-                    }
+                    wtv.append(&mut self.handle_write_data_cmd());
                 }
                 WriterCmd::AssertLiveliness => self.assert_liveliness_manually(),
             }
@@ -244,16 +392,25 @@ impl Writer {
         self.send_heart_beat(true);
     }
 
-    fn handle_write_data_cmd(&mut self) -> Option<WriterTimer> {
-        self.is_alive = true;
+    fn handle_write_data_cmd(&mut self) -> Vec<WriterTimer> {
+        let mut wts = Vec::new();
+        if self.add_written_changes(&mut wts) {
+            self.send_unsent_changes();
+        }
+        wts
+    }
 
-        let wt: Option<WriterTimer>;
+    /// Register the changes written by the DataWriter to the ReaderProxies or ReaderLocators.
+    ///
+    /// Return whether the unsent changes should be sent now.
+    /// They are not sent if they are batched by LatencyBudget.
+    fn add_written_changes(&mut self, wts: &mut Vec<WriterTimer>) -> bool {
+        self.is_alive = true;
 
         // get changes from HistoryCache and register it to cache_state of ReaderProxy
         let history = self.qos.history();
         let hkind = history.kind;
         let hdepth = history.depth;
-        let durability = self.qos.durability();
         let seq_nums = self.writer_cache.write().get_unprocessed();
         debug!(
             "handle_write_data_cmd: unprocessed {:?}\n\tWriter: {}",
@@ -266,48 +423,226 @@ impl Writer {
                 // called Writer::handle_write_data_cmd but writer_cache.unprocessed is empty.
                 // This decrease occurs when multiple data samples are written to the same DataWriter
                 // within a short period of time.
-                wt = None;
-                return wt;
+                return false;
             }
         };
-        for (i, seq_num) in seq_nums.iter().rev().enumerate() {
-            for reader_proxy in self.matched_readers.values_mut() {
-                reader_proxy.update_cache_state(
-                    *seq_num,
-                    /* TODO: if DDS_FILTER(reader_proxy, change) { false } else { true }, */
-                    match hkind {
-                        HistoryQosKind::KeepAll => true,
-                        HistoryQosKind::KeepLast => match durability {
-                            // to keep only the latest hdepth changes
-                            Durability::Volatile => i < hdepth as usize,
-                            // to retaine one additional Change
-                            Durability::TransientLocal => i <= hdepth as usize,
+        if self.is_stateless() {
+            // rtps 2.3 spec, 8.4.8.1 Best-Effort StatelessWriter Behavior
+            // the changes replaced due to HistoryQosKind::KeepLast are notified by GAP when sending.
+            for rl in self.reader_locators.values_mut() {
+                for seq_num in &seq_nums {
+                    rl.add_unsent_change(*seq_num);
+                }
+            }
+        } else {
+            // position of each unprocessed change in its instance, from the latest one
+            let mut instance_count: BTreeMap<InstantHandle, usize> = BTreeMap::new();
+            let positions: Vec<(SequenceNumber, usize)> = {
+                let writer_cache = self.writer_cache.read();
+                seq_nums
+                    .iter()
+                    .rev()
+                    .map(|seq_num| {
+                        let instance = writer_cache
+                            .get_change(self.guid, *seq_num)
+                            .map_or(InstantHandle::NIL, |c| c.instance_handle());
+                        let count = instance_count.entry(instance).or_insert(0);
+                        let i = *count;
+                        *count += 1;
+                        (*seq_num, i)
+                    })
+                    .collect()
+            };
+            for (seq_num, i) in positions.iter().map(|(sn, i)| (sn, *i)) {
+                for reader_proxy in self.matched_readers.values_mut() {
+                    reader_proxy.update_cache_state(
+                        *seq_num,
+                        /* TODO: if DDS_FILTER(reader_proxy, change) { false } else { true }, */
+                        match hkind {
+                            HistoryQosKind::KeepAll => true,
+                            // to keep only the latest hdepth changes of each instance
+                            HistoryQosKind::KeepLast => i < hdepth as usize,
                         },
-                    },
-                    if self.push_mode {
-                        ChangeForReaderStatusKind::Unsent
-                    } else {
-                        ChangeForReaderStatusKind::Unacknowledged
-                    },
-                )
+                        if self.push_mode {
+                            ChangeForReaderStatusKind::Unsent
+                        } else {
+                            ChangeForReaderStatusKind::Unacknowledged
+                        },
+                    )
+                }
+            }
+        }
+        // DDS 1.4 spec, 2.2.3.7 DEADLINE
+        // the deadline of the instance is reset by each write,
+        // and it is no longer tracked after the instance is disposed or unregistered.
+        let deadline_period = self.qos.deadline().period;
+        if deadline_period != Duration::INFINITE {
+            let writer_cache = self.writer_cache.read();
+            for change in seq_nums
+                .iter()
+                .filter_map(|seq_num| writer_cache.get_change(self.guid, *seq_num))
+                .filter(|change| !change.is_coherent_set_end())
+            {
+                if change.kind() == ChangeKind::Alive {
+                    wts.push(WriterTimer::Deadline(
+                        self.guid.entity_id,
+                        change.instance_handle(),
+                        deadline_period.into(),
+                    ));
+                } else {
+                    wts.push(WriterTimer::CancelDeadline(
+                        self.guid.entity_id,
+                        change.instance_handle(),
+                    ));
+                }
             }
         }
+        if self.is_stateless()
+            && self.reader_locators.is_empty()
+            && self.qos.durability() == Durability::Volatile
+        {
+            // there is no locator to send the changes to,
+            // and Volatile BestEffort Writer does not keep them for late-joining Readers.
+            // With TransientLocal or higher Durability, the changes are kept until
+            // a Reader is matched and they are sent to the new ReaderLocator.
+            let mut writer_cache = self.writer_cache.write();
+            for seq_num in &seq_nums {
+                writer_cache.remove_change_if_exist(&HCKey::new(self.guid, *seq_num));
+            }
+            return false;
+        }
         if self.is_reliable() {
             self.remove_acked_changes(oldest_unprocessed);
+            // send HEARTBEAT faster until the new changes are acknowledged
+            let fast_period = self.heartbeat_period() / FAST_HEARTBEAT_DIVISOR;
+            if self
+                .fast_heartbeat_period
+                .is_none_or(|period| period > fast_period)
+            {
+                self.fast_heartbeat_period = Some(fast_period);
+                wts.push(WriterTimer::Heartbeat(self.guid.entity_id, fast_period));
+            }
         }
 
-        let deadline_period = self.qos.deadline().period;
-        if deadline_period != Duration::INFINITE {
-            wt = Some(WriterTimer::Deadline(
-                self.guid.entity_id,
-                deadline_period.into(),
-            ));
+        // DDS 1.4 spec, 2.2.3.16 LIFESPAN
+        // Volatile BestEffort Writer removes changes after sending them,
+        // and the other Writers keep history, so they need to remove expired changes.
+        let lifespan = self.qos.lifespan().0;
+        let keeps_history = self.is_reliable() || self.qos.durability() != Durability::Volatile;
+        if lifespan != Duration::INFINITE && keeps_history {
+            let writer_cache = self.writer_cache.read();
+            for seq_num in &seq_nums {
+                if let Some(change) = writer_cache.get_change(self.guid, *seq_num) {
+                    wts.push(WriterTimer::Lifespan(
+                        self.guid.entity_id,
+                        *seq_num,
+                        change.timestamp,
+                        lifespan.into(),
+                    ));
+                }
+            }
+        }
+
+        // DDS 1.4 spec, 2.2.3.8 LATENCY_BUDGET
+        // DATA are not sent immediately but batched for up to the latency budget,
+        // so that DATA for the same destination are coalesced into one RTPS Message.
+        let latency_budget = self.qos.latency_budget().0;
+        if latency_budget != Duration::ZERO && !self.entity_id().is_builtin() {
+            {
+                let writer_cache = self.writer_cache.read();
+                self.batched_size += seq_nums
+                    .iter()
+                    .filter_map(|seq_num| writer_cache.get_change(self.guid, *seq_num))
+                    // INFO_TS (12 octets) + DATA (24 octets) + SerializedPayload
+                    .map(|change| 36 + change.data_value().map_or(0, |d| 4 + d.value.len()))
+                    .sum::<usize>();
+            }
+            if self.batched_size < min(BATCH_MESSAGE_SIZE, self.max_message_size) {
+                if !self.is_batching {
+                    self.is_batching = true;
+                    wts.push(WriterTimer::LatencyBudget(
+                        self.guid.entity_id,
+                        latency_budget.into(),
+                    ));
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    /// send DATA batched by LatencyBudget
+    pub fn flush_batch(&mut self) {
+        if self.is_batching {
+            self.send_unsent_changes();
+        }
+    }
+
+    /// DDS 1.4 spec, 2.2.2.4.1.9 resume_publications
+    /// Take the changes written while the publications are suspended,
+    /// and return the submessages of the unsent changes instead of sending them,
+    /// so that the submessages of all Writers of the Publisher are packed into the same RTPS Messages.
+    pub fn resume_publication(&mut self) -> (Vec<WriterTimer>, Vec<(Locator, MessageBuilder)>) {
+        let mut wts = Vec::new();
+        let messages = if self.add_written_changes(&mut wts) {
+            self.unsent_change_messages()
         } else {
-            wt = None;
+            Vec::new()
+        };
+        (wts, messages)
+    }
+
+    /// MessagePacker for the submessages of this Writer
+    pub fn message_packer(&self) -> MessagePacker {
+        let max_message_size = if self.qos.latency_budget().0 != Duration::ZERO {
+            min(BATCH_MESSAGE_SIZE, self.max_message_size)
+        } else {
+            self.max_message_size
+        };
+        MessagePacker::new(self.guid_prefix(), self.endianness, max_message_size, None)
+    }
+
+    pub fn socket_priority(&self) -> Option<SocketPriority> {
+        self.socket_priority
+    }
+
+    fn send_unsent_changes(&mut self) {
+        // pack the submessages destined for the same locator
+        // into as few RTPS Messages as possible
+        let mut packers: BTreeMap<Locator, MessagePacker> = BTreeMap::new();
+        for (loc, submessages) in self.unsent_change_messages() {
+            packers
+                .entry(loc)
+                .or_insert_with(|| self.message_packer())
+                .append(submessages);
+        }
+        for (loc, packer) in packers {
+            for message_buf in packer.finish() {
+                self.send_msg_to_locator(loc, message_buf, "data");
+            }
         }
+    }
 
+    /// DATA, GAP and HEARTBEAT submessages of the unsent changes for each locator
+    ///
+    /// Each MessageBuilder is a group of submessages which must be sent in the same RTPS Message.
+    fn unsent_change_messages(&mut self) -> Vec<(Locator, MessageBuilder)> {
+        self.is_batching = false;
+        self.batched_size = 0;
+        if self.is_stateless() {
+            return self.unsent_change_messages_to_locators();
+        }
+        let piggyback_hb = if self.is_reliable() {
+            self.hb_counter += 1;
+            let writer_cache = self.writer_cache.read();
+            Some((
+                writer_cache.get_seq_num_min(),
+                writer_cache.get_seq_num_max(),
+            ))
+        } else {
+            None
+        };
         let self_guid = self.guid();
-        let self_guid_prefix = self.guid_prefix();
         let self_entity_id = self.entity_id();
         let mut to_send_data: BTreeMap<SequenceNumber, Vec<(GUID, Vec<Locator>)>> = BTreeMap::new();
         let mut to_send_gap: BTreeMap<SequenceNumber, Vec<(GUID, Vec<Locator>)>> = BTreeMap::new();
@@ -335,7 +670,14 @@ impl Writer {
                         continue;
                     }
                 };
-                if change_for_reader.is_relevant {
+                // the change may have been replaced by a newer change of the same instance
+                // due to HistoryQosKind::KeepLast
+                let is_available = self
+                    .writer_cache
+                    .read()
+                    .get_change(self_guid, seq_num)
+                    .is_some();
+                if change_for_reader.is_relevant && is_available {
                     if let std::collections::btree_map::Entry::Vacant(e) =
                         to_send_data.entry(seq_num)
                     {
@@ -354,65 +696,201 @@ impl Writer {
                 }
             }
         }
-        for seq_num in to_send_data.keys() {
-            if let Some(aa_change) = self.writer_cache.read().get_change(self.guid, *seq_num) {
-                let reader_locators = to_send_data.get(seq_num).unwrap();
-                let send_list = Self::min_message_cover(reader_locators);
-                for (reid, loc) in send_list {
-                    // build RTPS Message
-                    let mut message_builder = MessageBuilder::new();
-                    let time_stamp = Timestamp::now();
-                    message_builder.info_ts(Endianness::LittleEndian, time_stamp);
-                    message_builder.data(
-                        Endianness::LittleEndian,
-                        self.guid.entity_id,
+        let mut to_send_data_per_dst: BTreeMap<(EntityId, Locator), Vec<SequenceNumber>> =
+            BTreeMap::new();
+        for (seq_num, reader_locators) in &to_send_data {
+            for dst in Self::min_message_cover(reader_locators) {
+                to_send_data_per_dst.entry(dst).or_default().push(*seq_num);
+            }
+        }
+        let mut submessages_per_dst: BTreeMap<(EntityId, Locator), Vec<MessageBuilder>> =
+            BTreeMap::new();
+        for ((reid, loc), seq_nums) in to_send_data_per_dst {
+            let mut submessages = Vec::new();
+            for seq_num in seq_nums {
+                if let Some(aa_change) = self.writer_cache.read().get_change(self.guid, seq_num) {
+                    let hb_frag_count =
+                        if self.is_reliable() && self.fragment_count(aa_change).is_some() {
+                            self.hb_frag_counter += 1;
+                            Some(self.hb_frag_counter)
+                        } else {
+                            None
+                        };
+                    for change_builder in self.change_messages(
                         reid,
                         aa_change,
-                    );
-                    // TODO: piggybacking HB
-                    let message = message_builder.build(self_guid_prefix);
-                    let message_buf = message
-                        .write_to_vec_with_ctx(self.endianness)
-                        .expect("failed to serialize message");
-                    self.send_msg_to_locator(loc, message_buf, "data");
+                        None,
+                        hb_frag_count,
+                        self.expects_inline_qos((reid, loc)),
+                    ) {
+                        submessages.push(change_builder);
+                    }
+                } else {
+                    unreachable!("Writer::handle_write_data_cmd, attempt to get non-existent change with HCKey {{ guid: {}, seq_num: {} }} from writer_cache", self.guid, seq_num.0)
                 }
-            } else {
-                unreachable!("Writer::handle_write_data_cmd, attempt to get non-existent change with HCKey {{ guid: {}, seq_num: {} }} from writer_cache", self.guid, seq_num.0)
-            }
-            if !self.is_reliable() {
-                self.writer_cache
-                    .write()
-                    .remove_change(&HCKey::new(self.guid, *seq_num), false);
             }
+            submessages_per_dst.insert((reid, loc), submessages);
         }
-        for seq_num in to_send_gap.keys() {
-            let reader_locators = to_send_gap.get(seq_num).unwrap();
-            let send_list = Self::min_message_cover(reader_locators);
-            for (reid, loc) in send_list {
-                let mut message_builder = MessageBuilder::new();
-                // let time_stamp = Timestamp::now();
-                // message_builder.info_ts(Endianness::LittleEndian, time_stamp);
-                let gap_start = *seq_num;
-                message_builder.gap(
-                    Endianness::LittleEndian,
+        for (dst, seq_nums) in Self::gaps_per_dst(&to_send_gap) {
+            submessages_per_dst
+                .entry(dst)
+                .or_default()
+                .push(self.gap_message(dst.0, &seq_nums));
+        }
+        let mut messages = Vec::new();
+        for ((reid, loc), mut submessages) in submessages_per_dst {
+            if let Some((first_sn, last_sn)) = piggyback_hb {
+                // rtps 2.3 spec, 8.4.15.4 Piggybacking HeartBeat submessages
+                // piggyback HEARTBEAT on DATA so that the Reader can detect missing changes
+                // without waiting for the periodic HEARTBEAT.
+                // The Final flag is set, so the Reader sends ACKNACK only if some changes are missing.
+                let mut hb_builder = MessageBuilder::new();
+                hb_builder.heartbeat(
+                    self.endianness,
+                    false,
                     self.guid.entity_id,
                     reid,
-                    gap_start,
-                    SequenceNumberSet::from_vec(gap_start + SequenceNumber(1), vec![]),
+                    first_sn,
+                    last_sn,
+                    self.hb_counter - 1,
+                    true,
                 );
-                let message = message_builder.build(self_guid_prefix);
-                let message_buf = message
-                    .write_to_vec_with_ctx(self.endianness)
-                    .expect("failed to serialize message");
-                self.send_msg_to_locator(loc, message_buf, "gap");
+                submessages.push(hb_builder);
             }
-            if !self.is_reliable() {
-                self.writer_cache
-                    .write()
-                    .remove_change(&HCKey::new(self.guid, *seq_num), false);
+            messages.extend(submessages.into_iter().map(|m| (loc, m)));
+        }
+        if !self.is_reliable() {
+            let mut writer_cache = self.writer_cache.write();
+            for seq_num in to_send_data.keys() {
+                writer_cache.remove_change(&HCKey::new(self.guid, *seq_num), false);
+            }
+            for seq_num in to_send_gap.keys() {
+                writer_cache.remove_change_if_exist(&HCKey::new(self.guid, *seq_num));
             }
         }
-        wt
+        messages
+    }
+
+    /// rtps 2.3 spec, 8.4.8.1 Best-Effort StatelessWriter Behavior
+    /// submessages of each unsent change for each ReaderLocator.
+    ///
+    /// Volatile Writer removes the sent changes. With TransientLocal or higher Durability,
+    /// they are kept within History and ResourceLimits for late-joining Readers.
+    fn unsent_change_messages_to_locators(&mut self) -> Vec<(Locator, MessageBuilder)> {
+        let mut messages = Vec::new();
+        let mut to_send: Vec<(Locator, bool, Vec<SequenceNumber>)> = Vec::new();
+        let mut sent: BTreeSet<SequenceNumber> = BTreeSet::new();
+        for rl in self.reader_locators.values_mut() {
+            let mut seq_nums = Vec::new();
+            while let Some(seq_num) = rl.next_unsent_change() {
+                seq_nums.push(seq_num);
+            }
+            sent.extend(&seq_nums);
+            to_send.push((rl.locator, rl.expects_inline_qos, seq_nums));
+        }
+        for (loc, expects_inline_qos, seq_nums) in to_send {
+            if seq_nums.is_empty() {
+                continue;
+            }
+            // the changes replaced by a newer change of the same instance
+            // due to HistoryQosKind::KeepLast
+            let mut irrelevant = Vec::new();
+            for seq_num in seq_nums {
+                match self.writer_cache.read().get_change(self.guid, seq_num) {
+                    // a change sent to a locator may be received by multiple Readers,
+                    // so readerId is ENTITYID_UNKNOWN.
+                    Some(change) => {
+                        for change_builder in self.change_messages(
+                            EntityId::UNKNOW,
+                            change,
+                            None,
+                            None,
+                            expects_inline_qos,
+                        ) {
+                            messages.push((loc, change_builder));
+                        }
+                    }
+                    None => irrelevant.push(seq_num),
+                }
+            }
+            if !irrelevant.is_empty() {
+                messages.push((loc, self.gap_message(EntityId::UNKNOW, &irrelevant)));
+            }
+        }
+        if self.qos.durability() == Durability::Volatile {
+            let mut writer_cache = self.writer_cache.write();
+            for seq_num in sent {
+                writer_cache.remove_change_if_exist(&HCKey::new(self.guid, seq_num));
+            }
+        }
+        messages
+    }
+
+    fn send_gap(&self, seq_num: SequenceNumber, reader_locators: &[(GUID, Vec<Locator>)]) {
+        let send_list = Self::min_message_cover(reader_locators);
+        for (reid, loc) in send_list {
+            let message = self.gap_message(reid, &[seq_num]).build(self.guid_prefix());
+            let message_buf = message
+                .write_to_vec_with_ctx(self.endianness)
+                .expect("failed to serialize message");
+            self.send_msg_to_locator(loc, message_buf, "gap");
+        }
+    }
+
+    /// GAP for the irrelevant `seq_nums`, coalesced into as few submessages as possible
+    fn gap_message(&self, reid: EntityId, seq_nums: &[SequenceNumber]) -> MessageBuilder {
+        let mut message_builder = MessageBuilder::new();
+        // let time_stamp = Timestamp::now();
+        // message_builder.info_ts(Endianness::LittleEndian, time_stamp);
+        message_builder.gaps(
+            Endianness::LittleEndian,
+            self.guid.entity_id,
+            reid,
+            seq_nums,
+        );
+        message_builder
+    }
+
+    /// irrelevant SequenceNumbers for each destination
+    fn gaps_per_dst(
+        to_send_gap: &BTreeMap<SequenceNumber, Vec<(GUID, Vec<Locator>)>>,
+    ) -> BTreeMap<(EntityId, Locator), Vec<SequenceNumber>> {
+        let mut gaps_per_dst: BTreeMap<(EntityId, Locator), Vec<SequenceNumber>> = BTreeMap::new();
+        for (seq_num, reader_locators) in to_send_gap {
+            for dst in Self::min_message_cover(reader_locators) {
+                gaps_per_dst.entry(dst).or_default().push(*seq_num);
+            }
+        }
+        gaps_per_dst
+    }
+
+    /// DDS 1.4 spec, 2.2.3.16 LIFESPAN
+    /// Remove the expired change from the HistoryCache and send GAP to the Readers
+    /// which have not acknowledged it, so that the change is never delivered
+    /// to late-joining Readers or in repairs.
+    pub fn remove_expired_change(&mut self, seq_num: SequenceNumber) {
+        let key = HCKey::new(self.guid, seq_num);
+        if !self.writer_cache.read().changes.contains_key(&key) {
+            // already removed because it is acknowledged by all Readers or replaced by newer change
+            return;
+        }
+        debug!(
+            "Writer remove expired change, seq_num: {}\n\tWriter: {}",
+            seq_num.0, self.guid
+        );
+        self.writer_cache.write().remove_change(&key, false);
+        let self_guid = self.guid;
+        let mut reader_locators = Vec::new();
+        for reader_proxy in self.matched_readers.values_mut() {
+            if reader_proxy.is_acked(seq_num) {
+                continue;
+            }
+            reader_proxy.update_cache_state(seq_num, false, ChangeForReaderStatusKind::Underway);
+            if let Some(ll_u) = Self::get_unicast_ll_from_proxy(self_guid, reader_proxy) {
+                reader_locators.push((reader_proxy.remote_reader_guid, ll_u));
+            }
+        }
+        self.send_gap(seq_num, &reader_locators);
     }
 
     pub fn send_builtin_data_for_loc(
@@ -427,7 +905,7 @@ impl Writer {
             SequenceNumber(1),
             time_stamp,
             Some(builtin_data),
-            InstantHandle {},
+            InstantHandle::NIL,
         );
         let mut message_builder = MessageBuilder::new();
         message_builder.info_ts(Endianness::LittleEndian, Some(time_stamp));
@@ -446,8 +924,14 @@ impl Writer {
         }
     }
 
+    /// whether all changes released to the RTPS Writer are acknowledged
     fn is_acked_all_changes(&self) -> bool {
-        for key in self.writer_cache.read().changes.keys() {
+        let writer_cache = self.writer_cache.read();
+        for key in writer_cache.changes.keys() {
+            // the changes not released yet are not announced by HEARTBEAT
+            if writer_cache.is_unprocessed(key.seq_num) {
+                continue;
+            }
             if !self.is_acked_by_all(key.seq_num) {
                 return false;
             }
@@ -459,9 +943,10 @@ impl Writer {
         // `remove_acked_changes` should be called before checking liveliness and `is_acked_all_changes`.
         // However, when launching many nodes (102 nodes per host across 2 hosts), we observed persistent unicast packet drops on 1–3 hosts after discovery completed.
         // As a mitigation, we first check liveliness and `is_acked_all_changes`, then call `remove_acked_changes`.
+        let is_acked_all = self.is_acked_all_changes();
         if liveliness {
             self.is_alive = true;
-        } else if self.is_acked_all_changes() {
+        } else if is_acked_all {
             // if all changes is acked and not for liveliness assersion, do nothing
             return;
         }
@@ -501,6 +986,9 @@ impl Writer {
             // build RTPS Message
             let mut message_builder = MessageBuilder::new();
             message_builder.info_ts(Endianness::LittleEndian, time_stamp);
+            // rtps 2.3 spec, 8.3.7.5 Heartbeat
+            // the Final flag indicates that the Reader is not required to respond,
+            // so it is set if all changes are acknowledged.
             message_builder.heartbeat(
                 self.endianness,
                 liveliness,
@@ -509,7 +997,7 @@ impl Writer {
                 first_sn,
                 last_sn,
                 self.hb_counter - 1,
-                false,
+                is_acked_all,
             );
             let msg = message_builder.build(self_guid_prefix);
             let message_buf = msg
@@ -526,9 +1014,15 @@ impl Writer {
         }
     }
 
-    pub fn handle_acknack(&mut self, acknack: AckNack, reader_guid: GUID) -> Option<WriterTimer> {
+    pub fn handle_acknack(
+        &mut self,
+        acknack: AckNack,
+        reader_guid: GUID,
+        reply_locators: &[Locator],
+    ) -> Option<WriterTimer> {
         let wt: Option<WriterTimer>;
         if let Some(reader_proxy) = self.matched_readers.get_mut(&reader_guid) {
+            reader_proxy.reply_locator_list = reply_locators.to_vec();
             let req_seq_num_set = acknack.reader_sn_state.set();
             trace!(
                 "Writer handle acknack from Reader\n\tSNState: {}, Set: {:?}\n\tWriter: {}\n\tReader: {}",
@@ -588,8 +1082,26 @@ impl Writer {
                     ChangeForReaderStatusKind::Underway,
                 );
                 let seq_num = change.seq_num;
+                // the change which is no longer in the HistoryCache
+                // (e.g. replaced by KeepLast, or expired by Lifespan) is notified by GAP
+                let is_available = self
+                    .writer_cache
+                    .read()
+                    .changes
+                    .contains_key(&HCKey::new(self_guid, seq_num));
+                let is_relevant = change.is_relevant && is_available;
+                if !is_relevant {
+                    reader_proxy.update_cache_state(
+                        seq_num,
+                        false,
+                        ChangeForReaderStatusKind::Underway,
+                    );
+                }
                 let reader_guid = reader_proxy.remote_reader_guid;
-                let locator_list = if self_entity_id.is_builtin() {
+                let locator_list = if !reader_proxy.reply_locator_list.is_empty() {
+                    // the Reader requested the repairs by the Message with INFO_REPLY
+                    reader_proxy.reply_locator_list.clone()
+                } else if self_entity_id.is_builtin() {
                     // built-in endpoint send DATA via multicast
                     if let Some(ll_m) = Self::get_multicast_ll_from_proxy(self_guid, reader_proxy) {
                         ll_m
@@ -604,7 +1116,7 @@ impl Writer {
                         continue;
                     }
                 };
-                if change.is_relevant {
+                if is_relevant {
                     if let std::collections::btree_map::Entry::Vacant(e) =
                         to_send_data.entry(seq_num)
                     {
@@ -628,80 +1140,267 @@ impl Writer {
                 self.guid, reader_guid
             );
         }
-        for seq_num in to_send_data.keys() {
-            let reader_locators = to_send_data.get(seq_num).unwrap();
+        // the repairs are sent only to the Reader, so INFO_DST is added to each Message
+        let mut packers: BTreeMap<(EntityId, Locator), MessagePacker> = BTreeMap::new();
+        let max_message_size = self.max_message_size;
+        let endianness = self.endianness;
+        let new_packer = || {
+            MessagePacker::new(
+                self_guid_prefix,
+                endianness,
+                max_message_size,
+                Some(reader_guid.guid_prefix),
+            )
+        };
+        for (seq_num, reader_locators) in &to_send_data {
             let send_list = Self::min_message_cover(reader_locators);
             if let Some(aa_change) = self.writer_cache.read().get_change(self.guid, *seq_num) {
-                for (reid, loc) in send_list {
-                    // build RTPS Message
+                let hb_frag_count = if self.fragment_count(aa_change).is_some() {
+                    self.hb_frag_counter += 1;
+                    Some(self.hb_frag_counter)
+                } else {
+                    None
+                };
+                for dst in send_list {
+                    let packer = packers.entry(dst).or_insert_with(new_packer);
+                    for change_builder in self.change_messages(
+                        dst.0,
+                        aa_change,
+                        None,
+                        hb_frag_count,
+                        self.expects_inline_qos(dst),
+                    ) {
+                        packer.append(change_builder);
+                    }
+                }
+            } else {
+                // rtps spec, 8.4.2.2.4 Writers must eventually respond to a negative acknowledgment (reliable only)
+                // the sample is no longer available, so it is notified by GAP
+                to_send_gap
+                    .entry(*seq_num)
+                    .or_default()
+                    .extend(reader_locators.iter().cloned());
+            }
+        }
+        for (dst, seq_nums) in Self::gaps_per_dst(&to_send_gap) {
+            packers
+                .entry(dst)
+                .or_insert_with(new_packer)
+                .append(self.gap_message(dst.0, &seq_nums));
+        }
+        for ((_reid, loc), packer) in packers {
+            for message_buf in packer.finish() {
+                self.send_msg_to_locator(loc, message_buf, "data");
+            }
+        }
+        self.an_state = AckNackState::Waiting;
+    }
+
+    /// rtps 2.3 spec, 8.4.14.1.4 Handling of NackFrag
+    ///
+    /// resend only the fragments requested by the Reader
+    pub fn handle_nackfrag(
+        &mut self,
+        nack_frag: NackFrag,
+        reader_guid: GUID,
+        reply_locators: &[Locator],
+    ) {
+        let locator_list = match self.matched_readers.get(&reader_guid) {
+            // the Reader requested the fragments by the Message with INFO_REPLY
+            Some(_) if !reply_locators.is_empty() => reply_locators.to_vec(),
+            Some(reader_proxy) => match Self::get_unicast_ll_from_proxy(self.guid, reader_proxy) {
+                Some(ll_u) => ll_u,
+                None => return,
+            },
+            None => {
+                trace!(
+                    "Writer received NACK_FRAG from unmatched Reader\n\tWriter: {}\n\tReader: {}",
+                    self.guid,
+                    reader_guid
+                );
+                return;
+            }
+        };
+        // ignore duplicated NACK_FRAG
+        let last_count = self.nack_frag_counts.entry(reader_guid).or_insert(0);
+        if nack_frag.count <= *last_count {
+            return;
+        }
+        *last_count = nack_frag.count;
+
+        let writer_sn = nack_frag.writer_sn;
+        let frag_nums = nack_frag.fragment_number_state.set();
+        debug!(
+            "Writer received NACK_FRAG for fragments {:?} of seq_num: {}\n\tWriter: {}\n\tReader: {}",
+            frag_nums, writer_sn.0, self.guid, reader_guid
+        );
+        let reader_locators = [(reader_guid, locator_list)];
+        let expects_inline_qos = self
+            .matched_readers
+            .get(&reader_guid)
+            .is_some_and(|rp| rp.expects_inline_qos);
+        let writer_cache = self.writer_cache.read();
+        match writer_cache.get_change(self.guid, writer_sn) {
+            Some(change) => {
+                for (reid, loc) in Self::min_message_cover(&reader_locators) {
+                    for message_builder in self.change_messages(
+                        reid,
+                        change,
+                        Some(&frag_nums),
+                        None,
+                        expects_inline_qos,
+                    ) {
+                        let message = message_builder.build(self.guid_prefix());
+                        let message_buf = message
+                            .write_to_vec_with_ctx(self.endianness)
+                            .expect("failed to serialize message");
+                        self.send_msg_to_locator(loc, message_buf, "data_frag");
+                    }
+                }
+            }
+            // the change is no longer available
+            None => self.send_gap(writer_sn, &reader_locators),
+        }
+    }
+
+    /// rtps 2.3 spec, 8.7.2.2.1 InlineQos
+    /// > the Writer must send the QoS policies that affect the matching with the remote Readers,
+    /// > such as the topic name and the partition, to the Readers with expectsInlineQos.
+    fn topic_inline_qos(&self, endianness: Endianness) -> ParameterList {
+        let mut param_list = ParameterList::default();
+        let topic_name = self.topic.name();
+        let mut cdr_str = Vec::with_capacity(4 + topic_name.len() + 1);
+        let cdr_str_len = topic_name.len() as u32 + 1; // +1 is null char
+        match endianness {
+            Endianness::LittleEndian => cdr_str.extend_from_slice(&cdr_str_len.to_le_bytes()),
+            Endianness::BigEndian => cdr_str.extend_from_slice(&cdr_str_len.to_be_bytes()),
+        }
+        cdr_str.extend_from_slice(topic_name.as_bytes());
+        cdr_str.push(0);
+        param_list.push(ParameterId::PID_TOPIC_NAME, cdr_str);
+        if !self.partition.name.is_empty() {
+            param_list.push(
+                ParameterId::PID_PARTITION,
+                self.partition
+                    .write_to_vec_with_ctx(endianness)
+                    .expect("failed to serialize Partition"),
+            );
+        }
+        param_list
+    }
+
+    /// whether a matched Reader reached by sending to `reid` at `loc` expects inline QoS
+    fn expects_inline_qos(&self, (reid, loc): (EntityId, Locator)) -> bool {
+        self.matched_readers.values().any(|rp| {
+            rp.expects_inline_qos
+                && (reid == EntityId::UNKNOW || rp.remote_reader_guid.entity_id == reid)
+                && (rp.get_unicast_locator_list().contains(&loc)
+                    || rp.get_multicast_locator_list().contains(&loc)
+                    || rp.reply_locator_list.contains(&loc))
+        })
+    }
+
+    /// number of fragments of the change, or None if the change is sent by DATA
+    fn fragment_count(&self, change: &CacheChange) -> Option<u32> {
+        // the fragmented data is the SerializedPayload including the encapsulation header (4 octets)
+        let data_size = 4 + change.data_value()?.value.len();
+        if data_size > self.fragment_size as usize {
+            Some(data_size.div_ceil(self.fragment_size as usize) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// RTPS Messages carrying the change to the Reader `reid`
+    ///
+    /// A change larger than the fragment size is carried by DATA_FRAG, one fragment per Message.
+    /// + fragments: fragments to send. None means all fragments.
+    /// + hb_frag_count: if Some, HEARTBEAT_FRAG is appended to the last Message
+    ///   so that the Reader can request missing fragments by NACK_FRAG.
+    /// + expects_inline_qos: if true, the topic name and the partition are sent as inline QoS.
+    fn change_messages(
+        &self,
+        reid: EntityId,
+        change: &CacheChange,
+        fragments: Option<&[FragmentNumber]>,
+        hb_frag_count: Option<Count>,
+        expects_inline_qos: bool,
+    ) -> Vec<MessageBuilder> {
+        let with_inline_qos;
+        let change = if expects_inline_qos {
+            let mut c = change.clone();
+            c.inline_qos = self.topic_inline_qos(Endianness::LittleEndian);
+            with_inline_qos = c;
+            &with_inline_qos
+        } else {
+            change
+        };
+        let mut message_builders = Vec::new();
+        match self.fragment_count(change) {
+            None => {
+                let mut message_builder = MessageBuilder::new();
+                // source timestamp of the change
+                message_builder.info_ts(Endianness::LittleEndian, Some(change.timestamp));
+                message_builder.data(Endianness::LittleEndian, self.guid.entity_id, reid, change);
+                message_builders.push(message_builder);
+            }
+            Some(fragment_count) => {
+                // rtps 2.3 spec, 8.4.14.1 Large Data
+                let frag_nums: Vec<FragmentNumber> = match fragments {
+                    Some(frag_nums) => frag_nums
+                        .iter()
+                        .copied()
+                        .filter(|frag_num| 1 <= *frag_num && *frag_num <= fragment_count)
+                        .collect(),
+                    None => (1..=fragment_count).collect(),
+                };
+                // the fragmented data is the whole SerializedPayload including the encapsulation header.
+                // it is serialized once and each fragment shares the buffer.
+                let payload = change
+                    .data_value()
+                    .expect("attempt to fragment a change without SerializedPayload")
+                    .to_bytes();
+                let data_size = payload.len();
+                for frag_num in frag_nums {
+                    let start = min(
+                        (frag_num as usize - 1) * self.fragment_size as usize,
+                        data_size,
+                    );
+                    let end = min(start + self.fragment_size as usize, data_size);
                     let mut message_builder = MessageBuilder::new();
-                    let time_stamp = Timestamp::now();
-                    message_builder.info_ts(Endianness::LittleEndian, time_stamp);
-                    message_builder.data(
+                    // source timestamp of the change
+                    message_builder.info_ts(Endianness::LittleEndian, Some(change.timestamp));
+                    message_builder.data_frag(
                         Endianness::LittleEndian,
                         self.guid.entity_id,
                         reid,
-                        aa_change,
+                        change,
+                        frag_num,
+                        1,
+                        self.fragment_size,
+                        data_size as u32,
+                        payload.slice(start..end),
                     );
-                    let message = message_builder.build(self_guid_prefix);
-                    let message_buf = message
-                        .write_to_vec_with_ctx(self.endianness)
-                        .expect("failed to serialize message");
-                    self.send_msg_to_locator(loc, message_buf, "data");
+                    message_builders.push(message_builder);
                 }
-            } else {
-                // rtps spec, 8.4.2.2.4 Writers must eventually respond to a negative acknowledgment (reliable only)
-                // send Heartbeat because, the sample is no longer avilable
-                self.hb_counter += 1;
-                let time_stamp = Timestamp::now();
-                let writer_cache = self.writer_cache.read();
-                for (reid, loc) in send_list {
-                    let mut message_builder = MessageBuilder::new();
-                    message_builder.info_ts(Endianness::LittleEndian, time_stamp);
-                    message_builder.heartbeat(
+                if let (Some(count), Some(message_builder)) =
+                    (hb_frag_count, message_builders.last_mut())
+                {
+                    message_builder.heartbeat_frag(
                         self.endianness,
-                        false,
-                        self_entity_id,
+                        self.guid.entity_id,
                         reid,
-                        *seq_num + SequenceNumber(1),
-                        writer_cache.get_seq_num_max(),
-                        self.hb_counter - 1,
-                        false,
+                        change.sequence_number,
+                        fragment_count,
+                        count,
                     );
-                    let msg = message_builder.build(self_guid_prefix);
-                    let message_buf = msg
-                        .write_to_vec_with_ctx(self.endianness)
-                        .expect("failed to serialize message");
-                    self.send_msg_to_locator(loc, message_buf, "heartbeat");
                 }
             }
         }
-        for seq_num in to_send_gap.keys() {
-            let reader_locators = to_send_gap.get(seq_num).unwrap();
-            let send_list = Self::min_message_cover(reader_locators);
-            for (reid, loc) in send_list {
-                let mut message_builder = MessageBuilder::new();
-                // let time_stamp = Timestamp::now();
-                // message_builder.info_ts(Endianness::LittleEndian, time_stamp);
-                let gap_start = *seq_num;
-                message_builder.gap(
-                    Endianness::LittleEndian,
-                    self.guid.entity_id,
-                    reid,
-                    gap_start,
-                    SequenceNumberSet::from_vec(gap_start + SequenceNumber(1), vec![]),
-                );
-                let message = message_builder.build(self_guid_prefix);
-                let message_buf = message
-                    .write_to_vec_with_ctx(self.endianness)
-                    .expect("failed to serialize message");
-                self.send_msg_to_locator(loc, message_buf, "gap");
-            }
-        }
-        self.an_state = AckNackState::Waiting;
+        message_builders
     }
 
-    fn send_msg_to_locator(&self, loc: Locator, msg_buf: Vec<u8>, msg_kind: &str) {
+    pub fn send_msg_to_locator(&self, loc: Locator, msg_buf: Vec<u8>, msg_kind: &str) {
         if loc.kind == Locator::KIND_UDPV4 {
             let port = loc.port;
             let addr = loc.address;
@@ -720,12 +1419,14 @@ impl Writer {
                     &msg_buf,
                     Ipv4Addr::new(addr[12], addr[13], addr[14], addr[15]),
                     port as u16,
+                    self.socket_priority,
                 );
             } else {
                 self.udp_sender.send_to_unicast(
                     &msg_buf,
                     Ipv4Addr::new(addr[12], addr[13], addr[14], addr[15]),
                     port as u16,
+                    self.socket_priority,
                 );
             }
         } else {
@@ -743,19 +1444,33 @@ impl Writer {
             "Writer::remove_acked_changes(base: {:?})\n\tWriter: {}",
             base, self.guid.entity_id
         );
-        let base_ = match self.qos.durability() {
-            Durability::Volatile => base,
-            Durability::TransientLocal => base - SequenceNumber(1),
-        };
         let mut todo_revemo = Vec::new();
-        for key in self.writer_cache.read().changes.keys() {
-            if key.seq_num <= base_ && self.is_acked_by_all(key.seq_num) {
-                todo_revemo.push(*key);
-            } else {
-                debug!(
-                    "Writer don't remove key: {:?} form HistoryCache\n\tWriter: {}",
-                    key.seq_num, self.guid.entity_id
-                );
+        match self.qos.durability() {
+            Durability::Volatile => {
+                let writer_cache = self.writer_cache.read();
+                for key in writer_cache.changes.keys() {
+                    // the changes not released yet (e.g. while the publications are suspended)
+                    // have no state in the ReaderProxies, so they are not acknowledged.
+                    if key.seq_num <= base
+                        && !writer_cache.is_unprocessed(key.seq_num)
+                        && self.is_acked_by_all(key.seq_num)
+                    {
+                        todo_revemo.push(*key);
+                    } else {
+                        debug!(
+                            "Writer don't remove key: {:?} form HistoryCache\n\tWriter: {}",
+                            key.seq_num, self.guid.entity_id
+                        );
+                    }
+                }
+            }
+            Durability::TransientLocal | Durability::Transient | Durability::Persistent => {
+                // DDS 1.4 spec, 2.2.3.4 DURABILITY
+                // acknowledged changes are kept for late-joining Readers.
+                // The HistoryCache keeps the latest `depth` changes of each instance by KeepLast,
+                // and the oldest acknowledged changes are removed so that
+                // the HistoryCache has room for the next change within ResourceLimits.
+                todo_revemo = self.acked_changes_over_resource_limits(base);
             }
         }
         for key in todo_revemo {
@@ -770,6 +1485,91 @@ impl Writer {
         }
     }
 
+    /// Return the oldest acknowledged changes to be removed
+    /// so that each instance and the HistoryCache can accept one more change,
+    /// and the HistoryCache can accept a change of a new instance.
+    ///
+    /// With HistoryQosKind::KeepAll and LENGTH_UNLIMITED ResourceLimits, no acknowledged change is removed,
+    /// so the HistoryCache of a durable Writer grows with every write.
+    /// ResourceLimits must be set to bound it.
+    fn acked_changes_over_resource_limits(&self, base: SequenceNumber) -> Vec<HCKey> {
+        let resource_limits = self.qos.resource_limits();
+        let max_samples = resource_limits.max_samples;
+        let max_samples_per_instanse = resource_limits.max_samples_per_instanse;
+        let max_instance = resource_limits.max_instance;
+        let writer_cache = self.writer_cache.read();
+        let is_removable = |key: &HCKey| {
+            key.seq_num <= base
+                && !writer_cache.is_unprocessed(key.seq_num)
+                && self.is_acked_by_all(key.seq_num)
+        };
+        let mut to_remove = Vec::new();
+        let mut samples = writer_cache.changes.len();
+        let mut instance_samples: BTreeMap<InstantHandle, usize> = BTreeMap::new();
+        for change in writer_cache.changes.values() {
+            // the end of a coherent set belongs to no instance
+            if !change.is_coherent_set_end() {
+                *instance_samples
+                    .entry(change.instance_handle())
+                    .or_insert(0) += 1;
+            }
+        }
+        // HCKey of the same Writer is ordered by SequenceNumber, so the oldest one comes first.
+        for (key, change) in writer_cache.changes.iter() {
+            if !is_removable(key) {
+                continue;
+            }
+            let instance = change.instance_handle();
+            let is_over_samples =
+                max_samples != LENGTH_UNLIMITED && samples >= max_samples as usize;
+            let is_over_samples_per_instance = max_samples_per_instanse != LENGTH_UNLIMITED
+                && instance_samples
+                    .get(&instance)
+                    .is_some_and(|n| *n >= max_samples_per_instanse as usize);
+            if is_over_samples || is_over_samples_per_instance {
+                to_remove.push(*key);
+                samples -= 1;
+                if let Some(n) = instance_samples.get_mut(&instance) {
+                    *n -= 1;
+                }
+            }
+        }
+        // DDS v1.4 spec, 2.2.3.19 RESOURCE_LIMITS
+        // the oldest instance whose changes are all acknowledged is removed
+        // so that the HistoryCache can accept a change of a new instance.
+        let instances = instance_samples.values().filter(|n| **n > 0).count();
+        if max_instance != LENGTH_UNLIMITED && instances >= max_instance as usize {
+            // the latest SequenceNumber of each instance, and whether all the changes are removable
+            let mut latest: BTreeMap<InstantHandle, (SequenceNumber, bool)> = BTreeMap::new();
+            for (key, change) in writer_cache.changes.iter() {
+                if change.is_coherent_set_end() || to_remove.contains(key) {
+                    continue;
+                }
+                let entry = latest
+                    .entry(change.instance_handle())
+                    .or_insert((key.seq_num, true));
+                entry.0 = max(entry.0, key.seq_num);
+                entry.1 &= is_removable(key);
+            }
+            let oldest = latest
+                .iter()
+                .filter(|(_, (_, removable))| *removable)
+                .min_by_key(|(_, (seq_num, _))| *seq_num)
+                .map(|(instance, _)| *instance);
+            if let Some(oldest) = oldest {
+                for (key, change) in writer_cache.changes.iter() {
+                    if change.instance_handle() == oldest
+                        && !change.is_coherent_set_end()
+                        && !to_remove.contains(key)
+                    {
+                        to_remove.push(*key);
+                    }
+                }
+            }
+        }
+        to_remove
+    }
+
     fn get_unicast_ll_from_proxy(
         my_guid: GUID,
         reader_proxy: &ReaderProxy,
@@ -831,7 +1631,7 @@ impl Writer {
         ((ipv4_addr[12] >> 4) ^ 0b1110) == 0
     }
 
-    fn min_message_cover(reader_locators: &Vec<(GUID, Vec<Locator>)>) -> Vec<(EntityId, Locator)> {
+    fn min_message_cover(reader_locators: &[(GUID, Vec<Locator>)]) -> Vec<(EntityId, Locator)> {
         let mut locator_to_readers: BTreeMap<Locator, BTreeSet<GUID>> = BTreeMap::new();
         for (guid, ll) in reader_locators {
             for l in ll {
@@ -848,7 +1648,13 @@ impl Writer {
                 }
             }
         }
+        // a Reader which receives the message sent to a shared locator
+        // doesn't need the message sent to its other locators.
+        let covered_readers: BTreeSet<GUID> = covered.iter().map(|(r, _)| *r).collect();
         for (reader, locs) in reader_locators {
+            if covered_readers.contains(reader) {
+                continue;
+            }
             for loc in locs {
                 if !covered.contains(&(*reader, *loc)) {
                     send_list.push((reader.entity_id, *loc));
@@ -874,6 +1680,7 @@ impl Writer {
             Vec::new(),
             Vec::new(),
             qos,
+            Partition::default(),
         );
     }
 
@@ -887,7 +1694,44 @@ impl Writer {
         default_unicast_locator_list: Vec<Locator>,
         default_multicast_locator_list: Vec<Locator>,
         qos: DataReaderQosPolicies,
+        partition: Partition,
     ) {
+        if self.disc_db.is_ignored_endpoint(remote_reader_guid) {
+            debug!(
+                "Writer ignored Reader\n\tWriter: {}\n\tReader: {}",
+                self.guid, remote_reader_guid
+            );
+            return;
+        }
+        // DDS 1.4 spec, 2.2.3.13 PARTITION
+        // Writer and Reader communicate only if they have at least one partition in common.
+        if !self.partition.is_match(&partition) {
+            if self.matched_readers.contains_key(&remote_reader_guid) {
+                self.matched_reader_remove(remote_reader_guid);
+            }
+            debug!(
+                "Writer found Reader in different Partition\n\tWriter: {}\n\tReader: {}\n\tpartition: {:?}",
+                self.guid, remote_reader_guid, partition.name
+            );
+            let mut proxy = ReaderProxy::new(
+                remote_reader_guid,
+                expects_inline_qos,
+                unicast_locator_list,
+                multicast_locator_list,
+                default_unicast_locator_list,
+                default_multicast_locator_list,
+                qos,
+                Arc::new(RwLock::new(HistoryCache::new(HistoryCacheType::Dummy))),
+                self.push_mode,
+            );
+            proxy.partition = partition;
+            self.partition_mismatched_readers
+                .insert(remote_reader_guid, proxy);
+            return;
+        }
+        self.partition_mismatched_readers
+            .remove(&remote_reader_guid);
+        let is_stateless = self.is_stateless();
         if let std::collections::btree_map::Entry::Vacant(e) =
             self.matched_readers.entry(remote_reader_guid)
         {
@@ -907,7 +1751,7 @@ impl Writer {
                 self.guid, remote_reader_guid
             );
 
-            e.insert(ReaderProxy::new(
+            let mut proxy = ReaderProxy::new(
                 remote_reader_guid,
                 expects_inline_qos,
                 unicast_locator_list,
@@ -915,9 +1759,36 @@ impl Writer {
                 default_unicast_locator_list,
                 default_multicast_locator_list,
                 qos,
-                self.writer_cache.clone(),
+                // StatelessWriter does not keep the state of the changes for each Reader
+                if is_stateless {
+                    Arc::new(RwLock::new(HistoryCache::new(HistoryCacheType::Dummy)))
+                } else {
+                    self.writer_cache.clone()
+                },
                 self.push_mode,
-            ));
+            );
+            proxy.partition = partition;
+            // DDS 1.4 spec, 2.2.3.16 LIFESPAN
+            // expired changes are not delivered to the late-joining Reader.
+            let lifespan = self.qos.lifespan().0;
+            if lifespan != Duration::INFINITE && !is_stateless {
+                let now = Timestamp::now().expect("failed to get Timestamp::now()");
+                let lifespan: CoreDuration = lifespan.into();
+                for (key, change) in self.writer_cache.read().changes.iter() {
+                    if change.timestamp + lifespan < now {
+                        proxy.update_cache_state(
+                            key.seq_num,
+                            false,
+                            if self.push_mode {
+                                ChangeForReaderStatusKind::Unsent
+                            } else {
+                                ChangeForReaderStatusKind::Unacknowledged
+                            },
+                        );
+                    }
+                }
+            }
+            e.insert(proxy);
             self.total_matched_readers.insert(remote_reader_guid);
             let pub_match_state = PublicationMatchedStatus::new(
                 self.total_matched_readers.len() as i32,
@@ -948,8 +1819,53 @@ impl Writer {
             update_proxy_if_need!(multicast_locator_list);
             update_proxy_if_need!(default_unicast_locator_list);
             update_proxy_if_need!(default_multicast_locator_list);
+            update_proxy_if_need!(partition);
+        }
+        self.update_reader_locators();
+    }
+
+    /// Apply the new Partition of the Publisher and re-evaluate matching with known Readers.
+    pub fn set_partition(&mut self, partition: Partition) {
+        if self.partition == partition {
+            return;
+        }
+        info!(
+            "Writer change Partition: {:?} -> {:?}\n\tWriter: {}",
+            self.partition.name, partition.name, self.guid
+        );
+        self.partition = partition;
+        let to_unmatch: Vec<GUID> = self
+            .matched_readers
+            .iter()
+            .filter(|(_, rp)| !self.partition.is_match(&rp.partition))
+            .map(|(guid, _)| *guid)
+            .collect();
+        for guid in to_unmatch {
+            if let Some(rp) = self.matched_readers.get(&guid).cloned() {
+                self.matched_reader_remove(guid);
+                self.partition_mismatched_readers.insert(guid, rp);
+            }
+        }
+        let to_match: Vec<ReaderProxy> = self
+            .partition_mismatched_readers
+            .values()
+            .filter(|rp| self.partition.is_match(&rp.partition))
+            .cloned()
+            .collect();
+        for rp in to_match {
+            self.matched_reader_add_with_default_locator(
+                rp.remote_reader_guid,
+                rp.expects_inline_qos,
+                rp.unicast_locator_list,
+                rp.multicast_locator_list,
+                rp.default_unicast_locator_list,
+                rp.default_multicast_locator_list,
+                rp.qos,
+                rp.partition,
+            );
         }
     }
+
     pub fn is_reader_match(&self, topic_name: &str, data_type: &str) -> bool {
         self.topic.name() == topic_name && self.topic.type_desc() == data_type
     }
@@ -983,11 +1899,17 @@ impl Writer {
         }
     }
 
-    pub fn notify_offered_deadline_missed(&self) {
+    pub fn notify_offered_deadline_missed(&mut self, instance: InstantHandle) {
+        self.offered_deadline_missed_count += 1;
         self.writer_state_notifier
-            .send(DataWriterStatusChanged::OfferedDeadlineMissed)
+            .send(DataWriterStatusChanged::OfferedDeadlineMissed(
+                OfferedDeadlineMissedStatus::new(self.offered_deadline_missed_count, 1, instance),
+            ))
             .expect("failed to send data via channel 'writer_state_notifier'");
-        info!("Writer offered deadline missed\n\tWriter: {}", self.guid);
+        info!(
+            "Writer offered deadline missed\n\tWriter: {}\n\tinstance: {:?}",
+            self.guid, instance
+        );
     }
 
     fn _matched_reader_unmatch(&mut self, guid: GUID) {
@@ -1003,6 +1925,7 @@ impl Writer {
 
     fn matched_reader_remove(&mut self, guid: GUID) {
         self.matched_readers.remove(&guid);
+        self.update_reader_locators();
         let pub_match_state = PublicationMatchedStatus::new(
             self.total_matched_readers.len() as i32,
             0,
@@ -1026,6 +1949,15 @@ impl Writer {
         for d in to_delete {
             self.matched_reader_remove(d);
         }
+        self.partition_mismatched_readers
+            .retain(|guid, _| guid.guid_prefix != guid_prefix);
+    }
+
+    pub fn delete_reader_proxy_with_guid(&mut self, guid: GUID) {
+        if self.matched_readers.contains_key(&guid) {
+            self.matched_reader_remove(guid);
+        }
+        self.partition_mismatched_readers.remove(&guid);
     }
 
     pub fn heartbeat_period(&self) -> CoreDuration {
@@ -1035,6 +1967,25 @@ impl Writer {
         )
     }
 
+    /// period until the next HEARTBEAT
+    ///
+    /// While some changes are not acknowledged, HEARTBEAT is sent faster
+    /// and the period is doubled each time until it reaches heartbeat_period.
+    pub fn next_heartbeat_period(&mut self) -> CoreDuration {
+        let heartbeat_period = self.heartbeat_period();
+        match self.fast_heartbeat_period {
+            Some(period) if !self.is_acked_all_changes() => {
+                let next_period = min(period * 2, heartbeat_period);
+                self.fast_heartbeat_period = Some(next_period);
+                next_period
+            }
+            _ => {
+                self.fast_heartbeat_period = None;
+                heartbeat_period
+            }
+        }
+    }
+
     pub fn nack_response_delay(&self) -> CoreDuration {
         CoreDuration::new(
             self.nack_response_delay.seconds as u64,
@@ -1058,11 +2009,32 @@ impl RTPSEntity for Writer {
 /// The content for each variant has not been implemented yet, but it is planned to be implemented in the future.
 pub enum DataWriterStatusChanged {
     LivelinessLost(LivelinessLostStatus),
-    OfferedDeadlineMissed,
+    OfferedDeadlineMissed(OfferedDeadlineMissedStatus),
     OfferedIncompatibleQos(String),
     PublicationMatched(PublicationMatchedStatus),
 }
 
+/// DDS 1.4 spec, 2.2.4.1 Communication Status, OFFERED_DEADLINE_MISSED
+pub struct OfferedDeadlineMissedStatus {
+    pub total_count: i32,
+    pub total_count_change: i32,
+    pub last_instance_handle: InstantHandle,
+}
+
+impl OfferedDeadlineMissedStatus {
+    pub fn new(
+        total_count: i32,
+        total_count_change: i32,
+        last_instance_handle: InstantHandle,
+    ) -> Self {
+        Self {
+            total_count,
+            total_count_change,
+            last_instance_handle,
+        }
+    }
+}
+
 pub struct LivelinessLostStatus {
     pub total_count: i32,
     pub total_count_change: i32,
@@ -1120,10 +2092,16 @@ pub(crate) struct WriterIngredients {
     pub nack_response_delay: Duration,
     pub nack_suppression_duration: Duration,
     pub data_max_size_serialized: i32,
+    pub fragment_size: u16,
+    pub max_message_size: usize,
+    /// locators the StatelessWriter sends changes to without discovery
+    pub fixed_locators: Vec<Locator>,
     pub(crate) whc: Arc<RwLock<HistoryCache>>,
     // This implementation spesific
     pub topic: Topic,
     pub qos: DataWriterQosPolicies,
+    pub partition: Partition,
+    pub socket_priority: Option<SocketPriority>,
     pub writer_command_receiver: mio_channel::Receiver<WriterCmd>,
     pub writer_state_notifier: mio_channel::Sender<DataWriterStatusChanged>,
     pub participant_msg_cmd_sender: mio_channel::SyncSender<ParticipantMessageCmd>,
@@ -2,7 +2,7 @@ use speedy::{Readable, Writable};
 
 // from jhelovuo/RustDDS(https://github.com/jhelovuo/RustDDS.git), src/structure/parameter_id.rs
 
-#[derive(Readable, Writable, PartialEq, Eq)]
+#[derive(Readable, Writable, PartialEq, Eq, Clone, Copy)]
 pub struct ParameterId {
     pub value: u16,
 }
@@ -64,6 +64,7 @@ impl ParameterId {
     pub const PID_ENTITY_NAME: Self = Self { value: 0x0062 };
     pub const PID_KEY_HASH: Self = Self { value: 0x0070 };
     pub const PID_STATUS_INFO: Self = Self { value: 0x0071 };
+    pub const PID_COHERENT_SET: Self = Self { value: 0x0056 };
     pub const PID_DOMAIN_TAG: Self = Self { value: 0x4014 };
 
     // From Specification "Remote Procedure Calls over DDS v1.0"
@@ -1,4 +1,7 @@
-use crate::dds::qos::{policy::Durability, DataReaderQosPolicies, DataWriterQosPolicies};
+use crate::dds::qos::{
+    policy::{Durability, Partition},
+    DataReaderQosPolicies, DataWriterQosPolicies,
+};
 use crate::message::submessage::element::{Locator, SequenceNumber};
 use crate::rtps::cache::{
     ChangeForReader, ChangeForReaderStatusKind, ChangeFromWriter, ChangeFromWriterStatusKind,
@@ -21,6 +24,13 @@ pub struct ReaderProxy {
     pub default_unicast_locator_list: Vec<Locator>,
     pub default_multicast_locator_list: Vec<Locator>,
     pub qos: DataReaderQosPolicies,
+    /// Partition of the Subscriber the remote Reader belongs to
+    pub partition: Partition,
+    /// locators announced by INFO_REPLY in the Message of the last ACKNACK
+    ///
+    /// rtps 2.3 spec, 8.3.4.1 Rules Followed by the Message Receiver
+    /// If not empty, the repairs are sent to these locators instead of the ones announced by Discovery.
+    pub reply_locator_list: Vec<Locator>,
     _history_cache: Arc<RwLock<HistoryCache>>,
     cache_state: BTreeMap<SequenceNumber, ChangeForReader>,
 }
@@ -56,17 +66,13 @@ impl ReaderProxy {
         {
             let hc = history_cache.read();
             for k in hc.changes.keys() {
-                let latest = hc.ts2key[hc.ts2key.len() - 1];
-                let is_relevant = {
-                    match durability {
-                        Durability::Volatile => false,
-                        Durability::TransientLocal => {
-                            if remote_reader_guid.entity_id.is_builtin() {
-                                true
-                            } else {
-                                *k == latest
-                            }
-                        }
+                // The Writer HistoryCache keeps only the latest `depth` changes of each instance
+                // by History QoS, so all of them are delivered to a late-joining
+                // TRANSIENT_LOCAL Reader. Other SequenceNumbers are notified by GAP.
+                let is_relevant = match durability {
+                    Durability::Volatile => false,
+                    Durability::TransientLocal | Durability::Transient | Durability::Persistent => {
+                        true
                     }
                 };
                 cache_state.insert(
@@ -83,6 +89,8 @@ impl ReaderProxy {
             default_unicast_locator_list,
             default_multicast_locator_list,
             qos,
+            partition: Partition::default(),
+            reply_locator_list: Vec::new(),
             _history_cache: history_cache,
             cache_state,
         }
@@ -293,6 +301,11 @@ impl<C: Context> Writable<C> for ReaderProxy {
         writer.write_u16(12)?;
         writer.write_value(&self.qos.resource_limits())?;
 
+        // partition
+        writer.write_u16(ParameterId::PID_PARTITION.value)?;
+        writer.write_u16(self.partition.serialized_size())?;
+        writer.write_value(&self.partition)?;
+
         // user_data
         writer.write_u16(ParameterId::PID_USER_DATA.value)?;
         writer.write_u16(4 + self.qos.user_data().value.len() as u16)?;
@@ -321,6 +334,13 @@ pub struct WriterProxy {
     pub default_multicast_locator_list: Vec<Locator>,
     pub data_max_size_serialized: i32, // in rtps 2.3 spec, Figure 8.30: long
     pub qos: DataWriterQosPolicies,
+    /// Partition of the Publisher the remote Writer belongs to
+    pub partition: Partition,
+    /// locators announced by INFO_REPLY in the Message of the last HEARTBEAT
+    ///
+    /// rtps 2.3 spec, 8.3.4.1 Rules Followed by the Message Receiver
+    /// If not empty, ACKNACK and NACK_FRAG are sent to these locators instead of the ones announced by Discovery.
+    pub reply_locator_list: Vec<Locator>,
     _history_cache: Arc<RwLock<HistoryCache>>,
     cache_state: BTreeMap<SequenceNumber, ChangeFromWriter>,
 }
@@ -353,6 +373,8 @@ impl WriterProxy {
             default_multicast_locator_list,
             data_max_size_serialized,
             qos,
+            partition: Partition::default(),
+            reply_locator_list: Vec::new(),
             _history_cache,
             cache_state: BTreeMap::new(),
         }
@@ -552,6 +574,11 @@ impl<C: Context> Writable<C> for WriterProxy {
         writer.write_u16(8)?;
         writer.write_value(&self.qos.lifespan())?;
 
+        // partition
+        writer.write_u16(ParameterId::PID_PARTITION.value)?;
+        writer.write_u16(self.partition.serialized_size())?;
+        writer.write_value(&self.partition)?;
+
         // user_data
         writer.write_u16(ParameterId::PID_USER_DATA.value)?;
         writer.write_u16(4 + self.qos.user_data().value.len() as u16)?;